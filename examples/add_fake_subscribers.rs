@@ -43,7 +43,7 @@ async fn main() {
 async fn create_subscriber(client: &Client, email_server: &MockServer) -> (String, String) {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email
     }))
@@ -96,7 +96,7 @@ pub fn get_confirmation_links(email_request: &wiremock::Request) -> Confirmation
         confirmation_link.set_port(Some(APP_PORT)).unwrap();
         confirmation_link
     };
-    let html = get_link(&body["HtmlBody"].as_str().unwrap());
-    let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+    let html = get_link(body["HtmlBody"].as_str().unwrap());
+    let plain_text = get_link(body["TextBody"].as_str().unwrap());
     ConfirmationLinks { html, plain_text }
 }
@@ -0,0 +1,6 @@
+CREATE TABLE issue_delivery_rate_limit (
+	bucket TEXT NOT NULL,
+	tokens DOUBLE PRECISION NOT NULL,
+	updated_at timestamptz NOT NULL,
+	PRIMARY KEY(bucket)
+);
@@ -0,0 +1,11 @@
+-- Admins are created with `zero2prod user create`.
+-- Remove the seeded `admin` user unless its password has been changed.
+WITH seed_user AS (
+	SELECT user_id FROM users
+	WHERE
+		user_id = 'ddf8994f-d522-4659-8d02-c1d479057be6' AND
+		password_hash = '$argon2id$v=19$m=15000,t=2,p=1$OEx/rcq+3ts//WUDzGNl2g$Am8UFBA4w5NJEmAtquGvBmAlu92q/VQcaoL5AyJPfc8'
+), deleted_keys AS (
+	DELETE FROM idempotency WHERE user_id IN (SELECT user_id FROM seed_user)
+)
+DELETE FROM users WHERE user_id IN (SELECT user_id FROM seed_user);
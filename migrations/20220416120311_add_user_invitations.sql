@@ -0,0 +1,11 @@
+ALTER TABLE users ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE;
+
+CREATE TABLE user_invitations (
+	invitation_id uuid NOT NULL,
+	email TEXT NOT NULL,
+	invited_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+	created_at timestamptz NOT NULL,
+	expires_at timestamptz NOT NULL,
+	accepted_at timestamptz NULL,
+	PRIMARY KEY (invitation_id)
+);
@@ -0,0 +1,19 @@
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+-- Logged in sessions, so that resetting a password can revoke them
+CREATE TABLE user_sessions (
+	session_id uuid NOT NULL,
+	user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+	created_at timestamptz NOT NULL,
+	revoked_at timestamptz NULL,
+	PRIMARY KEY (session_id)
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+
+CREATE TABLE password_reset_tokens (
+	token_hash TEXT NOT NULL,
+	user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+	created_at timestamptz NOT NULL,
+	expires_at timestamptz NOT NULL,
+	used_at timestamptz NULL,
+	PRIMARY KEY (token_hash)
+);
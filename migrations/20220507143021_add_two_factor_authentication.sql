@@ -0,0 +1,12 @@
+-- Encrypted TOTP secret, pending until `totp_enabled_at` is set
+ALTER TABLE users ADD COLUMN totp_secret BYTEA NULL;
+ALTER TABLE users ADD COLUMN totp_enabled_at timestamptz NULL;
+-- Last accepted time step, so a code can't be replayed
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE two_factor_recovery_codes (
+	user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+	code_hash TEXT NOT NULL,
+	used_at timestamptz NULL,
+	PRIMARY KEY (user_id, code_hash)
+);
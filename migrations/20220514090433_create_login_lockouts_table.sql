@@ -0,0 +1,9 @@
+CREATE TABLE login_lockouts (
+	lockout_id uuid NOT NULL,
+	-- Exactly one of them is set, depending on what was locked out
+	username TEXT NULL,
+	client_ip TEXT NULL,
+	created_at timestamptz NOT NULL,
+	locked_until timestamptz NOT NULL,
+	PRIMARY KEY (lockout_id)
+);
@@ -0,0 +1,9 @@
+-- Keep enough about each login session to let users recognise and revoke it
+ALTER TABLE user_sessions
+	ADD COLUMN last_seen_at timestamptz NOT NULL DEFAULT now(),
+	ADD COLUMN client_ip TEXT NOT NULL DEFAULT '',
+	ADD COLUMN user_agent TEXT NOT NULL DEFAULT '';
+ALTER TABLE user_sessions
+	ALTER COLUMN last_seen_at DROP DEFAULT,
+	ALTER COLUMN client_ip DROP DEFAULT,
+	ALTER COLUMN user_agent DROP DEFAULT;
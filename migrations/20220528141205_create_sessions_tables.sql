@@ -0,0 +1,16 @@
+-- Used when sessions are stored in Postgres instead of Redis
+CREATE TABLE sessions (
+	-- SHA-256 of the key in the session cookie
+	session_key_hash TEXT NOT NULL,
+	state jsonb NOT NULL,
+	expires_at timestamptz NOT NULL,
+	PRIMARY KEY (session_key_hash)
+);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
+CREATE TABLE login_failures (
+	counter_key TEXT NOT NULL,
+	failures INT NOT NULL,
+	expires_at timestamptz NOT NULL,
+	PRIMARY KEY (counter_key)
+);
+CREATE INDEX login_failures_expires_at_idx ON login_failures (expires_at);
@@ -0,0 +1,21 @@
+-- Actors are not a foreign key, so entries outlive deleted users
+CREATE TABLE audit_log (
+	event_id BIGINT GENERATED ALWAYS AS IDENTITY,
+	actor_id uuid NULL,
+	action TEXT NOT NULL,
+	client_ip TEXT NULL,
+	payload jsonb NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT now(),
+	PRIMARY KEY (event_id)
+);
+CREATE INDEX audit_log_created_at_idx ON audit_log (created_at);
+CREATE INDEX audit_log_actor_id_idx ON audit_log (actor_id);
+-- The log is append-only, even for the application
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'The audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER audit_log_append_only
+	BEFORE UPDATE OR DELETE ON audit_log
+	FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
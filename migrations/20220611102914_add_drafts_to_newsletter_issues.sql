@@ -0,0 +1,7 @@
+-- Issues can be created as drafts through the API and published later
+ALTER TABLE newsletter_issues
+	ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz,
+	ALTER COLUMN published_at DROP NOT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+-- Number of subscribers the issue was sent to, to report the delivery progress
+ALTER TABLE newsletter_issues ADD COLUMN n_recipients INT NULL;
@@ -0,0 +1,14 @@
+CREATE TABLE api_tokens (
+	token_id uuid NOT NULL,
+	user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+	name TEXT NOT NULL,
+	-- SHA-256 of the token, which is only shown once
+	token_hash TEXT NOT NULL UNIQUE,
+	scopes TEXT[] NOT NULL,
+	created_at timestamptz NOT NULL,
+	expires_at timestamptz NULL,
+	last_used_at timestamptz NULL,
+	revoked_at timestamptz NULL,
+	PRIMARY KEY (token_id)
+);
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,7 @@
+-- Keys can belong to a user, an API token or an anonymous client
+ALTER TABLE idempotency ADD COLUMN key_owner TEXT NULL;
+UPDATE idempotency SET key_owner = 'user:' || user_id;
+ALTER TABLE idempotency ALTER COLUMN key_owner SET NOT NULL;
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency DROP COLUMN user_id;
+ALTER TABLE idempotency ADD PRIMARY KEY (key_owner, idempotency_key);
@@ -0,0 +1,2 @@
+-- Keys saved before are not checked against the request reusing them
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT;
@@ -0,0 +1,2 @@
+-- Keys saved before expire after the default retention
+ALTER TABLE idempotency ADD COLUMN expires_at timestamptz;
@@ -0,0 +1,7 @@
+CREATE TABLE rate_limit_counters (
+	counter_key TEXT NOT NULL,
+	hits INT NOT NULL,
+	expires_at timestamptz NOT NULL,
+	PRIMARY KEY (counter_key)
+);
+CREATE INDEX rate_limit_counters_expires_at_idx ON rate_limit_counters (expires_at);
@@ -0,0 +1,91 @@
+-- Addresses differing only by case are the same subscriber. Their duplicates are removed,
+-- keeping the confirmed subscription or else the oldest one, and reported here.
+CREATE TABLE subscription_email_duplicates (
+	subscriber_id uuid NOT NULL,
+	email TEXT NOT NULL,
+	name TEXT NOT NULL,
+	status TEXT NOT NULL,
+	subscribed_at timestamptz NOT NULL,
+	kept_subscriber_id uuid NOT NULL,
+	deduplicated_at timestamptz NOT NULL DEFAULT now(),
+	PRIMARY KEY (subscriber_id)
+);
+
+-- Trimmed with a lowercase domain, like `SubscriberEmail`. Unicode domains of existing rows
+-- keep their spelling, IDNA is only applied to new addresses.
+CREATE TEMPORARY TABLE normalized_emails AS
+SELECT
+	id,
+	email AS old_email,
+	COALESCE(
+		regexp_replace(btrim(email), '@[^@]*$', '') || lower(substring(btrim(email) FROM '@[^@]*$')),
+		btrim(email)
+	) AS new_email
+FROM subscriptions;
+
+CREATE TEMPORARY TABLE email_duplicates AS
+SELECT id, kept_id
+FROM (
+	SELECT
+		n.id,
+		first_value(n.id) OVER (
+			PARTITION BY lower(n.new_email)
+			ORDER BY s.status = 'confirmed' DESC, s.subscribed_at, s.id
+		) AS kept_id
+	FROM normalized_emails n
+	JOIN subscriptions s ON s.id = n.id
+) ranked
+WHERE id <> kept_id;
+
+INSERT INTO subscription_email_duplicates (
+	subscriber_id, email, name, status, subscribed_at, kept_subscriber_id
+)
+SELECT s.id, s.email, s.name, s.status, s.subscribed_at, d.kept_id
+FROM email_duplicates d
+JOIN subscriptions s ON s.id = d.id;
+
+-- Where the deliveries to each changed or removed address go now
+CREATE TEMPORARY TABLE email_changes AS
+SELECT n.old_email, kept.new_email
+FROM normalized_emails n
+LEFT JOIN email_duplicates d ON d.id = n.id
+JOIN normalized_emails kept ON kept.id = COALESCE(d.kept_id, n.id)
+WHERE n.old_email <> kept.new_email;
+
+-- A subscriber gets each issue once, from the unchanged address if it is queued
+DELETE FROM issue_delivery_queue q
+USING email_changes c
+WHERE q.subscriber_email = c.old_email
+	AND EXISTS (
+		SELECT 1
+		FROM issue_delivery_queue other
+		LEFT JOIN email_changes oc ON oc.old_email = other.subscriber_email
+		WHERE other.newsletter_issue_id = q.newsletter_issue_id
+			AND other.subscriber_email <> q.subscriber_email
+			AND COALESCE(oc.new_email, other.subscriber_email) = c.new_email
+			AND (oc.old_email IS NULL OR other.subscriber_email < q.subscriber_email)
+	);
+UPDATE issue_delivery_queue q
+SET subscriber_email = c.new_email
+FROM email_changes c
+WHERE q.subscriber_email = c.old_email;
+
+DELETE FROM subscription_tokens WHERE subscriber_id IN (SELECT id FROM email_duplicates);
+DELETE FROM subscriptions WHERE id IN (SELECT id FROM email_duplicates);
+UPDATE subscriptions s
+SET email = n.new_email
+FROM normalized_emails n
+WHERE s.id = n.id AND s.email <> n.new_email;
+
+DO $$
+DECLARE
+	n_duplicates BIGINT;
+BEGIN
+	SELECT count(*) INTO n_duplicates FROM email_duplicates;
+	RAISE NOTICE '% duplicate subscriptions removed, see subscription_email_duplicates.', n_duplicates;
+END $$;
+
+DROP TABLE email_changes, email_duplicates, normalized_emails;
+
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_lower_email_key ON subscriptions (lower(email));
@@ -0,0 +1,3 @@
+-- A key in progress can be taken over once its request stops renewing the lease
+ALTER TABLE idempotency ADD COLUMN locked_until timestamptz NULL;
+UPDATE idempotency SET locked_until = now() WHERE response_status_code IS NULL;
@@ -0,0 +1,6 @@
+CREATE TABLE used_subscription_forms (
+	form_signature TEXT NOT NULL,
+	expires_at timestamptz NOT NULL,
+	PRIMARY KEY (form_signature)
+);
+CREATE INDEX used_subscription_forms_expires_at_idx ON used_subscription_forms (expires_at);
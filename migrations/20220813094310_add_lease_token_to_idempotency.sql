@@ -0,0 +1,3 @@
+-- Set by the request holding a key in progress, so that a request whose key was taken over
+-- can't renew, release or answer it anymore
+ALTER TABLE idempotency ADD COLUMN lease_token uuid NULL;
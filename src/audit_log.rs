@@ -0,0 +1,211 @@
+use crate::authentication::LoginThrottle;
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+use std::{future::Ready, str::FromStr};
+use uuid::Uuid;
+
+/// Security-relevant and publishing actions worth keeping a record of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(into = "&'static str")]
+pub enum AuditAction {
+    LoginSucceeded,
+    LoginFailed,
+    LoggedOut,
+    PasswordChanged,
+    PasswordReset,
+    NewsletterPublished,
+    SubscriberAdded,
+    SubscriberConfirmed,
+    UserInvited,
+    InvitationAccepted,
+    UserActivated,
+    UserDeactivated,
+    RoleChanged,
+    TwoFactorReset,
+    LockoutLifted,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 17] = [
+        AuditAction::LoginSucceeded,
+        AuditAction::LoginFailed,
+        AuditAction::LoggedOut,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::NewsletterPublished,
+        AuditAction::SubscriberAdded,
+        AuditAction::SubscriberConfirmed,
+        AuditAction::UserInvited,
+        AuditAction::InvitationAccepted,
+        AuditAction::UserActivated,
+        AuditAction::UserDeactivated,
+        AuditAction::RoleChanged,
+        AuditAction::TwoFactorReset,
+        AuditAction::LockoutLifted,
+        AuditAction::ApiTokenCreated,
+        AuditAction::ApiTokenRevoked,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LoginSucceeded => "login.succeeded",
+            AuditAction::LoginFailed => "login.failed",
+            AuditAction::LoggedOut => "logout",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::PasswordReset => "password.reset",
+            AuditAction::NewsletterPublished => "newsletter.published",
+            AuditAction::SubscriberAdded => "subscriber.added",
+            AuditAction::SubscriberConfirmed => "subscriber.confirmed",
+            AuditAction::UserInvited => "user.invited",
+            AuditAction::InvitationAccepted => "user.invitation_accepted",
+            AuditAction::UserActivated => "user.activated",
+            AuditAction::UserDeactivated => "user.deactivated",
+            AuditAction::RoleChanged => "user.role_changed",
+            AuditAction::TwoFactorReset => "user.two_factor_reset",
+            AuditAction::LockoutLifted => "user.lockout_lifted",
+            AuditAction::ApiTokenCreated => "api_token.created",
+            AuditAction::ApiTokenRevoked => "api_token.revoked",
+        }
+    }
+}
+
+impl From<AuditAction> for &'static str {
+    fn from(action: AuditAction) -> Self {
+        action.as_str()
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AuditAction::ALL
+            .iter()
+            .find(|a| a.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("{} is not a valid audit action.", s))
+    }
+}
+
+/// The address of the client, as seen by the login throttle.
+pub struct ClientIp(pub String);
+
+impl FromRequest for ClientIp {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let throttle = req
+            .app_data::<web::Data<LoginThrottle>>()
+            .expect("The login throttle is not registered.");
+        std::future::ready(Ok(ClientIp(throttle.client_ip(req))))
+    }
+}
+
+/// Append an entry to the audit log.
+/// Pass the transaction of the change when there is one, so both are committed together.
+#[tracing::instrument(name = "Record audit event", skip(executor, payload))]
+pub async fn record_audit_event(
+    executor: impl PgExecutor<'_>,
+    actor_id: Option<Uuid>,
+    action: AuditAction,
+    client_ip: Option<&str>,
+    payload: serde_json::Value,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (actor_id, action, client_ip, payload)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        actor_id,
+        action.as_str(),
+        client_ip,
+        payload
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record the audit event.")?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    /// Username of the actor
+    pub actor: Option<String>,
+    pub since: Option<NaiveDate>,
+    /// Inclusive
+    pub until: Option<NaiveDate>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub action: String,
+    pub actor_id: Option<Uuid>,
+    pub actor: Option<String>,
+    pub client_ip: Option<String>,
+    pub payload: String,
+    pub created_at: String,
+}
+
+const MAX_AUDIT_ENTRIES: i64 = 200;
+
+/// The most recent entries matching the filter, newest first.
+#[tracing::instrument(name = "List audit events", skip(pool))]
+pub async fn list_audit_events(filter: &AuditFilter, pool: &PgPool) -> Result<Vec<AuditEntry>> {
+    let start_of_day = |date: NaiveDate| DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc);
+    let since = filter.since.map(start_of_day);
+    let until = filter
+        .until
+        .map(|d| start_of_day(d) + chrono::Duration::days(1));
+    let entries = sqlx::query!(
+        r#"
+        SELECT a.action, a.actor_id, u.username AS "actor?", a.client_ip, a.payload, a.created_at
+        FROM audit_log a
+        LEFT JOIN users u ON u.user_id = a.actor_id
+        WHERE ($1::TEXT IS NULL OR a.action = $1)
+            AND ($2::TEXT IS NULL OR u.username = $2)
+            AND ($3::timestamptz IS NULL OR a.created_at >= $3)
+            AND ($4::timestamptz IS NULL OR a.created_at < $4)
+        ORDER BY a.event_id DESC
+        LIMIT $5
+        "#,
+        filter.action.map(|a| a.as_str()),
+        filter.actor,
+        since,
+        until,
+        MAX_AUDIT_ENTRIES
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the audit log.")?
+    .into_iter()
+    .map(|r| AuditEntry {
+        action: r.action,
+        actor_id: r.actor_id,
+        actor: r.actor,
+        client_ip: r.client_ip,
+        payload: r.payload.to_string(),
+        created_at: r.created_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    })
+    .collect();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+
+    #[test]
+    fn actions_roundtrip_through_their_names() {
+        for action in AuditAction::ALL {
+            assert_eq!(action.as_str().parse::<AuditAction>(), Ok(action));
+        }
+        assert!("login".parse::<AuditAction>().is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use super::UserRole;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Prefix of every token, so leaked ones are easy to recognise.
+const TOKEN_PREFIX: &str = "z2p_";
+
+/// What an API token can be used for, on top of the role of its user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(into = "&'static str")]
+pub enum ApiScope {
+    SubscribersRead,
+    IssuesRead,
+    IssuesWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 3] = [
+        ApiScope::SubscribersRead,
+        ApiScope::IssuesRead,
+        ApiScope::IssuesWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::IssuesRead => "issues:read",
+            ApiScope::IssuesWrite => "issues:write",
+        }
+    }
+}
+
+impl From<ApiScope> for &'static str {
+    fn from(scope: ApiScope) -> Self {
+        scope.as_str()
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ApiScope::ALL
+            .iter()
+            .find(|scope| scope.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("{} is not a valid scope.", s))
+    }
+}
+
+/// A token as listed to its user, the token itself is never shown again.
+#[derive(Serialize)]
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub is_expired: bool,
+}
+
+/// The user an API token acts on behalf of.
+#[derive(Debug)]
+pub struct ApiTokenOwner {
+    pub token_id: Uuid,
+    pub user_id: Uuid,
+    pub role: UserRole,
+    pub scopes: Vec<ApiScope>,
+}
+
+/// Store a new token for the user, only its hash is kept in the database.
+#[tracing::instrument(name = "Create API token", skip(pool))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    expires_at: Option<DateTime<Utc>>,
+    pool: &PgPool,
+) -> Result<(Uuid, Secret<String>)> {
+    let token_id = Uuid::new_v4();
+    let token: String = {
+        let mut rng = thread_rng();
+        let random: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(40)
+            .collect();
+        format!("{}{}", TOKEN_PREFIX, random)
+    };
+    let scopes = scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, now(), $6)
+        "#,
+        token_id,
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes as &[&str],
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok((token_id, Secret::new(token)))
+}
+
+/// The tokens of the user that were not revoked, newest first.
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiToken>> {
+    let format = |at: DateTime<Utc>| at.format("%Y-%m-%d %H:%M UTC").to_string();
+    let tokens = sqlx::query!(
+        r#"
+        SELECT token_id, name, scopes, created_at, expires_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the API tokens.")?
+    .into_iter()
+    .map(|r| ApiToken {
+        token_id: r.token_id,
+        name: r.name,
+        scopes: r.scopes,
+        created_at: format(r.created_at),
+        is_expired: r.expires_at.map(|at| at <= Utc::now()).unwrap_or(false),
+        expires_at: r.expires_at.map(format),
+        last_used_at: r.last_used_at.map(format),
+    })
+    .collect();
+    Ok(tokens)
+}
+
+/// Returns the name of the token, or `None` if the user has no such token.
+#[tracing::instrument(name = "Revoke API token", skip(pool))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    token_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<String>> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        RETURNING name
+        "#,
+        token_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to revoke the API token.")?;
+    Ok(row.map(|r| r.name))
+}
+
+/// Mark the token as used and return who it belongs to.
+/// Returns `None` if the token is unknown, expired, revoked or its user is not active.
+#[tracing::instrument(name = "Authenticate API token", skip_all)]
+pub async fn authenticate_api_token(
+    token: &Secret<String>,
+    pool: &PgPool,
+) -> Result<Option<ApiTokenOwner>> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens t
+        SET last_used_at = now()
+        FROM users u
+        WHERE t.token_hash = $1
+            AND t.revoked_at IS NULL
+            AND (t.expires_at IS NULL OR t.expires_at > now())
+            AND u.user_id = t.user_id
+            AND u.is_active
+        RETURNING t.token_id, t.user_id, u.role, t.scopes
+        "#,
+        hash_token(token.expose_secret())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the API token.")?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let owner = ApiTokenOwner {
+        token_id: row.token_id,
+        user_id: row.user_id,
+        role: row.role.parse().map_err(anyhow::Error::msg)?,
+        // Scopes that were removed since the token was created are ignored
+        scopes: row.scopes.iter().filter_map(|s| s.parse().ok()).collect(),
+    };
+    Ok(Some(owner))
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiScope;
+
+    #[test]
+    fn scopes_roundtrip_through_their_names() {
+        for scope in ApiScope::ALL {
+            assert_eq!(scope.as_str().parse::<ApiScope>(), Ok(scope));
+        }
+        assert!("issues".parse::<ApiScope>().is_err());
+    }
+}
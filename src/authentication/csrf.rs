@@ -0,0 +1,80 @@
+use crate::{
+    session_state::TypedSession,
+    utils::{e500, peek_form_field, see_other},
+};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    FromRequest,
+};
+use actix_web_flash_messages::FlashMessage;
+use actix_web_lab::middleware::Next;
+
+/// Form field carrying the token, for the HTML forms.
+pub const CSRF_TOKEN_FIELD: &str = "csrf_token";
+/// Header carrying the token, for clients that don't submit forms.
+pub const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+
+/// Reject the requests changing state unless they carry the CSRF token of the session.
+pub async fn reject_forged_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if req.method().is_safe() {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await?
+    };
+    let expected = session.get_csrf_token().map_err(e500)?;
+    let submitted = match req.headers().get(CSRF_TOKEN_HEADER) {
+        Some(header) => header.to_str().ok().map(str::to_owned),
+        None => peek_form_field(&mut req, CSRF_TOKEN_FIELD).await?,
+    };
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) if tokens_match(&expected, &submitted) => next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body),
+        _ => {
+            let redirect_to = if req.path().starts_with("/admin") {
+                "/admin/dashboard"
+            } else {
+                "/login"
+            };
+            // Not an error response, so the flash middleware still sends the message
+            tracing::warn!("The CSRF token is missing or invalid");
+            FlashMessage::error("The form has expired, please submit it again.").send();
+            Ok(req
+                .into_response(see_other(redirect_to))
+                .map_into_right_body())
+        }
+    }
+}
+
+/// Compare in constant time, so the token can't be guessed byte by byte.
+pub(super) fn tokens_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokens_match;
+
+    #[test]
+    fn only_identical_tokens_match() {
+        assert!(tokens_match("abcdef", "abcdef"));
+        assert!(!tokens_match("abcdef", "abcdeg"));
+        assert!(!tokens_match("abcdef", "abcde"));
+        assert!(!tokens_match("abcdef", ""));
+    }
+}
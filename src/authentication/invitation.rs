@@ -0,0 +1,79 @@
+use super::signature::{sign, verify_signature};
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use uuid::Uuid;
+
+/// Parameters of the link sent by email to invited users.
+#[derive(serde::Deserialize)]
+pub struct SignedInvitation {
+    pub invitation_id: Uuid,
+    /// Unix timestamp after which the link is no longer valid
+    pub expires_at: i64,
+    pub signature: String,
+}
+
+impl SignedInvitation {
+    pub fn new(invitation_id: Uuid, expires_at: DateTime<Utc>, secret: &Secret<String>) -> Self {
+        let expires_at = expires_at.timestamp();
+        let signature = sign(secret, &Self::payload(invitation_id, expires_at));
+        Self {
+            invitation_id,
+            expires_at,
+            signature,
+        }
+    }
+
+    fn payload(invitation_id: Uuid, expires_at: i64) -> String {
+        format!("invitation:{}:{}", invitation_id, expires_at)
+    }
+
+    /// Check that the link was generated by us and has not expired yet.
+    pub fn verify(&self, secret: &Secret<String>) -> Result<Uuid, String> {
+        let payload = Self::payload(self.invitation_id, self.expires_at);
+        if !verify_signature(secret, &payload, &self.signature)
+            || self.expires_at <= Utc::now().timestamp()
+        {
+            return Err("The invitation link is invalid or has expired.".to_string());
+        }
+        Ok(self.invitation_id)
+    }
+
+    pub fn path(&self) -> String {
+        format!(
+            "/invitations/accept?invitation_id={}&expires_at={}&signature={}",
+            self.invitation_id, self.expires_at, self.signature
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret() -> Secret<String> {
+        Secret::new("some-secret".to_string())
+    }
+
+    #[test]
+    fn a_fresh_invitation_is_valid() {
+        let invitation_id = Uuid::new_v4();
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+        let invitation = SignedInvitation::new(invitation_id, expires_at, &secret());
+        assert_eq!(invitation.verify(&secret()), Ok(invitation_id));
+    }
+
+    #[test]
+    fn an_expired_invitation_is_rejected() {
+        let expires_at = Utc::now() - chrono::Duration::seconds(1);
+        let invitation = SignedInvitation::new(Uuid::new_v4(), expires_at, &secret());
+        assert!(invitation.verify(&secret()).is_err());
+    }
+
+    #[test]
+    fn extending_the_expiration_invalidates_the_signature() {
+        let expires_at = Utc::now() - chrono::Duration::seconds(1);
+        let mut invitation = SignedInvitation::new(Uuid::new_v4(), expires_at, &secret());
+        invitation.expires_at += 3600;
+        assert!(invitation.verify(&secret()).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use crate::configuration::{LoginThrottleSettings, SessionStoreBackend};
+use crate::expiring_map::ExpiringMap;
+use actix_web::HttpRequest;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{sync::Mutex, time::Duration};
+use uuid::Uuid;
+
+/// Counts failed logins per username and per client IP in the session store, so every
+/// instance shares the same view.
+pub struct LoginThrottle {
+    counters: FailureCounters,
+    settings: LoginThrottleSettings,
+}
+
+/// Where the failure counters are kept, follows the session store backend.
+enum FailureCounters {
+    Redis(ConnectionManager),
+    Postgres(PgPool),
+    Memory(Mutex<ExpiringMap<u32>>),
+}
+
+/// What happened to the counters after a failed attempt.
+pub struct FailedLogin {
+    /// How long to wait before answering
+    pub delay: Duration,
+    pub username_locked: bool,
+    pub ip_locked: bool,
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        backend: SessionStoreBackend,
+        redis_uri: &Secret<String>,
+        pool: &PgPool,
+        settings: LoginThrottleSettings,
+    ) -> Result<Self> {
+        let counters = match backend {
+            SessionStoreBackend::Redis => {
+                let client = redis::Client::open(redis_uri.expose_secret().as_str())?;
+                FailureCounters::Redis(ConnectionManager::new(client).await?)
+            }
+            SessionStoreBackend::Postgres => FailureCounters::Postgres(pool.clone()),
+            SessionStoreBackend::Memory => FailureCounters::Memory(Mutex::default()),
+        };
+        Ok(Self { counters, settings })
+    }
+
+    pub fn client_ip(&self, request: &HttpRequest) -> String {
+        let connection_info = request.connection_info();
+        let ip = if self.settings.trust_proxy_headers {
+            connection_info.realip_remote_addr()
+        } else {
+            connection_info.peer_addr()
+        };
+        ip.unwrap_or("unknown").to_string()
+    }
+
+    fn username_key(&self, username: &str) -> String {
+        // Hashing keeps keys short whatever was typed in the form
+        let hash = hex::encode(Sha256::digest(username.as_bytes()));
+        format!(
+            "{}:login_failures:username:{}",
+            self.settings.redis_key_prefix, hash
+        )
+    }
+
+    fn ip_key(&self, ip: &str) -> String {
+        format!(
+            "{}:login_failures:ip:{}",
+            self.settings.redis_key_prefix, ip
+        )
+    }
+
+    /// Check if the username or the client are locked out.
+    /// If the store is unavailable we let the attempt through rather than locking everyone out.
+    #[tracing::instrument(name = "Check login lockout", skip(self))]
+    pub async fn is_locked(&self, username: &str, ip: &str) -> bool {
+        match self.get_failures(username, ip).await {
+            Ok((username_failures, ip_failures)) => {
+                username_failures >= self.settings.max_failures_per_username
+                    || ip_failures >= self.settings.max_failures_per_ip
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to check the login failures."
+                );
+                false
+            }
+        }
+    }
+
+    /// Count an attempt rejected by a lockout, true for the first one of the lockout.
+    /// The locked counters go past the limit without changing their expiration.
+    #[tracing::instrument(name = "Record locked out login", skip(self))]
+    pub async fn record_locked_attempt(&self, username: &str, ip: &str) -> bool {
+        let result = async {
+            let (username_failures, ip_failures) = self.get_failures(username, ip).await?;
+            let mut is_first = false;
+            if username_failures >= self.settings.max_failures_per_username {
+                let failures = self
+                    .counters
+                    .increment(&self.username_key(username), self.settings.lockout_secs)
+                    .await?;
+                is_first |= failures == self.settings.max_failures_per_username + 1;
+            }
+            if ip_failures >= self.settings.max_failures_per_ip {
+                let failures = self
+                    .counters
+                    .increment(&self.ip_key(ip), self.settings.lockout_secs)
+                    .await?;
+                is_first |= failures == self.settings.max_failures_per_ip + 1;
+            }
+            Ok::<_, anyhow::Error>(is_first)
+        }
+        .await;
+        result.unwrap_or_else(|e| {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to record the locked out login."
+            );
+            true
+        })
+    }
+
+    async fn get_failures(&self, username: &str, ip: &str) -> Result<(u32, u32)> {
+        let username_failures = self.counters.get(&self.username_key(username)).await?;
+        let ip_failures = self.counters.get(&self.ip_key(ip)).await?;
+        Ok((username_failures, ip_failures))
+    }
+
+    #[tracing::instrument(name = "Record failed login", skip(self))]
+    pub async fn record_failure(&self, username: &str, ip: &str) -> FailedLogin {
+        let result = async {
+            let window = self.settings.failure_window_secs;
+            let username_failures = self
+                .counters
+                .increment(&self.username_key(username), window)
+                .await?;
+            let ip_failures = self.counters.increment(&self.ip_key(ip), window).await?;
+            let username_locked = username_failures == self.settings.max_failures_per_username;
+            let ip_locked = ip_failures == self.settings.max_failures_per_ip;
+            // Attempts are rejected before being counted while locked, so this is the full lockout
+            if username_locked {
+                self.counters
+                    .expire(&self.username_key(username), self.settings.lockout_secs)
+                    .await?;
+            }
+            if ip_locked {
+                self.counters
+                    .expire(&self.ip_key(ip), self.settings.lockout_secs)
+                    .await?;
+            }
+            Ok::<_, anyhow::Error>(FailedLogin {
+                delay: self.delay(username_failures),
+                username_locked,
+                ip_locked,
+            })
+        }
+        .await;
+        result.unwrap_or_else(|e| {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to record the failed login."
+            );
+            FailedLogin {
+                delay: Duration::from_millis(self.settings.max_delay_ms),
+                username_locked: false,
+                ip_locked: false,
+            }
+        })
+    }
+
+    fn delay(&self, username_failures: u32) -> Duration {
+        let exponent = username_failures.saturating_sub(1).min(16);
+        let delay_ms = self.settings.delay_base_ms.saturating_mul(1 << exponent);
+        Duration::from_millis(delay_ms.min(self.settings.max_delay_ms))
+    }
+
+    /// Forget the failures of a username, after a successful login or when an owner
+    /// lifts a lockout.
+    #[tracing::instrument(name = "Clear login failures", skip(self))]
+    pub async fn clear(&self, username: &str) {
+        if let Err(e) = self.counters.delete(&self.username_key(username)).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to clear the login failures."
+            );
+        }
+    }
+
+    #[tracing::instrument(name = "Clear client login failures", skip(self))]
+    pub async fn clear_ip(&self, ip: &str) {
+        if let Err(e) = self.counters.delete(&self.ip_key(ip)).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to clear the login failures."
+            );
+        }
+    }
+
+    pub fn lockout_duration(&self) -> Duration {
+        Duration::from_secs(self.settings.lockout_secs)
+    }
+}
+
+impl FailureCounters {
+    async fn get(&self, key: &str) -> Result<u32> {
+        match self {
+            Self::Redis(redis) => {
+                let failures: Option<u32> = redis.clone().get(key).await?;
+                Ok(failures.unwrap_or(0))
+            }
+            Self::Postgres(pool) => {
+                let failures = sqlx::query_scalar!(
+                    r#"
+                    SELECT failures
+                    FROM login_failures
+                    WHERE counter_key = $1 AND expires_at > now()
+                    "#,
+                    key
+                )
+                .fetch_optional(pool)
+                .await?;
+                Ok(failures.unwrap_or(0) as u32)
+            }
+            Self::Memory(counters) => Ok(counters.lock().unwrap().get(key).copied().unwrap_or(0)),
+        }
+    }
+
+    /// Add a failure, a counter that doesn't exist yet lasts `window_secs`.
+    async fn increment(&self, key: &str, window_secs: u64) -> Result<u32> {
+        match self {
+            Self::Redis(redis) => {
+                let mut redis = redis.clone();
+                // Creating the counter with its expiration first means it can't outlive the window
+                redis::cmd("SET")
+                    .arg(key)
+                    .arg(0)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(window_secs)
+                    .query_async::<_, ()>(&mut redis)
+                    .await?;
+                Ok(redis.incr(key, 1).await?)
+            }
+            Self::Postgres(pool) => {
+                let expires_at = Utc::now() + chrono::Duration::seconds(window_secs as i64);
+                let failures = sqlx::query_scalar!(
+                    r#"
+                    INSERT INTO login_failures (counter_key, failures, expires_at)
+                    VALUES ($1, 1, $2)
+                    ON CONFLICT (counter_key) DO UPDATE
+                    SET
+                        failures = CASE WHEN login_failures.expires_at > now()
+                            THEN login_failures.failures + 1 ELSE 1 END,
+                        expires_at = CASE WHEN login_failures.expires_at > now()
+                            THEN login_failures.expires_at ELSE EXCLUDED.expires_at END
+                    RETURNING failures
+                    "#,
+                    key,
+                    expires_at
+                )
+                .fetch_one(pool)
+                .await?;
+                Ok(failures as u32)
+            }
+            Self::Memory(counters) => {
+                let mut counters = counters.lock().unwrap();
+                Ok(counters.increment(key, Duration::from_secs(window_secs)))
+            }
+        }
+    }
+
+    async fn expire(&self, key: &str, secs: u64) -> Result<()> {
+        match self {
+            Self::Redis(redis) => {
+                redis.clone().expire::<_, ()>(key, secs as usize).await?;
+            }
+            Self::Postgres(pool) => {
+                let expires_at = Utc::now() + chrono::Duration::seconds(secs as i64);
+                sqlx::query!(
+                    r#"UPDATE login_failures SET expires_at = $2 WHERE counter_key = $1"#,
+                    key,
+                    expires_at
+                )
+                .execute(pool)
+                .await?;
+            }
+            Self::Memory(counters) => {
+                counters
+                    .lock()
+                    .unwrap()
+                    .expire(key, Duration::from_secs(secs));
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self {
+            Self::Redis(redis) => {
+                redis.clone().del::<_, ()>(key).await?;
+            }
+            Self::Postgres(pool) => {
+                sqlx::query!(r#"DELETE FROM login_failures WHERE counter_key = $1"#, key)
+                    .execute(pool)
+                    .await?;
+            }
+            Self::Memory(counters) => {
+                counters.lock().unwrap().remove(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct LoginLockout {
+    pub username: Option<String>,
+    pub client_ip: Option<String>,
+    pub created_at: String,
+    pub locked_until: String,
+    pub is_active: bool,
+}
+
+/// Keep track of lockouts so owners can see them.
+#[tracing::instrument(name = "Record login lockout", skip(pool))]
+pub async fn record_lockout(
+    username: Option<&str>,
+    client_ip: Option<&str>,
+    duration: Duration,
+    pool: &PgPool,
+) -> Result<()> {
+    let locked_until = Utc::now() + chrono::Duration::from_std(duration)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO login_lockouts (lockout_id, username, client_ip, created_at, locked_until)
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        Uuid::new_v4(),
+        username,
+        client_ip,
+        locked_until
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the login lockout.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get recent login lockouts", skip(pool))]
+pub async fn get_recent_lockouts(pool: &PgPool) -> Result<Vec<LoginLockout>> {
+    let lockouts = sqlx::query!(
+        r#"
+        SELECT username, client_ip, created_at, locked_until, locked_until > now() as "is_active!"
+        FROM login_lockouts
+        WHERE created_at > now() - interval '7 days'
+        ORDER BY created_at DESC
+        LIMIT 50
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the recent login lockouts.")?
+    .into_iter()
+    .map(|r| LoginLockout {
+        username: r.username,
+        client_ip: r.client_ip,
+        created_at: r.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+        locked_until: r.locked_until.format("%Y-%m-%d %H:%M UTC").to_string(),
+        is_active: r.is_active,
+    })
+    .collect();
+    Ok(lockouts)
+}
+
+/// End the recorded lockouts of a username or client IP early.
+#[tracing::instrument(name = "Lift login lockouts", skip(pool))]
+pub async fn lift_lockouts(
+    username: Option<&str>,
+    client_ip: Option<&str>,
+    pool: &PgPool,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE login_lockouts
+        SET locked_until = now()
+        WHERE locked_until > now()
+            AND (username = $1 OR client_ip = $2)
+        "#,
+        username,
+        client_ip
+    )
+    .execute(pool)
+    .await
+    .context("Failed to lift the login lockouts.")?;
+    Ok(())
+}
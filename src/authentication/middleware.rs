@@ -1,23 +1,48 @@
+use super::{
+    authenticate_api_token,
+    csrf::{tokens_match, CSRF_TOKEN_HEADER},
+    touch_user_session, ApiScope, UserRole,
+};
 use crate::{
+    routes::{forbidden, ApiError},
     session_state::TypedSession,
     utils::{e500, see_other},
 };
 use actix_web::{
-    body::MessageBody,
+    body::{EitherBody, MessageBody},
     dev::{ServiceRequest, ServiceResponse},
     error::InternalError,
-    FromRequest, HttpMessage,
+    http::header,
+    web, FromRequest, HttpMessage, ResponseError,
 };
+use actix_web_flash_messages::FlashMessage;
 use actix_web_lab::middleware::Next;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
 #[derive(Debug, Copy, Clone)]
-pub struct UserId(Uuid);
+pub struct UserId {
+    user_id: Uuid,
+    role: UserRole,
+    /// Set when the request was authenticated with an API token instead of a session
+    api_token_id: Option<Uuid>,
+}
+
+impl UserId {
+    pub fn role(&self) -> UserRole {
+        self.role
+    }
+
+    pub fn api_token_id(&self) -> Option<Uuid> {
+        self.api_token_id
+    }
+}
 
 impl std::fmt::Display for UserId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        self.user_id.fmt(f)
     }
 }
 
@@ -25,14 +50,14 @@ impl Deref for UserId {
     type Target = Uuid;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.user_id
     }
 }
 
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
-) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await?
@@ -40,8 +65,36 @@ pub async fn reject_anonymous_users(
 
     match session.get_user_id().map_err(e500)? {
         Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .expect("The database pool is not registered.");
+            // Sessions outlive deactivations, role changes and revocations, so we
+            // check them on every request
+            let role = match session.get_session_id().map_err(e500)? {
+                Some(session_id) => touch_user_session(user_id, session_id, pool)
+                    .await
+                    .map_err(e500)?,
+                None => None,
+            };
+            let role = match role {
+                Some(role) => role,
+                None => {
+                    // Not an error response, so the session and flash middlewares still
+                    // apply the log out and the message
+                    tracing::info!("The user account is not active or the session was revoked");
+                    session.log_out();
+                    FlashMessage::error("Your session has expired, please log in again.").send();
+                    return Ok(req.into_response(see_other("/login")).map_into_right_body());
+                }
+            };
+            req.extensions_mut().insert(UserId {
+                user_id,
+                role,
+                api_token_id: None,
+            });
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
         }
         None => {
             let response = see_other("/login");
@@ -50,3 +103,146 @@ pub async fn reject_anonymous_users(
         }
     }
 }
+
+/// Like `reject_anonymous_users`, with JSON errors for the API.
+/// Clients without a browser session can send an API token as `Authorization: Bearer`.
+/// Requests changing state with a session must carry its CSRF token in `X-CSRF-Token`.
+pub async fn reject_anonymous_api_users(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if let Some(authorization) = req.headers().get(header::AUTHORIZATION) {
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| Secret::new(token.trim().to_owned()));
+        let owner = match token {
+            Some(token) => {
+                let pool = req
+                    .app_data::<web::Data<PgPool>>()
+                    .expect("The database pool is not registered.");
+                authenticate_api_token(&token, pool)
+                    .await
+                    .map_err(unexpected_api_error)?
+            }
+            None => None,
+        };
+        let owner = match owner {
+            Some(owner) => owner,
+            None => {
+                let e =
+                    ApiError::Unauthorized("The API token is invalid, expired or revoked.".into());
+                return Ok(req.into_response(e.error_response()).map_into_right_body());
+            }
+        };
+        // Resources declare the scope they need as `ApiScope` app data, tokens can't
+        // call those that declare none
+        let e = match req.app_data::<ApiScope>().copied() {
+            Some(scope) if owner.scopes.contains(&scope) => None,
+            Some(scope) => Some(ApiError::MissingScope(scope)),
+            None => Some(ApiError::Forbidden),
+        };
+        if let Some(e) = e {
+            return Ok(req.into_response(e.error_response()).map_into_right_body());
+        }
+        req.extensions_mut().insert(UserId {
+            user_id: owner.user_id,
+            role: owner.role,
+            api_token_id: Some(owner.token_id),
+        });
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await?
+    };
+    let user_id = session.get_user_id().map_err(unexpected_api_error)?;
+    let session_id = session.get_session_id().map_err(unexpected_api_error)?;
+    let role = match (user_id, session_id) {
+        (Some(user_id), Some(session_id)) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .expect("The database pool is not registered.");
+            touch_user_session(user_id, session_id, pool)
+                .await
+                .map_err(unexpected_api_error)?
+        }
+        _ => None,
+    };
+    match (user_id, role) {
+        (Some(user_id), Some(role)) => {
+            // Browsers send the session cookie along with forged requests, unlike API tokens
+            if !req.method().is_safe() {
+                let expected = session.get_csrf_token().map_err(unexpected_api_error)?;
+                let submitted = req
+                    .headers()
+                    .get(CSRF_TOKEN_HEADER)
+                    .and_then(|header| header.to_str().ok());
+                match (expected, submitted) {
+                    (Some(expected), Some(submitted)) if tokens_match(&expected, submitted) => {}
+                    _ => {
+                        tracing::warn!("The CSRF token is missing or invalid");
+                        let e = ApiError::InvalidCsrfToken;
+                        return Ok(req.into_response(e.error_response()).map_into_right_body());
+                    }
+                }
+            }
+            req.extensions_mut().insert(UserId {
+                user_id,
+                role,
+                api_token_id: None,
+            });
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        _ => {
+            if user_id.is_some() {
+                session.log_out();
+            }
+            let e = ApiError::Unauthorized("Authentication required.".into());
+            // Not an error, so the session middleware still applies the log out
+            Ok(req.into_response(e.error_response()).map_into_right_body())
+        }
+    }
+}
+
+fn unexpected_api_error(e: impl Into<anyhow::Error>) -> actix_web::Error {
+    ApiError::Unexpected(e.into()).into()
+}
+
+/// Only editors and owners get through, must run after `reject_anonymous_users`.
+pub async fn require_editor(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(UserRole::Editor, req, next).await
+}
+
+/// Only owners get through, must run after `reject_anonymous_users`.
+pub async fn require_owner(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(UserRole::Owner, req, next).await
+}
+
+async fn require_role(
+    required: UserRole,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let role = req.extensions().get::<UserId>().map(|user_id| user_id.role);
+    match role {
+        Some(role) if role >= required => next.call(req).await,
+        _ => {
+            let e = anyhow::anyhow!("The user needs the {} role", required);
+            Err(InternalError::from_response(e, forbidden()).into())
+        }
+    }
+}
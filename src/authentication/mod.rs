@@ -1,5 +1,46 @@
+mod api_tokens;
+mod csrf;
+mod invitation;
+mod login_throttle;
 mod middleware;
 mod password;
+mod password_reset;
+mod role;
+mod sessions;
+mod signature;
+mod two_factor;
+mod users;
 
-pub use middleware::{reject_anonymous_users, UserId};
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use api_tokens::{
+    authenticate_api_token, create_api_token, list_api_tokens, revoke_api_token, ApiScope,
+    ApiToken, ApiTokenOwner,
+};
+pub use csrf::{reject_forged_requests, CSRF_TOKEN_FIELD, CSRF_TOKEN_HEADER};
+pub use invitation::SignedInvitation;
+pub use login_throttle::{
+    get_recent_lockouts, lift_lockouts, record_lockout, LoginLockout, LoginThrottle,
+};
+pub use middleware::{
+    reject_anonymous_api_users, reject_anonymous_users, require_editor, require_owner, UserId,
+};
+pub use password::{
+    change_password, validate_credentials, validate_new_password, AuthError, Credentials,
+};
+pub use password_reset::{
+    create_password_reset_token, get_password_reset_recipient, is_valid_password_reset_token,
+    reset_password, PasswordResetRecipient,
+};
+pub use role::UserRole;
+pub use sessions::{
+    create_user_session, list_user_sessions, revoke_other_user_sessions, revoke_user_session,
+    touch_user_session, UserSession,
+};
+pub use signature::{sign, verify_signature};
+pub use two_factor::{
+    enable_two_factor, is_two_factor_enabled, reset_two_factor, start_two_factor_enrollment,
+    verify_second_factor, TotpCipher, TotpEnrollment,
+};
+pub use users::{
+    create_user, delete_user, get_user_id, insert_user, list_users, set_user_active, set_user_role,
+    validate_username, User,
+};
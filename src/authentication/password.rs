@@ -1,3 +1,5 @@
+use super::revoke_other_user_sessions;
+use crate::configuration::PasswordHashingSettings;
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use anyhow::Result;
@@ -9,6 +11,7 @@ use argon2::Version;
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use tracing::Instrument;
 use uuid::Uuid;
 
 #[derive(thiserror::Error, Debug)]
@@ -24,33 +27,66 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+#[tracing::instrument(name = "Validate credentials", skip(credentials, hashing, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashingSettings,
     pool: &PgPool,
 ) -> Result<uuid::Uuid, AuthError> {
-    let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
-
-    if let Some((stored_user_id, stored_password_hash)) =
-        get_stored_credentials(&credentials.username, pool).await?
-    {
-        user_id = Some(stored_user_id);
-        expected_password_hash = stored_password_hash;
-    }
+    let params = hashing
+        .params()
+        .context("Invalid password hashing settings.")?;
+    let (user_id, expected_password_hash) =
+        match get_stored_credentials(&credentials.username, pool).await? {
+            Some(stored) => stored,
+            None => {
+                // Verify against the hash of another user, so that unknown usernames
+                // cost as much as known ones, whatever params the stored hashes use
+                match get_fallback_password_hash(pool).await? {
+                    Some(fallback_password_hash) => {
+                        let _ = spawn_blocking_with_tracing(move || {
+                            verify_password_hash(&fallback_password_hash, credentials.password)
+                        })
+                        .await
+                        .context("Failed to spawn blocking task.")?;
+                    }
+                    None => {
+                        spawn_blocking_with_tracing(move || {
+                            compute_password_hash(credentials.password, params)
+                        })
+                        .await
+                        .context("Failed to spawn blocking task.")??;
+                    }
+                }
+                return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                    "Unkwown username."
+                )));
+            }
+        };
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let password = credentials.password.clone();
+    let stored_password_hash = expected_password_hash.clone();
+    let current_params = params.clone();
+    let needs_rehash = spawn_blocking_with_tracing(move || {
+        verify_password_hash(&expected_password_hash, password)?;
+        Ok::<_, AuthError>(needs_rehash(&expected_password_hash, &current_params))
     })
     .await
     .context("Failed to spawn blocking task.")??;
 
-    user_id.ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unkwown username.")))
+    if needs_rehash {
+        tokio::spawn(
+            rehash_password(
+                user_id,
+                credentials.password,
+                stored_password_hash,
+                params,
+                pool.clone(),
+            )
+            .in_current_span(),
+        );
+    }
+    Ok(user_id)
 }
 
 #[tracing::instrument(
@@ -58,7 +94,7 @@ pub async fn validate_credentials(
     skip(expected_password_hash, password_candidate)
 )]
 fn verify_password_hash(
-    expected_password_hash: Secret<String>,
+    expected_password_hash: &Secret<String>,
     password_candidate: Secret<String>,
 ) -> Result<(), AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
@@ -73,6 +109,66 @@ fn verify_password_hash(
         .map_err(AuthError::InvalidCredentials)
 }
 
+/// Check if a hash was computed with another algorithm or cost than the current one.
+fn needs_rehash(password_hash: &Secret<String>, params: &Params) -> bool {
+    let password_hash = match PasswordHash::new(password_hash.expose_secret()) {
+        Ok(password_hash) => password_hash,
+        Err(_) => return true,
+    };
+    let hash_params = match Params::try_from(&password_hash) {
+        Ok(hash_params) => hash_params,
+        Err(_) => return true,
+    };
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+        || hash_params.m_cost() != params.m_cost()
+        || hash_params.t_cost() != params.t_cost()
+        || hash_params.p_cost() != params.p_cost()
+}
+
+/// Store a hash of the password with the current parameters.
+/// The hash is only replaced if the password wasn't changed in the meantime.
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(password, old_password_hash, params, pool)
+)]
+async fn rehash_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    old_password_hash: Secret<String>,
+    params: Params,
+    pool: PgPool,
+) {
+    let result = async {
+        let password_hash =
+            spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+                .await?
+                .context("Failed to hash password")?;
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE user_id = $2 AND password_hash = $3
+            "#,
+            password_hash.expose_secret(),
+            user_id,
+            old_password_hash.expose_secret()
+        )
+        .execute(&pool)
+        .await
+        .context("Failed to store the upgraded password hash.")?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to upgrade the password hash."
+        );
+    }
+}
+
 #[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
 pub async fn get_stored_credentials(
     username: &str,
@@ -82,7 +178,7 @@ pub async fn get_stored_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-		WHERE username = $1
+		WHERE username = $1 AND is_active
         "#,
         username
     )
@@ -93,11 +189,39 @@ pub async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
-pub async fn change_password(user_id: Uuid, password: Secret<String>, pool: &PgPool) -> Result<()> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+/// Hash of any active user, verified in place of the hash of an unknown user.
+#[tracing::instrument(name = "Get fallback password hash", skip(pool))]
+async fn get_fallback_password_hash(pool: &PgPool) -> Result<Option<Secret<String>>> {
+    let password_hash = sqlx::query!(
+        r#"
+        SELECT password_hash
+        FROM users
+        WHERE is_active
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a fallback password hash.")?
+    .map(|row| Secret::new(row.password_hash));
+    Ok(password_hash)
+}
+
+/// Set a new password and log the user out everywhere, except in `keep_session_id`.
+#[tracing::instrument(name = "Change password", skip(password, hashing, pool))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    keep_session_id: Option<Uuid>,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<()> {
+    let params = hashing.params()?;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
+    let mut transaction = pool.begin().await?;
     sqlx::query!(
         r#"
         UPDATE users
@@ -107,20 +231,65 @@ pub async fn change_password(user_id: Uuid, password: Secret<String>, pool: &PgP
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(&mut transaction)
     .await
     .context("Failed to change user's password in the database.")?;
+    revoke_other_user_sessions(&mut transaction, user_id, keep_session_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password change.")?;
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
+/// Check the requirements for a new password.
+pub fn validate_new_password(password: &Secret<String>) -> Result<(), String> {
+    if !(12..=128).contains(&password.expose_secret().len()) {
+        return Err("New password should have between 12 and 128 characters.".to_string());
+    }
+    Ok(())
+}
+
+pub(super) fn compute_password_hash(
+    password: Secret<String>,
+    params: Params,
+) -> Result<Secret<String>> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(params: Params) -> Secret<String> {
+        compute_password_hash(Secret::new("password".to_string()), params).unwrap()
+    }
+
+    #[test]
+    fn hashes_with_the_current_params_are_kept() {
+        let params = Params::new(8, 1, 1, None).unwrap();
+        assert!(!needs_rehash(&hash(params.clone()), &params));
+    }
+
+    #[test]
+    fn hashes_with_other_params_are_upgraded() {
+        let params = Params::new(8, 1, 1, None).unwrap();
+        let weaker_params = Params::new(8, 2, 1, None).unwrap();
+        assert!(needs_rehash(&hash(weaker_params), &params));
+    }
+
+    #[test]
+    fn hashes_with_another_algorithm_are_upgraded() {
+        let params = Params::new(8, 1, 1, None).unwrap();
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let argon2i_hash = Argon2::new(Algorithm::Argon2i, Version::V0x13, params.clone())
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string();
+        assert!(needs_rehash(&Secret::new(argon2i_hash), &params));
+    }
+}
@@ -0,0 +1,153 @@
+use super::{password::compute_password_hash, revoke_other_user_sessions};
+use crate::{configuration::PasswordHashingSettings, telemetry::spawn_blocking_with_tracing};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Where to send the reset link of an active user.
+pub struct PasswordResetRecipient {
+    pub user_id: Uuid,
+    pub email: String,
+}
+
+#[tracing::instrument(name = "Get password reset recipient", skip(pool))]
+pub async fn get_password_reset_recipient(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<PasswordResetRecipient>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, email
+        FROM users
+        WHERE username = $1 AND is_active
+        "#,
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the password reset recipient.")?;
+    Ok(row.and_then(|r| {
+        r.email.map(|email| PasswordResetRecipient {
+            user_id: r.user_id,
+            email,
+        })
+    }))
+}
+
+/// Store a new single-use reset token for the user, only its hash is kept in the database.
+#[tracing::instrument(name = "Create password reset token", skip(pool))]
+pub async fn create_password_reset_token(
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+    pool: &PgPool,
+) -> Result<Secret<String>> {
+    let token: String = {
+        let mut rng = thread_rng();
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect()
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, now(), $3)
+        "#,
+        hash_token(&token),
+        user_id,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the password reset token.")?;
+    Ok(Secret::new(token))
+}
+
+/// Check that the token can still be used.
+#[tracing::instrument(name = "Validate password reset token", skip_all)]
+pub async fn is_valid_password_reset_token(token: &Secret<String>, pool: &PgPool) -> Result<bool> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM password_reset_tokens
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        "#,
+        hash_token(token.expose_secret())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the password reset token.")?;
+    Ok(row.is_some())
+}
+
+/// Set a new password and log the user out everywhere.
+/// Returns the user whose password was reset, or `None` if the token was already used
+/// or has expired.
+#[tracing::instrument(name = "Reset password", skip_all)]
+pub async fn reset_password(
+    token: &Secret<String>,
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<Option<Uuid>> {
+    let params = hashing.params()?;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
+    let mut transaction = pool.begin().await?;
+    let row = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_token(token.expose_secret())
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to mark the password reset token as used.")?;
+    let user_id = match row {
+        Some(row) => row.user_id,
+        None => return Ok(None),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2
+        "#,
+        password_hash.expose_secret(),
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to reset the user's password.")?;
+    revoke_other_user_sessions(&mut transaction, user_id, None).await?;
+    // Other links sent before this one are no longer needed
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to invalidate the remaining password reset tokens.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset.")?;
+    Ok(Some(user_id))
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
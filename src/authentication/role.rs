@@ -0,0 +1,72 @@
+use std::str::FromStr;
+
+/// Roles are ordered: each one can do everything the previous ones can.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    /// Can check the delivery process
+    Viewer,
+    /// Can also draft and publish newsletter issues
+    Editor,
+    /// Can also manage users
+    Owner,
+}
+
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Viewer => "viewer",
+            UserRole::Editor => "editor",
+            UserRole::Owner => "owner",
+        }
+    }
+}
+
+impl FromStr for UserRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(UserRole::Viewer),
+            "editor" => Ok(UserRole::Editor),
+            "owner" => Ok(UserRole::Owner),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for UserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserRole;
+
+    #[test]
+    fn roles_roundtrip_through_their_names() {
+        for role in [UserRole::Viewer, UserRole::Editor, UserRole::Owner] {
+            assert_eq!(role.as_str().parse::<UserRole>(), Ok(role));
+        }
+        assert!("admin".parse::<UserRole>().is_err());
+    }
+
+    #[test]
+    fn owners_have_every_permission() {
+        assert!(UserRole::Owner > UserRole::Editor);
+        assert!(UserRole::Editor > UserRole::Viewer);
+    }
+}
@@ -0,0 +1,144 @@
+use super::UserRole;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// A logged in session, as shown to its user.
+#[derive(Serialize)]
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub client_ip: String,
+    pub user_agent: String,
+    pub is_current: bool,
+}
+
+#[tracing::instrument(name = "Create user session", skip(pool))]
+pub async fn create_user_session(
+    user_id: Uuid,
+    client_ip: &str,
+    user_agent: &str,
+    pool: &PgPool,
+) -> Result<Uuid> {
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions
+            (session_id, user_id, created_at, last_seen_at, client_ip, user_agent)
+        VALUES ($1, $2, now(), now(), $3, $4)
+        "#,
+        session_id,
+        user_id,
+        client_ip,
+        user_agent
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the new session.")?;
+    Ok(session_id)
+}
+
+/// Mark the session as seen and return the role of its user.
+/// Returns `None` if the session was revoked or the user is not active anymore.
+#[tracing::instrument(name = "Touch user session", skip(pool))]
+pub async fn touch_user_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<UserRole>> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE user_sessions s
+        SET last_seen_at = now()
+        FROM users u
+        WHERE s.session_id = $1
+            AND s.user_id = $2
+            AND s.revoked_at IS NULL
+            AND u.user_id = s.user_id
+            AND u.is_active
+        RETURNING u.role
+        "#,
+        session_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the user's session.")?;
+    row.map(|r| r.role.parse().map_err(anyhow::Error::msg))
+        .transpose()
+}
+
+/// The session store drops a session a day after it was last changed, which is at login.
+#[tracing::instrument(name = "List user sessions", skip(pool))]
+pub async fn list_user_sessions(
+    user_id: Uuid,
+    current_session_id: Option<Uuid>,
+    pool: &PgPool,
+) -> Result<Vec<UserSession>> {
+    let sessions = sqlx::query!(
+        r#"
+        SELECT session_id, created_at, last_seen_at, client_ip, user_agent
+        FROM user_sessions
+        WHERE user_id = $1 AND revoked_at IS NULL AND created_at > now() - interval '1 day'
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the user's sessions.")?
+    .into_iter()
+    .map(|r| UserSession {
+        session_id: r.session_id,
+        created_at: r.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+        last_seen_at: r.last_seen_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+        client_ip: r.client_ip,
+        user_agent: r.user_agent,
+        is_current: Some(r.session_id) == current_session_id,
+    })
+    .collect();
+    Ok(sessions)
+}
+
+/// Returns `false` if the user has no such active session.
+#[tracing::instrument(name = "Revoke user session", skip(pool))]
+pub async fn revoke_user_session(user_id: Uuid, session_id: Uuid, pool: &PgPool) -> Result<bool> {
+    let n_revoked = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the session.")?
+    .rows_affected();
+    Ok(n_revoked > 0)
+}
+
+/// Log the user out everywhere, except in `keep_session_id` if given.
+#[tracing::instrument(name = "Revoke other user sessions", skip(transaction))]
+pub async fn revoke_other_user_sessions(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    keep_session_id: Option<Uuid>,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL AND session_id IS DISTINCT FROM $2
+        "#,
+        user_id,
+        keep_session_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to revoke the user's sessions.")?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Hex-encoded HMAC-SHA256 of `payload`, used to sign links sent by email.
+pub fn sign(secret: &Secret<String>, payload: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size.");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Check `signature` against `payload` in constant time.
+pub fn verify_signature(secret: &Secret<String>, payload: &str, signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let mut mac = HmacSha256::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size.");
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret() -> Secret<String> {
+        Secret::new("some-secret".to_string())
+    }
+
+    #[test]
+    fn a_valid_signature_is_accepted() {
+        let signature = sign(&secret(), "payload");
+        assert!(verify_signature(&secret(), "payload", &signature));
+    }
+
+    #[test]
+    fn a_signature_for_another_payload_is_rejected() {
+        let signature = sign(&secret(), "payload");
+        assert!(!verify_signature(&secret(), "another payload", &signature));
+    }
+
+    #[test]
+    fn a_malformed_signature_is_rejected() {
+        assert!(!verify_signature(&secret(), "payload", "not-hex"));
+    }
+}
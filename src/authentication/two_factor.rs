@@ -0,0 +1,360 @@
+use anyhow::{Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const ISSUER: &str = "Zero2Prod";
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+const N_RECOVERY_CODES: usize = 10;
+
+/// Encrypts TOTP secrets at rest with a key derived from the application secret.
+pub struct TotpCipher(XChaCha20Poly1305);
+
+impl TotpCipher {
+    pub fn new(hmac_secret: &Secret<String>) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size.");
+        mac.update(b"totp-secret-encryption");
+        let key = mac.finalize().into_bytes();
+        Self(XChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    /// Returns the nonce followed by the ciphertext.
+    fn encrypt(&self, secret: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; 24];
+        thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .0
+            .encrypt(XNonce::from_slice(&nonce), secret)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt the TOTP secret."))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, encrypted: &[u8]) -> Result<Secret<Vec<u8>>> {
+        if encrypted.len() < 24 {
+            anyhow::bail!("The encrypted TOTP secret is too short.");
+        }
+        let (nonce, ciphertext) = encrypted.split_at(24);
+        self.0
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map(Secret::new)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt the TOTP secret."))
+    }
+}
+
+/// HOTP value for the given time step (RFC 4226 and RFC 6238).
+fn totp(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC can take a key of any size.");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    code % 10u32.pow(DIGITS)
+}
+
+fn current_step() -> i64 {
+    chrono::Utc::now().timestamp() / STEP_SECS
+}
+
+/// Find the time step matching `code`, allowing one step of clock drift.
+fn matching_step(secret: &[u8], code: &str, now: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    (now - 1..=now + 1).find(|step| totp(secret, *step) == code)
+}
+
+/// Data shown to the user to set up their authenticator app.
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub uri: String,
+}
+
+/// Return the pending secret of the user, creating one if needed.
+#[tracing::instrument(name = "Start two-factor enrollment", skip(cipher, pool))]
+pub async fn start_two_factor_enrollment(
+    user_id: Uuid,
+    username: &str,
+    cipher: &TotpCipher,
+    pool: &PgPool,
+) -> Result<TotpEnrollment> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1 AND totp_enabled_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the pending TOTP secret.")?;
+    let secret = match row.totp_secret {
+        // Reusing it keeps an already scanned QR code valid when the page is reloaded
+        Some(encrypted) => cipher.decrypt(&encrypted)?,
+        None => {
+            let mut secret = vec![0u8; 20];
+            thread_rng().fill_bytes(&mut secret);
+            sqlx::query!(
+                r#"UPDATE users SET totp_secret = $1 WHERE user_id = $2"#,
+                cipher.encrypt(&secret)?,
+                user_id
+            )
+            .execute(pool)
+            .await
+            .context("Failed to store the pending TOTP secret.")?;
+            Secret::new(secret)
+        }
+    };
+    let secret = data_encoding::BASE32_NOPAD.encode(secret.expose_secret());
+    let mut uri = reqwest::Url::parse("otpauth://totp/").expect("Invalid otpauth URI.");
+    uri.path_segments_mut()
+        .expect("otpauth URIs have a path.")
+        .push(&format!("{}:{}", ISSUER, username));
+    uri.query_pairs_mut()
+        .append_pair("secret", &secret)
+        .append_pair("issuer", ISSUER)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECS.to_string());
+    Ok(TotpEnrollment {
+        secret,
+        uri: uri.to_string(),
+    })
+}
+
+/// Enable two-factor authentication if `code` matches the pending secret.
+/// Returns the recovery codes, or `None` if the code is wrong.
+#[tracing::instrument(name = "Enable two-factor authentication", skip(code, cipher, pool))]
+pub async fn enable_two_factor(
+    user_id: Uuid,
+    code: &str,
+    cipher: &TotpCipher,
+    pool: &PgPool,
+) -> Result<Option<Vec<Secret<String>>>> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1 AND totp_enabled_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the pending TOTP secret.")?;
+    let secret = match row.totp_secret {
+        Some(encrypted) => cipher.decrypt(&encrypted)?,
+        None => return Ok(None),
+    };
+    let step = match matching_step(secret.expose_secret(), code, current_step()) {
+        Some(step) => step,
+        None => return Ok(None),
+    };
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_enabled_at = now(), totp_last_used_step = $1
+        WHERE user_id = $2
+        "#,
+        step,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enable two-factor authentication.")?;
+    let recovery_codes = (0..N_RECOVERY_CODES)
+        .map(|_| generate_recovery_code())
+        .collect::<Vec<_>>();
+    for code in &recovery_codes {
+        sqlx::query!(
+            r#"INSERT INTO two_factor_recovery_codes (user_id, code_hash) VALUES ($1, $2)"#,
+            user_id,
+            hash_recovery_code(code.expose_secret())
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to store a recovery code.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the two-factor enrollment.")?;
+    Ok(Some(recovery_codes))
+}
+
+#[tracing::instrument(name = "Check if two-factor authentication is enabled", skip(pool))]
+pub async fn is_two_factor_enabled(user_id: Uuid, pool: &PgPool) -> Result<bool> {
+    let row = sqlx::query!(
+        r#"SELECT totp_enabled_at FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check if two-factor authentication is enabled.")?;
+    Ok(row.totp_enabled_at.is_some())
+}
+
+/// Check the second factor of a login, either a TOTP code or an unused recovery code.
+/// Both are single-use.
+#[tracing::instrument(name = "Verify second factor", skip(code, cipher, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    cipher: &TotpCipher,
+    pool: &PgPool,
+) -> Result<bool> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret
+        FROM users
+        WHERE user_id = $1 AND totp_enabled_at IS NOT NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the TOTP secret.")?;
+    let encrypted = match row {
+        Some(row) => row
+            .totp_secret
+            .context("Two-factor authentication is enabled without a secret.")?,
+        None => return Ok(false),
+    };
+    let secret = cipher.decrypt(&encrypted)?;
+    if let Some(step) = matching_step(secret.expose_secret(), code, current_step()) {
+        // Codes from already used steps are rejected, the condition also guards
+        // against concurrent logins using the same code
+        let n_updated = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $1
+            WHERE user_id = $2
+                AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+            "#,
+            step,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to store the last used TOTP step.")?
+        .rows_affected();
+        return Ok(n_updated > 0);
+    }
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE two_factor_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use a recovery code.")?
+    .rows_affected();
+    Ok(n_updated > 0)
+}
+
+/// Turn off two-factor authentication and forget the secret and recovery codes.
+/// Returns `false` if there is no user with that id.
+#[tracing::instrument(name = "Reset two-factor authentication", skip(pool))]
+pub async fn reset_two_factor(user_id: Uuid, pool: &PgPool) -> Result<bool> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"DELETE FROM two_factor_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the recovery codes.")?;
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to reset two-factor authentication.")?
+    .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the two-factor reset.")?;
+    Ok(n_updated > 0)
+}
+
+/// Formatted as `xxxxx-xxxxx` to be easier to copy by hand.
+fn generate_recovery_code() -> Secret<String> {
+    let mut rng = thread_rng();
+    let chars = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect::<String>();
+    Secret::new(format!("{}-{}", &chars[..5], &chars[5..]))
+}
+
+/// Recovery codes are random, a fast hash is enough to protect them at rest.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect::<String>();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totp_matches_the_rfc_6238_test_vectors() {
+        // SHA1 vectors from RFC 6238 appendix B, truncated to 6 digits
+        let secret = b"12345678901234567890";
+        assert_eq!(totp(secret, 59 / STEP_SECS), 287082);
+        assert_eq!(totp(secret, 1111111109 / STEP_SECS), 81804);
+        assert_eq!(totp(secret, 1234567890 / STEP_SECS), 5924);
+    }
+
+    #[test]
+    fn codes_from_adjacent_steps_are_accepted() {
+        let secret = b"12345678901234567890";
+        let code = format!("{:06}", totp(secret, 100));
+        assert_eq!(matching_step(secret, &code, 101), Some(100));
+        assert_eq!(matching_step(secret, &code, 99), Some(100));
+        assert_eq!(matching_step(secret, &code, 102), None);
+        assert_eq!(matching_step(secret, "12345", 100), None);
+    }
+
+    #[test]
+    fn secrets_roundtrip_through_encryption() {
+        let cipher = TotpCipher::new(&Secret::new("some-secret".to_string()));
+        let encrypted = cipher.encrypt(b"totp secret").unwrap();
+        let decrypted = cipher.decrypt(&encrypted).unwrap();
+        assert_eq!(decrypted.expose_secret(), b"totp secret");
+        let other = TotpCipher::new(&Secret::new("other-secret".to_string()));
+        assert!(other.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        assert_eq!(
+            hash_recovery_code("abcde-12345"),
+            hash_recovery_code("ABCDE 12345")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use super::{password::compute_password_hash, UserRole};
+use crate::{
+    configuration::PasswordHashingSettings, idempotency::IdempotencyKeyOwner,
+    telemetry::spawn_blocking_with_tracing,
+};
+use anyhow::{Context, Result};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct User {
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: Option<String>,
+    pub is_active: bool,
+    pub role: UserRole,
+    pub two_factor_enabled: bool,
+}
+
+/// Check the requirements for a new username.
+pub fn validate_username(username: &str) -> Result<(), String> {
+    if username.trim().is_empty() {
+        return Err("Username can't be empty.".to_string());
+    }
+    if username.graphemes(true).count() > 64 {
+        return Err("Username should have at most 64 characters.".to_string());
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Create user", skip(password, hashing, pool))]
+pub async fn create_user(
+    username: &str,
+    email: Option<&str>,
+    password: Secret<String>,
+    role: UserRole,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<Uuid> {
+    let mut transaction = pool.begin().await?;
+    let user_id = insert_user(&mut transaction, username, email, password, role, hashing).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new user.")?;
+    Ok(user_id)
+}
+
+#[tracing::instrument(name = "Insert user", skip(transaction, password, hashing))]
+pub async fn insert_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: Option<&str>,
+    password: Secret<String>,
+    role: UserRole,
+    hashing: &PasswordHashingSettings,
+) -> Result<Uuid> {
+    validate_username(username).map_err(anyhow::Error::msg)?;
+    let params = hashing.params()?;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, password_hash, role)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        username,
+        email,
+        password_hash.expose_secret(),
+        role.as_str()
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.constraint() == Some("users_email_key") => {
+            anyhow::anyhow!(
+                "The email {} is already used by another user.",
+                email.unwrap_or("")
+            )
+        }
+        sqlx::Error::Database(e) if e.to_string().contains("duplicate key value") => {
+            anyhow::anyhow!("The username {} is already taken.", username)
+        }
+        e => anyhow::Error::from(e).context("Failed to insert the new user in the database."),
+    })?;
+    Ok(user_id)
+}
+
+#[tracing::instrument(name = "Get user id", skip(pool))]
+pub async fn get_user_id(username: &str, pool: &PgPool) -> Result<Option<Uuid>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a user id.")?;
+    Ok(row.map(|r| r.user_id))
+}
+
+#[tracing::instrument(name = "List users", skip(pool))]
+pub async fn list_users(pool: &PgPool) -> Result<Vec<User>> {
+    sqlx::query!(
+        r#"
+        SELECT
+            user_id, username, email, is_active, role,
+            totp_enabled_at IS NOT NULL as "two_factor_enabled!"
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the list of users.")?
+    .into_iter()
+    .map(|r| {
+        Ok(User {
+            user_id: r.user_id,
+            username: r.username,
+            email: r.email,
+            is_active: r.is_active,
+            role: r.role.parse().map_err(anyhow::Error::msg)?,
+            two_factor_enabled: r.two_factor_enabled,
+        })
+    })
+    .collect()
+}
+
+/// Delete a user together with the data that references it.
+/// Returns `false` if there is no user with that id.
+#[tracing::instrument(name = "Delete user", skip(pool))]
+pub async fn delete_user(user_id: Uuid, pool: &PgPool) -> Result<bool> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"DELETE FROM idempotency WHERE key_owner = $1"#,
+        IdempotencyKeyOwner::User(user_id).to_string()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the user's idempotency keys.")?;
+    let n_deleted = sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the user.")?
+        .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the user deletion.")?;
+    Ok(n_deleted > 0)
+}
+
+/// Inactive users can't log in and their sessions are rejected.
+/// Returns `false` if there is no user with that id.
+#[tracing::instrument(name = "Set user active", skip(pool))]
+pub async fn set_user_active(user_id: Uuid, is_active: bool, pool: &PgPool) -> Result<bool> {
+    let n_updated = sqlx::query!(
+        r#"UPDATE users SET is_active = $1 WHERE user_id = $2"#,
+        is_active,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the user's status.")?
+    .rows_affected();
+    Ok(n_updated > 0)
+}
+
+/// Returns `false` if there is no user with that id.
+#[tracing::instrument(name = "Set user role", skip(pool))]
+pub async fn set_user_role(user_id: Uuid, role: UserRole, pool: &PgPool) -> Result<bool> {
+    let n_updated = sqlx::query!(
+        r#"UPDATE users SET role = $1 WHERE user_id = $2"#,
+        role.as_str(),
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the user's role.")?
+    .rows_affected();
+    Ok(n_updated > 0)
+}
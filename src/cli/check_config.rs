@@ -0,0 +1,35 @@
+use crate::{configuration::Settings, get_connection_pool};
+use anyhow::Context;
+
+/// Validate the configuration and check that the database is reachable.
+pub async fn check_config(configuration: Settings) -> Result<(), anyhow::Error> {
+    configuration
+        .email_client
+        .sender()
+        .map_err(anyhow::Error::msg)
+        .context("Invalid email_client.sender_email.")?;
+    configuration
+        .email_client
+        .clone()
+        .client()
+        .context("Invalid email_client settings.")?;
+    configuration
+        .password_hashing
+        .params()
+        .context("Invalid password_hashing settings.")?;
+    configuration
+        .rate_limit
+        .validate()
+        .context("Invalid rate_limit settings.")?;
+    configuration
+        .idempotency
+        .validate()
+        .context("Invalid idempotency settings.")?;
+    let pool = get_connection_pool(&configuration.database);
+    sqlx::query("SELECT 1")
+        .execute(&pool)
+        .await
+        .context("Failed to connect to the database.")?;
+    println!("Configuration is valid.");
+    Ok(())
+}
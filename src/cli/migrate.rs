@@ -0,0 +1,13 @@
+use crate::{configuration::Settings, get_connection_pool};
+use anyhow::Context;
+
+/// Apply the pending database migrations embedded in the binary.
+pub async fn migrate(configuration: Settings) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .context("Failed to migrate the database.")?;
+    println!("Database migrations applied.");
+    Ok(())
+}
@@ -0,0 +1,92 @@
+mod check_config;
+mod migrate;
+mod run;
+mod user;
+
+pub use check_config::check_config;
+pub use migrate::migrate;
+pub use run::{run, Role, RunArgs};
+pub use user::{user, UserCommand};
+
+use clap::{Parser, Subcommand};
+
+/// Zero2prod newsletter service.
+#[derive(Parser)]
+#[clap(version)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web server and/or the background workers (default).
+    Run(RunArgs),
+    /// Apply the pending database migrations.
+    Migrate,
+    /// Manage the admin users.
+    #[clap(subcommand)]
+    User(UserCommand),
+    /// Check that the configuration is valid and the database is reachable.
+    CheckConfig,
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self::Run(RunArgs::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_run_args(args: &[&str]) -> RunArgs {
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command.unwrap_or_default() {
+            Command::Run(args) => args,
+            _ => panic!("Expected the run command."),
+        }
+    }
+
+    #[test]
+    fn all_roles_run_by_default() {
+        let args = parse_run_args(&["zero2prod"]);
+        assert!(args.runs(Role::Web));
+        assert!(args.runs(Role::IssueDelivery));
+        assert!(args.runs(Role::IdempotencyExpiration));
+        assert!(args.runs(Role::SessionExpiration));
+    }
+
+    #[test]
+    fn only_the_selected_roles_run() {
+        let args = parse_run_args(&[
+            "zero2prod",
+            "run",
+            "--role",
+            "issue-delivery",
+            "--issue-delivery-workers",
+            "4",
+        ]);
+        assert!(!args.runs(Role::Web));
+        assert!(args.runs(Role::IssueDelivery));
+        assert!(!args.runs(Role::IdempotencyExpiration));
+        assert!(!args.runs(Role::SessionExpiration));
+        assert_eq!(args.issue_delivery_workers, 4);
+    }
+
+    #[test]
+    fn user_subcommands_are_parsed() {
+        let cli = Cli::try_parse_from(["zero2prod", "user", "reset-password", "admin"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::User(UserCommand::ResetPassword { username })) if username == "admin"
+        ));
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        let result = Cli::try_parse_from(["zero2prod", "run", "--role", "mailer"]);
+        assert!(result.is_err());
+    }
+}
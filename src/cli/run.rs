@@ -0,0 +1,149 @@
+use crate::{
+    configuration::{SessionStoreBackend, Settings},
+    idempotency_expiration_worker, issue_delivery_worker, session_expiration_worker,
+    shutdown::{shutdown_channel, wait_for_signal},
+    Application,
+};
+use clap::{Args, ValueEnum};
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Role {
+    /// The HTTP server
+    Web,
+    /// Workers sending newsletter issues
+    IssueDelivery,
+    /// Workers removing expired idempotency keys
+    IdempotencyExpiration,
+    /// Workers removing expired sessions, when they are kept in Postgres
+    SessionExpiration,
+    /// Every role in a single process
+    All,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Roles to run in this process, can be repeated
+    #[clap(long = "role", value_enum, default_value = "all")]
+    pub roles: Vec<Role>,
+    /// Number of issue delivery workers
+    #[clap(long, default_value_t = 1)]
+    pub issue_delivery_workers: usize,
+    /// Number of idempotency expiration workers
+    #[clap(long, default_value_t = 1)]
+    pub idempotency_expiration_workers: usize,
+    /// Number of session expiration workers
+    #[clap(long, default_value_t = 1)]
+    pub session_expiration_workers: usize,
+}
+
+impl Default for RunArgs {
+    fn default() -> Self {
+        Self {
+            roles: vec![Role::All],
+            issue_delivery_workers: 1,
+            idempotency_expiration_workers: 1,
+            session_expiration_workers: 1,
+        }
+    }
+}
+
+impl RunArgs {
+    pub fn runs(&self, role: Role) -> bool {
+        self.roles.contains(&Role::All) || self.roles.contains(&role)
+    }
+}
+
+/// Run the selected roles until a shutdown signal arrives or any of them exits.
+pub async fn run(configuration: Settings, args: RunArgs) -> Result<(), anyhow::Error> {
+    let drain_timeout = configuration.application.drain_timeout();
+    let (shutdown_trigger, shutdown) = shutdown_channel();
+    let mut tasks: FuturesUnordered<BoxFuture<()>> = FuturesUnordered::new();
+
+    if args.runs(Role::Web) {
+        let application = Application::build(configuration.clone()).await?;
+        let application_task = tokio::spawn(application.run_until_shutdown(shutdown.clone()));
+        tasks.push(report_exit("API".to_string(), application_task).boxed());
+    }
+    if args.runs(Role::IssueDelivery) {
+        for i in 0..args.issue_delivery_workers {
+            let worker_task = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
+                configuration.clone(),
+                shutdown.clone(),
+            ));
+            let task_name = format!("Background worker (issue_delivery #{})", i);
+            tasks.push(report_exit(task_name, worker_task).boxed());
+        }
+    }
+    if args.runs(Role::IdempotencyExpiration) {
+        for i in 0..args.idempotency_expiration_workers {
+            let worker_task =
+                tokio::spawn(idempotency_expiration_worker::run_worker_until_stopped(
+                    configuration.clone(),
+                    shutdown.clone(),
+                ));
+            let task_name = format!("Background worker (idempotency_expiration #{})", i);
+            tasks.push(report_exit(task_name, worker_task).boxed());
+        }
+    }
+    // Redis expires its keys and the memory store checks on read
+    if args.runs(Role::SessionExpiration)
+        && configuration.session_store.backend == SessionStoreBackend::Postgres
+    {
+        for i in 0..args.session_expiration_workers {
+            let worker_task = tokio::spawn(session_expiration_worker::run_worker_until_stopped(
+                configuration.clone(),
+                shutdown.clone(),
+            ));
+            let task_name = format!("Background worker (session_expiration #{})", i);
+            tasks.push(report_exit(task_name, worker_task).boxed());
+        }
+    }
+    if tasks.is_empty() {
+        anyhow::bail!("There is nothing to run, check the selected roles and worker counts.");
+    }
+
+    // Stop everything as soon as we get a signal or any of the tasks exits
+    tokio::select! {
+        _ = wait_for_signal() => tracing::info!("Shutdown signal received"),
+        _ = tasks.next() => {}
+    }
+    shutdown_trigger.trigger();
+    let drain = tasks.collect::<Vec<_>>();
+    if tokio::time::timeout(drain_timeout, drain).await.is_err() {
+        tracing::warn!(
+            "Some tasks didn't finish within the drain timeout of {:?}",
+            drain_timeout
+        );
+    }
+    Ok(())
+}
+
+async fn report_exit(
+    task_name: String,
+    task: impl std::future::Future<Output = Result<Result<(), impl Debug + Display>, JoinError>>,
+) {
+    match task.await {
+        Ok(Ok(_)) => {
+            tracing::info!("{} has exited", task_name);
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            );
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{}' task failed to complete",
+                task_name
+            );
+        }
+    }
+}
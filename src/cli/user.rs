@@ -0,0 +1,106 @@
+use crate::{
+    authentication::{
+        change_password, create_user, delete_user, get_user_id, list_users, validate_new_password,
+        UserRole,
+    },
+    configuration::Settings,
+    domain::SubscriberEmail,
+    get_connection_pool,
+};
+use anyhow::Context;
+use clap::Subcommand;
+use secrecy::Secret;
+use std::io::{BufRead, Write};
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Create a new admin user, the password is read from stdin.
+    Create {
+        username: String,
+        /// Address where password reset links are sent
+        #[clap(long)]
+        email: Option<String>,
+        #[clap(long, value_enum, default_value = "owner")]
+        role: UserRole,
+    },
+    /// Set a new password for a user, the password is read from stdin.
+    ResetPassword { username: String },
+    /// Delete a user.
+    Delete { username: String },
+    /// List all the users.
+    List,
+}
+
+pub async fn user(configuration: Settings, command: UserCommand) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    match command {
+        UserCommand::Create {
+            username,
+            email,
+            role,
+        } => {
+            let email = email
+                .map(|e| e.parse::<SubscriberEmail>())
+                .transpose()
+                .map_err(anyhow::Error::msg)?;
+            let password = read_new_password()?;
+            let email = email.as_ref().map(|e| e.as_ref());
+            let user_id = create_user(
+                &username,
+                email,
+                password,
+                role,
+                &configuration.password_hashing,
+                &pool,
+            )
+            .await?;
+            println!("Created {} {} ({}).", role, username, user_id);
+        }
+        UserCommand::ResetPassword { username } => {
+            let user_id = get_user_id(&username, &pool)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("There is no user named {}.", username))?;
+            let password = read_new_password()?;
+            change_password(
+                user_id,
+                password,
+                None,
+                &configuration.password_hashing,
+                &pool,
+            )
+            .await?;
+            println!("Changed the password of {}.", username);
+        }
+        UserCommand::Delete { username } => {
+            let user_id = get_user_id(&username, &pool)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("There is no user named {}.", username))?;
+            delete_user(user_id, &pool).await?;
+            println!("Deleted user {}.", username);
+        }
+        UserCommand::List => {
+            for user in list_users(&pool).await? {
+                let status = if user.is_active { "active" } else { "inactive" };
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    user.user_id, user.username, user.role, status
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read a password from the first line of stdin, so it doesn't end up in the shell history.
+fn read_new_password() -> Result<Secret<String>, anyhow::Error> {
+    eprint!("New password: ");
+    std::io::stderr().flush()?;
+    let mut password = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut password)
+        .context("Failed to read the password from stdin.")?;
+    let password = Secret::new(password.trim_end_matches(&['\r', '\n'][..]).to_string());
+    validate_new_password(&password).map_err(anyhow::Error::msg)?;
+    Ok(password)
+}
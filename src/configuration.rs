@@ -1,4 +1,7 @@
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{
+    domain::{LocalPartHandling, SubscriberEmail},
+    email_client::EmailClient,
+};
 use config::Config;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
@@ -9,6 +12,7 @@ use sqlx::{
 };
 use std::{
     convert::{TryFrom, TryInto},
+    num::{NonZeroU32, NonZeroU64},
     time::Duration,
 };
 
@@ -18,8 +22,15 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub session_store: SessionStoreSettings,
     pub issue_delivery: IssueDeliverySettings,
     pub idempotency: IdempotencySettings,
+    pub accounts: AccountSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub email_policy: EmailPolicySettings,
 }
 
 #[derive(Clone, Deserialize)]
@@ -58,6 +69,15 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// How long to wait for in-flight requests and tasks to finish on shutdown
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub drain_timeout_secs: u64,
+}
+
+impl ApplicationSettings {
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs)
+    }
 }
 
 #[derive(Clone, Deserialize)]
@@ -79,15 +99,242 @@ pub struct IssueDeliverySettings {
     pub backoff_cap_secs: i64,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub max_retries: i16,
+    /// Send rate limits shared by all the worker instances
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_emails_per_second: NonZeroU32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_emails_per_hour: NonZeroU32,
+    /// How often idle workers check the queue when no notification arrives, never 0 so that
+    /// they don't busy-loop on the database
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_secs: NonZeroU64,
+}
+
+impl IssueDeliverySettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs.get())
+    }
 }
 
 #[derive(Clone, Deserialize)]
 pub struct IdempotencySettings {
+    /// Retention of the keys of the routes that don't set their own
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub expiration_secs: u64,
     /// How often to check for expiration
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub expiration_frequency_secs: u64,
+    /// How long a request waits for another one with the same key before being told to retry
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub wait_timeout_millis: u64,
+    /// How long a key stays in progress without news from its request, before another
+    /// request can take it over
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub processing_timeout_secs: u64,
+    /// Where the keys of the public endpoints are kept, Redis suits their volume better
+    pub public_backend: IdempotencyBackend,
+    /// Retention of the keys of the public endpoints
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub public_expiration_secs: u64,
+    /// Namespace of the keys kept in Redis
+    pub redis_key_prefix: String,
+}
+
+/// Where idempotency keys and their saved responses are kept.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdempotencyBackend {
+    Postgres,
+    Redis,
+}
+
+impl IdempotencySettings {
+    pub fn wait_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.wait_timeout_millis)
+    }
+
+    pub fn expiration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.expiration_secs)
+    }
+
+    pub fn processing_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.processing_timeout_secs)
+    }
+
+    pub fn public_expiration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.public_expiration_secs)
+    }
+
+    /// Keys can't be kept or leased for no time, Redis rejects a zero expiration.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        for (name, secs) in [
+            ("expiration_secs", self.expiration_secs),
+            ("public_expiration_secs", self.public_expiration_secs),
+            ("processing_timeout_secs", self.processing_timeout_secs),
+        ] {
+            if secs == 0 {
+                anyhow::bail!("idempotency.{} must be greater than 0.", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SessionStoreSettings {
+    pub backend: SessionStoreBackend,
+    /// How often to remove the expired sessions from Postgres
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub expiration_frequency_secs: u64,
+}
+
+/// Where the sessions and the login failure counters are kept.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreBackend {
+    Redis,
+    Postgres,
+    /// Only for tests and single process deployments, everything is lost on restart
+    Memory,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AccountSettings {
+    /// How long an invitation link stays valid
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub invitation_expiration_secs: u64,
+    /// How long a password reset link stays valid
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub password_reset_expiration_secs: u64,
+}
+
+impl AccountSettings {
+    pub fn invitation_expiration(&self) -> Duration {
+        Duration::from_secs(self.invitation_expiration_secs)
+    }
+
+    pub fn password_reset_expiration(&self) -> Duration {
+        Duration::from_secs(self.password_reset_expiration_secs)
+    }
+}
+
+/// Argon2id cost of new password hashes, older hashes are upgraded on login.
+#[derive(Clone, Deserialize)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_size_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(
+            self.memory_size_kib,
+            self.iterations,
+            self.parallelism,
+            None,
+        )
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct LoginThrottleSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u32,
+    /// Failures older than this are forgotten
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_window_secs: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_secs: u64,
+    /// Delay after a failed attempt, doubled with each failure of the same username
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub delay_base_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_ms: u64,
+    /// Take the client IP from the `X-Forwarded-For` header, only safe behind a proxy
+    pub trust_proxy_headers: bool,
+    /// Namespace of the failure counters
+    pub redis_key_prefix: String,
+}
+
+/// Limits of the public endpoints that send emails, counted over a sliding window.
+#[derive(Clone, Deserialize)]
+pub struct RateLimitSettings {
+    pub subscriptions: RateLimitRule,
+    pub password_reset: RateLimitRule,
+    /// Namespace of the counters
+    pub redis_key_prefix: String,
+}
+
+impl RateLimitSettings {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        self.subscriptions.validate("subscriptions")?;
+        self.password_reset.validate("password_reset")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RateLimitRule {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests_per_ip: u32,
+    /// Requests for the same email address or username, whoever sends them
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests_per_recipient: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_secs: u64,
+}
+
+impl RateLimitRule {
+    fn validate(&self, route: &str) -> Result<(), anyhow::Error> {
+        for (name, value) in [
+            ("max_requests_per_ip", self.max_requests_per_ip as u64),
+            (
+                "max_requests_per_recipient",
+                self.max_requests_per_recipient as u64,
+            ),
+            ("window_secs", self.window_secs),
+        ] {
+            if value == 0 {
+                anyhow::bail!("rate_limit.{}.{} must be greater than 0.", route, name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks of the subscription form, see `BotCheck`.
+#[derive(Clone, Deserialize)]
+pub struct BotProtectionSettings {
+    /// Forms submitted sooner after being served are taken for bots
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_fill_secs: u64,
+    /// Forms served longer ago have to be submitted again
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_form_age_secs: u64,
+    /// Difficulty of the proof of work asked from the browser, 0 to disable it
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub proof_of_work_bits: u32,
+}
+
+/// Which addresses can subscribe, see `EmailPolicy`.
+#[derive(Clone, Deserialize)]
+pub struct EmailPolicySettings {
+    /// File listing the domains of disposable addresses, one per line
+    pub disposable_domains_file: Option<String>,
+    /// Reject shared mailboxes like `postmaster@` or `abuse@`
+    pub block_role_addresses: bool,
+    /// Only these domains and their subdomains can subscribe when the list isn't empty
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    #[serde(default)]
+    pub denied_domains: Vec<String>,
+    #[serde(default)]
+    pub local_part: LocalPartHandling,
 }
 
 impl DatabaseSettings {
@@ -168,3 +415,47 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(window_secs: u64) -> RateLimitRule {
+        RateLimitRule {
+            max_requests_per_ip: 20,
+            max_requests_per_recipient: 3,
+            window_secs,
+        }
+    }
+
+    #[test]
+    fn rate_limit_windows_cant_be_empty() {
+        let mut settings = RateLimitSettings {
+            subscriptions: rule(3600),
+            password_reset: rule(3600),
+            redis_key_prefix: "rate_limit".into(),
+        };
+        assert!(settings.validate().is_ok());
+        settings.password_reset = rule(0);
+        assert!(settings.validate().is_err());
+        settings.password_reset.window_secs = 3600;
+        settings.subscriptions.max_requests_per_recipient = 0;
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn idempotency_keys_cant_be_kept_for_no_time() {
+        let mut settings = IdempotencySettings {
+            expiration_secs: 1800,
+            expiration_frequency_secs: 3600,
+            wait_timeout_millis: 5000,
+            processing_timeout_secs: 30,
+            public_backend: IdempotencyBackend::Redis,
+            public_expiration_secs: 600,
+            redis_key_prefix: "idempotency".into(),
+        };
+        assert!(settings.validate().is_ok());
+        settings.public_expiration_secs = 0;
+        assert!(settings.validate().is_err());
+    }
+}
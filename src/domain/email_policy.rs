@@ -0,0 +1,168 @@
+use super::{subscriber_email::normalize_domain, LocalPartHandling, SubscriberEmail};
+use crate::configuration::EmailPolicySettings;
+use anyhow::Context;
+use std::collections::HashSet;
+
+/// Local parts of shared mailboxes, rather than of a person.
+const ROLE_ADDRESSES: &[&str] = &[
+    "abuse",
+    "admin",
+    "hostmaster",
+    "mailer-daemon",
+    "no-reply",
+    "noc",
+    "noreply",
+    "postmaster",
+    "root",
+    "security",
+    "webmaster",
+];
+
+/// Which addresses can subscribe, on top of being valid.
+#[derive(Debug, Default)]
+pub struct EmailPolicy {
+    disposable_domains: HashSet<String>,
+    block_role_addresses: bool,
+    allowed_domains: HashSet<String>,
+    denied_domains: HashSet<String>,
+    local_part: LocalPartHandling,
+}
+
+impl EmailPolicy {
+    pub fn new(settings: &EmailPolicySettings) -> Result<Self, anyhow::Error> {
+        let disposable_domains = match &settings.disposable_domains_file {
+            Some(path) => {
+                let list = std::fs::read_to_string(path).with_context(|| {
+                    format!("Failed to read the disposable domains in {}.", path)
+                })?;
+                parse_domains(list.lines())?
+            }
+            None => HashSet::new(),
+        };
+        Ok(Self {
+            disposable_domains,
+            block_role_addresses: settings.block_role_addresses,
+            allowed_domains: parse_domains(settings.allowed_domains.iter().map(String::as_str))?,
+            denied_domains: parse_domains(settings.denied_domains.iter().map(String::as_str))?,
+            local_part: settings.local_part,
+        })
+    }
+
+    /// Rewrite `email` the way it is stored, see `LocalPartHandling`.
+    pub fn normalize(&self, email: SubscriberEmail) -> SubscriberEmail {
+        email.normalize_local_part(self.local_part)
+    }
+
+    /// Check that `email` can subscribe.
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        let domain = email.domain();
+        let rejected = || format!("{} can't be used to subscribe.", email);
+        if matches_any(domain, &self.denied_domains) {
+            return Err(rejected());
+        }
+        let allowed = matches_any(domain, &self.allowed_domains);
+        if !self.allowed_domains.is_empty() && !allowed {
+            return Err(rejected());
+        }
+        // An allowed domain is trusted even if it looks disposable
+        if !allowed && matches_any(domain, &self.disposable_domains) {
+            return Err(format!(
+                "{} is a disposable address, please use a permanent one.",
+                email
+            ));
+        }
+        if self.block_role_addresses && is_role_address(email.local_part()) {
+            return Err(format!(
+                "{} is a shared mailbox, please use a personal address.",
+                email
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Normalized domains, skipping blank lines and `#` comments.
+fn parse_domains<'a>(
+    lines: impl Iterator<Item = &'a str>,
+) -> Result<HashSet<String>, anyhow::Error> {
+    lines
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| normalize_domain(line).map_err(anyhow::Error::msg))
+        .collect()
+}
+
+/// Whether `domain` is one of `domains` or a subdomain of one.
+fn matches_any(domain: &str, domains: &HashSet<String>) -> bool {
+    let mut parent = domain;
+    loop {
+        if domains.contains(parent) {
+            return true;
+        }
+        match parent.split_once('.') {
+            Some((_, rest)) => parent = rest,
+            None => return false,
+        }
+    }
+}
+
+fn is_role_address(local_part: &str) -> bool {
+    // `postmaster+tag@` is still the postmaster
+    let mailbox = local_part.split('+').next().unwrap_or(local_part);
+    ROLE_ADDRESSES.contains(&mailbox.to_lowercase().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+
+    fn policy(allowed: &[&str], denied: &[&str]) -> EmailPolicy {
+        EmailPolicy {
+            disposable_domains: parse_domains(["# comment", "", "Mailinator.com"].into_iter())
+                .unwrap(),
+            block_role_addresses: true,
+            allowed_domains: parse_domains(allowed.iter().copied()).unwrap(),
+            denied_domains: parse_domains(denied.iter().copied()).unwrap(),
+            local_part: LocalPartHandling::default(),
+        }
+    }
+
+    fn check(policy: &EmailPolicy, email: &str) -> Result<(), String> {
+        policy.check(&email.parse().unwrap())
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = policy(&[], &[]);
+        assert_err!(check(&policy, "ursula@mailinator.com"));
+        assert_err!(check(&policy, "ursula@eu.MAILINATOR.com"));
+        assert_ok!(check(&policy, "ursula@notmailinator.com"));
+    }
+
+    #[test]
+    fn role_addresses_are_rejected_when_blocked() {
+        let mut policy = policy(&[], &[]);
+        assert_err!(check(&policy, "PostMaster@example.com"));
+        assert_err!(check(&policy, "abuse+reports@example.com"));
+        assert_ok!(check(&policy, "ursula@example.com"));
+        policy.block_role_addresses = false;
+        assert_ok!(check(&policy, "postmaster@example.com"));
+    }
+
+    #[test]
+    fn allowed_domains_are_the_only_ones_accepted() {
+        let policy = policy(&["example.com", "bücher.example"], &[]);
+        assert_ok!(check(&policy, "ursula@example.com"));
+        assert_ok!(check(&policy, "ursula@xn--bcher-kva.example"));
+        assert_err!(check(&policy, "ursula@gmail.com"));
+    }
+
+    #[test]
+    fn denied_domains_are_rejected_even_when_allowed() {
+        let policy = policy(&["example.com"], &["spam.example.com", "gmail.com"]);
+        assert_err!(check(&policy, "ursula@gmail.com"));
+        assert_err!(check(&policy, "ursula@spam.example.com"));
+        assert_ok!(check(&policy, "ursula@example.com"));
+    }
+}
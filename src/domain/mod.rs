@@ -1,11 +1,13 @@
+mod email_policy;
 mod new_subscriber;
 mod newsletter_issue;
 mod subscriber_email;
 mod subscriber_name;
 mod subscription_token;
 
+pub use email_policy::EmailPolicy;
 pub use new_subscriber::NewSubscriber;
 pub use newsletter_issue::NewsletterIssue;
-pub use subscriber_email::SubscriberEmail;
+pub use subscriber_email::{LocalPartHandling, SubscriberEmail};
 pub use subscriber_name::SubscriberName;
 pub use subscription_token::SubscriptionToken;
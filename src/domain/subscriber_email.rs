@@ -1,9 +1,58 @@
 use std::str::FromStr;
 use validator::validate_email;
 
+/// How the local part of addresses is rewritten. Mail servers may treat its case and
+/// `+tag` suffixes as significant, so it is kept as is by default.
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+pub struct LocalPartHandling {
+    #[serde(default)]
+    pub lowercase: bool,
+    /// Drop the `+tag` of `ursula+news@example.com`
+    #[serde(default)]
+    pub strip_subaddress: bool,
+}
+
 #[derive(Debug)]
 pub struct SubscriberEmail(String);
 
+impl SubscriberEmail {
+    pub fn local_part(&self) -> &str {
+        self.split().0
+    }
+
+    /// The domain, lowercase and in its ASCII form.
+    pub fn domain(&self) -> &str {
+        self.split().1
+    }
+
+    pub fn normalize_local_part(self, handling: LocalPartHandling) -> Self {
+        let (local_part, domain) = self.split();
+        let mut local_part = local_part.to_owned();
+        if handling.strip_subaddress {
+            if let Some((mailbox, _)) = local_part.split_once('+') {
+                local_part = mailbox.to_owned();
+            }
+        }
+        if handling.lowercase {
+            local_part = local_part.to_lowercase();
+        }
+        Self(format!("{}@{}", local_part, domain))
+    }
+
+    fn split(&self) -> (&str, &str) {
+        self.0
+            .rsplit_once('@')
+            .expect("A subscriber email always has a domain.")
+    }
+}
+
+/// Lowercase `domain` and convert Unicode labels to ASCII (IDNA), so that the different
+/// spellings of a domain compare equal.
+pub fn normalize_domain(domain: &str) -> Result<String, String> {
+    idna::domain_to_ascii(domain.trim().trim_end_matches('.'))
+        .map_err(|_| format!("{} is not a valid email domain.", domain))
+}
+
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
         &self.0
@@ -14,14 +63,17 @@ impl FromStr for SubscriberEmail {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.trim().is_empty() {
+        let s = s.trim();
+        if s.is_empty() {
             return Err("Subscriber email can't be empty.".to_string());
         }
-        if validate_email(s) {
-            Ok(Self(s.to_owned()))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+        let invalid = || format!("{} is not a valid subscriber email.", s);
+        if !validate_email(s) {
+            return Err(invalid());
         }
+        let (local_part, domain) = s.rsplit_once('@').ok_or_else(invalid)?;
+        let domain = normalize_domain(domain).map_err(|_| invalid())?;
+        Ok(Self(format!("{}@{}", local_part, domain)))
     }
 }
 
@@ -67,6 +119,36 @@ mod tests {
         assert_err!(SubscriberEmail::from_str(email));
     }
 
+    #[test]
+    fn domains_are_lowercased_and_converted_to_ascii() {
+        let email = SubscriberEmail::from_str("Ursula@Bücher.Example").unwrap();
+        assert_eq!(email.as_ref(), "Ursula@xn--bcher-kva.example");
+        assert_eq!(email.local_part(), "Ursula");
+        assert_eq!(email.domain(), "xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn surrounding_spaces_are_trimmed() {
+        let email = SubscriberEmail::from_str("  ursula@example.com\n").unwrap();
+        assert_eq!(email.as_ref(), "ursula@example.com");
+    }
+
+    #[test]
+    fn local_parts_are_kept_unless_asked_otherwise() {
+        let email = || SubscriberEmail::from_str("Ursula+News@Example.com").unwrap();
+        let normalize = |lowercase, strip_subaddress| {
+            let handling = LocalPartHandling {
+                lowercase,
+                strip_subaddress,
+            };
+            email().normalize_local_part(handling).to_string()
+        };
+        assert_eq!(normalize(false, false), "Ursula+News@example.com");
+        assert_eq!(normalize(true, false), "ursula+news@example.com");
+        assert_eq!(normalize(false, true), "Ursula@example.com");
+        assert_eq!(normalize(true, true), "ursula@example.com");
+    }
+
     #[quickcheck_macros::quickcheck]
     fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::from_str(&valid_email.0).is_ok()
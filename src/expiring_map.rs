@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The map isn't swept before it holds this many entries.
+const MIN_SWEEP_LEN: usize = 64;
+
+/// Entries with an expiration, kept in the process memory by the `Memory` backends.
+/// Expired entries are ignored when read, and swept in a batch once the map has doubled
+/// since the last sweep, so that entries never read again don't pile up and inserting
+/// stays O(1) amortized.
+pub struct ExpiringMap<V> {
+    entries: HashMap<String, (V, Instant)>,
+    /// How many entries were left after the last sweep
+    swept_len: usize,
+}
+
+impl<V> Default for ExpiringMap<V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            swept_len: 0,
+        }
+    }
+}
+
+impl<V> ExpiringMap<V> {
+    pub fn get(&self, key: &str) -> Option<&V> {
+        match self.entries.get(key) {
+            Some((value, expires_at)) if *expires_at > Instant::now() => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        match self.entries.get_mut(key) {
+            Some((value, expires_at)) if *expires_at > Instant::now() => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, key: String, value: V, ttl: Duration) {
+        let now = Instant::now();
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.next_sweep_len() {
+            self.entries.retain(|_, (_, expires_at)| *expires_at > now);
+            self.swept_len = self.entries.len();
+        }
+        self.entries.insert(key, (value, now + ttl));
+    }
+
+    /// Make an entry that didn't expire yet last `ttl` from now.
+    pub fn expire(&mut self, key: &str, ttl: Duration) {
+        if let Some((_, expires_at)) = self.entries.get_mut(key) {
+            if *expires_at > Instant::now() {
+                *expires_at = Instant::now() + ttl;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+
+    fn next_sweep_len(&self) -> usize {
+        (2 * self.swept_len).max(MIN_SWEEP_LEN)
+    }
+}
+
+impl ExpiringMap<u32> {
+    /// Add one to the counter at `key`. A counter that doesn't exist or expired starts again
+    /// and lasts `ttl`.
+    pub fn increment(&mut self, key: &str, ttl: Duration) -> u32 {
+        match self.get_mut(key) {
+            Some(counter) => {
+                *counter += 1;
+                *counter
+            }
+            None => {
+                self.insert(key.to_owned(), 1, ttl);
+                1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_entries_are_ignored() {
+        let mut map = ExpiringMap::default();
+        map.insert("current".into(), 1, Duration::from_secs(60));
+        map.insert("expired".into(), 2, Duration::ZERO);
+        assert_eq!(map.get("current"), Some(&1));
+        assert_eq!(map.get("expired"), None);
+        assert_eq!(map.increment("expired", Duration::from_secs(60)), 1);
+        assert_eq!(map.increment("expired", Duration::from_secs(60)), 2);
+    }
+
+    #[test]
+    fn expired_entries_are_swept_once_the_map_has_doubled() {
+        let mut map = ExpiringMap::default();
+        for i in 0..MIN_SWEEP_LEN {
+            map.insert(format!("expired {}", i), 0, Duration::ZERO);
+        }
+        assert_eq!(map.entries.len(), MIN_SWEEP_LEN);
+        map.insert("current".into(), 0, Duration::from_secs(60));
+        assert_eq!(map.entries.len(), 1);
+
+        // Live entries are kept, and not scanned again before the map has doubled
+        for i in 1..MIN_SWEEP_LEN {
+            map.insert(format!("current {}", i), 0, Duration::from_secs(60));
+        }
+        map.insert("expired".into(), 0, Duration::ZERO);
+        assert_eq!(map.entries.len(), MIN_SWEEP_LEN + 1);
+        for i in MIN_SWEEP_LEN..2 * MIN_SWEEP_LEN - 1 {
+            map.insert(format!("current {}", i), 0, Duration::from_secs(60));
+        }
+        assert_eq!(map.entries.len(), 2 * MIN_SWEEP_LEN);
+        map.insert("last".into(), 0, Duration::from_secs(60));
+        assert_eq!(map.entries.len(), 2 * MIN_SWEEP_LEN);
+        assert_eq!(map.get("expired"), None);
+    }
+}
@@ -0,0 +1,93 @@
+use super::IDEMPOTENCY_KEY_FIELD;
+use crate::authentication::CSRF_TOKEN_FIELD;
+use actix_web::http::Method;
+use sha2::{Digest, Sha256};
+
+/// Identify what a request asks for, so that a key reused for something else is caught.
+/// Bodies are canonicalized first: JSON objects don't depend on the order of their fields,
+/// and forms don't depend on the order of their fields nor on their idempotency key and
+/// CSRF token.
+pub fn request_fingerprint(
+    method: &Method,
+    path: &str,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b" ");
+    hasher.update(path);
+    hasher.update(b"\n");
+    hasher.update(canonical_body(content_type.unwrap_or_default(), body));
+    hex::encode(hasher.finalize())
+}
+
+fn canonical_body(content_type: &str, body: &[u8]) -> Vec<u8> {
+    if content_type.starts_with("application/json") {
+        // Maps are sorted by key when parsed into a `Value`
+        if let Ok(value) = serde_json::from_slice::<serde_json::Value>(body) {
+            return value.to_string().into_bytes();
+        }
+    } else if content_type.starts_with("application/x-www-form-urlencoded") {
+        if let Ok(mut fields) = serde_urlencoded::from_bytes::<Vec<(String, String)>>(body) {
+            fields.retain(|(name, _)| name != IDEMPOTENCY_KEY_FIELD && name != CSRF_TOKEN_FIELD);
+            fields.sort();
+            if let Ok(form) = serde_urlencoded::to_string(fields) {
+                return form.into_bytes();
+            }
+        }
+    }
+    body.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::request_fingerprint;
+    use actix_web::http::Method;
+
+    const JSON: Option<&str> = Some("application/json");
+    const FORM: Option<&str> = Some("application/x-www-form-urlencoded");
+
+    #[test]
+    fn json_fingerprints_dont_depend_on_the_order_of_fields() {
+        let a = request_fingerprint(&Method::POST, "/api/v1/issues", JSON, br#"{"a":1,"b":2}"#);
+        let b = request_fingerprint(
+            &Method::POST,
+            "/api/v1/issues",
+            JSON,
+            br#"{ "b": 2, "a": 1 }"#,
+        );
+        let c = request_fingerprint(&Method::POST, "/api/v1/issues", JSON, br#"{"a":1,"b":3}"#);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn form_fingerprints_ignore_the_key_and_csrf_token() {
+        let a = request_fingerprint(
+            &Method::POST,
+            "/subscriptions",
+            FORM,
+            b"name=le%20guin&idempotency_key=1&csrf_token=a",
+        );
+        let b = request_fingerprint(
+            &Method::POST,
+            "/subscriptions",
+            FORM,
+            b"csrf_token=b&name=le+guin",
+        );
+        let c = request_fingerprint(&Method::POST, "/subscriptions", FORM, b"name=tolkien");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn fingerprints_depend_on_the_method_and_path() {
+        let body = br#"{"a":1}"#;
+        let post = request_fingerprint(&Method::POST, "/api/v1/issues", JSON, body);
+        let put = request_fingerprint(&Method::PUT, "/api/v1/issues", JSON, body);
+        let other = request_fingerprint(&Method::POST, "/api/v1/subscriptions", JSON, body);
+        assert_ne!(post, put);
+        assert_ne!(post, other);
+    }
+}
@@ -1,3 +1,5 @@
+use uuid::Uuid;
+
 #[derive(Debug)]
 pub struct IdempotencyKey(String);
 
@@ -27,3 +29,22 @@ impl AsRef<str> for IdempotencyKey {
         &self.0
     }
 }
+
+/// Who sent the key, the same key can be used by different owners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyKeyOwner {
+    User(Uuid),
+    ApiToken(Uuid),
+    /// Anonymous requests are told apart by client IP
+    Client(String),
+}
+
+impl std::fmt::Display for IdempotencyKeyOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdempotencyKeyOwner::User(user_id) => write!(f, "user:{}", user_id),
+            IdempotencyKeyOwner::ApiToken(token_id) => write!(f, "api_token:{}", token_id),
+            IdempotencyKeyOwner::Client(ip) => write!(f, "client:{}", ip),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use super::{
+    release_key, renew_lease, request_fingerprint, save_response, try_processing, IdempotencyKey,
+    IdempotencyKeyOwner, IdempotencyStore, NextAction,
+};
+use crate::{
+    audit_log::ClientIp,
+    authentication::UserId,
+    configuration::IdempotencySettings,
+    routes::ApiError,
+    utils::{e500, form_field, peek_body},
+};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderValue, CONTENT_TYPE, RETRY_AFTER},
+    web, HttpMessage, HttpResponse, ResponseError,
+};
+use actix_web_lab::middleware::Next;
+use std::{future::Future, time::Duration};
+use uuid::Uuid;
+
+/// Header carrying the key, for clients that don't submit forms.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Form field carrying the key, for the HTML forms.
+pub const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+
+/// How `idempotent` handles the requests of a route, registered with `app_data` on the
+/// same resource or scope as the middleware.
+/// The keys are kept in the `IdempotencyStore` registered for the route.
+#[derive(Clone, Copy, Default)]
+pub struct IdempotencyConfig {
+    required: bool,
+    on_replay: Option<fn()>,
+    retention: Option<Duration>,
+}
+
+impl IdempotencyConfig {
+    /// Reject the requests without a key, instead of processing them as usual.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Called before a saved response is sent again, e.g. to send its flash messages.
+    pub fn on_replay(mut self, on_replay: fn()) -> Self {
+        self.on_replay = Some(on_replay);
+        self
+    }
+
+    /// Keep the keys for `retention` instead of the configured `expiration_secs`.
+    pub fn retain_for(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+}
+
+/// Process each key once per user, API token or anonymous client, and answer the requests
+/// reusing it with the saved response.
+/// Failed requests are not saved, so they can be retried with the same key.
+/// A request arriving while the key is in use waits for the first one to complete, and gets
+/// a 409 asking to retry if it takes longer than the configured timeout.
+/// Reusing a key for a different request is rejected with a 422.
+pub async fn idempotent(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if req.method().is_safe() {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+    let config = req
+        .app_data::<IdempotencyConfig>()
+        .copied()
+        .unwrap_or_default();
+    let body = peek_body(&mut req).await?;
+    let idempotency_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(header) => Some(header.to_str().unwrap_or_default().to_owned()),
+        None => form_field(&body, IDEMPOTENCY_KEY_FIELD),
+    };
+    let idempotency_key: IdempotencyKey = match idempotency_key {
+        Some(key) => match key.try_into() {
+            Ok(key) => key,
+            Err(e) => return Ok(reject(req, e)),
+        },
+        None if config.required => {
+            let e = anyhow::anyhow!("The idempotency key is missing");
+            return Ok(reject(req, e));
+        }
+        None => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+    };
+    let owner = key_owner(&mut req).await?;
+    let fingerprint = request_fingerprint(
+        req.method(),
+        req.path(),
+        req.headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        &body,
+    );
+    let store = req
+        .app_data::<web::Data<IdempotencyStore>>()
+        .expect("The idempotency store is not registered.")
+        .clone();
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .expect("The idempotency settings are not registered.");
+    let retention = config.retention.unwrap_or_else(|| settings.expiration());
+    let lease = settings.processing_timeout();
+    let wait_timeout = settings.wait_timeout();
+    let lease_token = match try_processing(
+        &store,
+        &idempotency_key,
+        &owner,
+        &fingerprint,
+        retention,
+        lease,
+        wait_timeout,
+    )
+    .await
+    .map_err(|e| unexpected(req.path(), e))?
+    {
+        NextAction::StartProcessing { lease_token } => lease_token,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            if let Some(on_replay) = config.on_replay {
+                on_replay();
+            }
+            return Ok(req.into_response(saved_response).map_into_right_body());
+        }
+        NextAction::InProgress => return Ok(in_progress(req)),
+        NextAction::KeyReused => return Ok(key_reused(req)),
+    };
+    let path = req.path().to_owned();
+    let processing = next.call(req);
+    let response = match hold_key(
+        &store,
+        &idempotency_key,
+        &owner,
+        lease,
+        lease_token,
+        processing,
+    )
+    .await
+    {
+        Ok(response)
+            if response.response().error().is_none() && !response.status().is_server_error() =>
+        {
+            response
+        }
+        failed => {
+            release_key(&store, &idempotency_key, &owner, lease_token)
+                .await
+                .map_err(|e| unexpected(&path, e))?;
+            return failed.map(ServiceResponse::map_into_left_body);
+        }
+    };
+    let (request, response) = response.into_parts();
+    let response = save_response(
+        &store,
+        &idempotency_key,
+        &owner,
+        &fingerprint,
+        lease_token,
+        response.map_into_boxed_body(),
+    )
+    .await
+    .map_err(|e| unexpected(request.path(), e))?;
+    Ok(ServiceResponse::new(request, response).map_into_right_body())
+}
+
+/// Renew the lease on the key until `processing` completes. If the request is dropped or
+/// the server dies, the lease runs out and the key can be taken over by a retry.
+async fn hold_key<F: Future>(
+    store: &IdempotencyStore,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+    lease: Duration,
+    lease_token: Uuid,
+    processing: F,
+) -> F::Output {
+    let mut processing = Box::pin(processing);
+    loop {
+        tokio::select! {
+            output = &mut processing => return output,
+            _ = tokio::time::sleep(lease / 3) => {
+                if let Err(e) = renew_lease(store, idempotency_key, owner, lease, lease_token).await {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to renew the lease on the idempotency key."
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn key_owner(req: &mut ServiceRequest) -> Result<IdempotencyKeyOwner, actix_web::Error> {
+    let user_id = req.extensions().get::<UserId>().copied();
+    let owner = match user_id {
+        Some(user_id) => match user_id.api_token_id() {
+            Some(token_id) => IdempotencyKeyOwner::ApiToken(token_id),
+            None => IdempotencyKeyOwner::User(*user_id),
+        },
+        None => IdempotencyKeyOwner::Client(req.extract::<ClientIp>().await?.0),
+    };
+    Ok(owner)
+}
+
+fn is_api_request(path: &str) -> bool {
+    path.starts_with("/api/")
+}
+
+fn reject<B>(req: ServiceRequest, e: anyhow::Error) -> ServiceResponse<EitherBody<B>> {
+    let response = if is_api_request(req.path()) {
+        ApiError::Validation(e.to_string()).error_response()
+    } else {
+        HttpResponse::BadRequest().body(e.to_string())
+    };
+    req.into_response(response).map_into_right_body()
+}
+
+fn in_progress<B>(req: ServiceRequest) -> ServiceResponse<EitherBody<B>> {
+    let mut response = if is_api_request(req.path()) {
+        ApiError::RequestInProgress.error_response()
+    } else {
+        HttpResponse::Conflict().body(ApiError::RequestInProgress.to_string())
+    };
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+    req.into_response(response).map_into_right_body()
+}
+
+fn key_reused<B>(req: ServiceRequest) -> ServiceResponse<EitherBody<B>> {
+    let response = if is_api_request(req.path()) {
+        ApiError::IdempotencyKeyReused.error_response()
+    } else {
+        HttpResponse::UnprocessableEntity().body(ApiError::IdempotencyKeyReused.to_string())
+    };
+    req.into_response(response).map_into_right_body()
+}
+
+fn unexpected(path: &str, e: anyhow::Error) -> actix_web::Error {
+    if is_api_request(path) {
+        ApiError::Unexpected(e).into()
+    } else {
+        e500(e).into()
+    }
+}
@@ -1,4 +1,16 @@
+mod fingerprint;
 mod key;
+mod middleware;
 mod persistence;
-pub use key::IdempotencyKey;
-pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
+mod postgres;
+mod redis;
+pub use fingerprint::request_fingerprint;
+pub use key::{IdempotencyKey, IdempotencyKeyOwner};
+pub use middleware::{
+    idempotent, IdempotencyConfig, IDEMPOTENCY_KEY_FIELD, IDEMPOTENCY_KEY_HEADER,
+};
+pub use persistence::{
+    get_saved_response, release_key, renew_lease, save_response, try_processing, IdempotencyStore,
+    NextAction,
+};
+pub use redis::RedisIdempotencyStore;
@@ -1,132 +1,248 @@
-use super::IdempotencyKey;
+use super::redis::RedisIdempotencyStore;
+use super::{postgres, IdempotencyKey, IdempotencyKeyOwner};
+use crate::configuration::IdempotencyBackend;
 use actix_web::body::to_bytes;
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
-use sqlx::postgres::PgHasArrayType;
-use sqlx::{PgPool, Postgres, Transaction};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
-#[derive(Debug, sqlx::Type)]
-#[sqlx(type_name = "header_pair")]
-struct HeaderPairRecord {
-    name: String,
-    value: Vec<u8>,
+/// How often a request checks whether the one holding its key is done.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where the keys of a route are kept, registered with `app_data` like `IdempotencyConfig`.
+#[derive(Clone)]
+pub enum IdempotencyStore {
+    Postgres(PgPool),
+    Redis(RedisIdempotencyStore),
 }
 
-impl PgHasArrayType for HeaderPairRecord {
-    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
-        sqlx::postgres::PgTypeInfo::with_name("_header_pair")
+impl IdempotencyStore {
+    pub async fn new(
+        backend: IdempotencyBackend,
+        redis_uri: &Secret<String>,
+        pool: &PgPool,
+        redis_key_prefix: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let store = match backend {
+            IdempotencyBackend::Postgres => Self::Postgres(pool.clone()),
+            IdempotencyBackend::Redis => {
+                Self::Redis(RedisIdempotencyStore::new(redis_uri, redis_key_prefix).await?)
+            }
+        };
+        Ok(store)
     }
 }
 
-pub async fn get_saved_response(
-    pool: &PgPool,
-    idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Result<Option<HttpResponse>, anyhow::Error> {
-    let saved_response = sqlx::query!(
-        r#"
-		SELECT
-			response_status_code as "response_status_code!",
-			response_headers as "response_headers!: Vec<HeaderPairRecord>",
-			response_body as "response_body!"
-		FROM idempotency
-		WHERE user_id = $1 AND idempotency_key = $2
-		"#,
-        user_id,
-        idempotency_key.as_ref()
-    )
-    .fetch_optional(pool)
-    .await?;
-    if let Some(r) = saved_response {
-        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
-        let mut response = HttpResponse::build(status_code);
-        for HeaderPairRecord { name, value } in r.response_headers {
+/// A request that claimed a key.
+pub(super) struct SavedRequest {
+    /// `None` for the keys saved before requests were fingerprinted
+    pub fingerprint: Option<String>,
+    /// `None` while the request is processed
+    pub response: Option<SavedResponse>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct SavedResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
+
+impl SavedResponse {
+    fn into_http_response(self) -> Result<HttpResponse, anyhow::Error> {
+        let mut response = HttpResponse::build(StatusCode::from_u16(self.status_code)?);
+        for (name, value) in self.headers {
             response.append_header((name, value));
         }
-        Ok(Some(response.body(r.response_body)))
-    } else {
-        Ok(None)
+        Ok(response.body(self.body))
     }
 }
 
+async fn get_saved_request(
+    store: &IdempotencyStore,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+) -> Result<Option<SavedRequest>, anyhow::Error> {
+    match store {
+        IdempotencyStore::Postgres(pool) => {
+            postgres::get_saved_request(pool, idempotency_key, owner).await
+        }
+        IdempotencyStore::Redis(redis) => redis.get_saved_request(idempotency_key, owner).await,
+    }
+}
+
+/// Returns `None` if there is no response saved for the key yet.
+pub async fn get_saved_response(
+    store: &IdempotencyStore,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    get_saved_request(store, idempotency_key, owner)
+        .await?
+        .and_then(|r| r.response)
+        .map(SavedResponse::into_http_response)
+        .transpose()
+}
+
+/// The response is saved only if the key wasn't taken over since `lease_token` claimed it.
 pub async fn save_response(
-    mut transaction: Transaction<'static, Postgres>,
+    store: &IdempotencyStore,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    owner: &IdempotencyKeyOwner,
+    request_fingerprint: &str,
+    lease_token: Uuid,
     http_response: HttpResponse,
 ) -> Result<HttpResponse, anyhow::Error> {
     let (response_head, body) = http_response.into_parts();
     let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
-    let status_code = response_head.status().as_u16() as i16;
-    let headers = {
-        let mut h = Vec::with_capacity(response_head.headers().len());
-        for (name, value) in response_head.headers().iter() {
-            let name = name.as_str().to_owned();
-            let value = value.as_bytes().to_owned();
-            h.push(HeaderPairRecord { name, value });
-        }
-        h
+    let response = SavedResponse {
+        status_code: response_head.status().as_u16(),
+        headers: response_head
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str().to_owned(), value.as_bytes().to_owned()))
+            .collect(),
+        body: body.to_vec(),
     };
-
-    sqlx::query_unchecked!(
-        r#"
-		UPDATE idempotency
-        SET
-			response_status_code = $3,
-			response_headers = $4,
-			response_body = $5
-        WHERE
-			user_id = $1 AND
-			idempotency_key = $2
-		"#,
-        user_id,
-        idempotency_key.as_ref(),
-        status_code,
-        headers,
-        body.as_ref()
-    )
-    .execute(&mut transaction)
-    .await?;
-    transaction.commit().await?;
+    match store {
+        IdempotencyStore::Postgres(pool) => {
+            postgres::save_response(pool, idempotency_key, owner, lease_token, &response).await?
+        }
+        IdempotencyStore::Redis(redis) => {
+            redis
+                .save_response(
+                    idempotency_key,
+                    owner,
+                    request_fingerprint,
+                    lease_token,
+                    response,
+                )
+                .await?
+        }
+    }
     let http_response = response_head.set_body(body).map_into_boxed_body();
     Ok(http_response)
 }
 
-#[allow(clippy::large_enum_variant)]
+/// Give the key back when its request failed, so that it can be retried.
+/// Nothing is done if the key was taken over since `lease_token` claimed it.
+pub async fn release_key(
+    store: &IdempotencyStore,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+    lease_token: Uuid,
+) -> Result<(), anyhow::Error> {
+    match store {
+        IdempotencyStore::Postgres(pool) => {
+            postgres::release_key(pool, idempotency_key, owner, lease_token).await
+        }
+        IdempotencyStore::Redis(redis) => {
+            redis.release_key(idempotency_key, owner, lease_token).await
+        }
+    }
+}
+
+/// Keep the key of a request that is still processed, see `try_processing`.
+/// Nothing is done if the key was taken over since `lease_token` claimed it.
+pub async fn renew_lease(
+    store: &IdempotencyStore,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+    lease: Duration,
+    lease_token: Uuid,
+) -> Result<(), anyhow::Error> {
+    match store {
+        IdempotencyStore::Postgres(pool) => {
+            postgres::renew_lease(pool, idempotency_key, owner, lease, lease_token).await
+        }
+        IdempotencyStore::Redis(redis) => {
+            redis
+                .renew_lease(idempotency_key, owner, lease, lease_token)
+                .await
+        }
+    }
+}
+
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    /// The key was claimed, `lease_token` identifies the claim to renew, save or release it
+    StartProcessing {
+        lease_token: Uuid,
+    },
     ReturnSavedResponse(HttpResponse),
+    /// Another request with the same key was still being processed when we stopped waiting
+    InProgress,
+    /// The key was already used for a different request
+    KeyReused,
 }
 
+/// Claim the key for the request with this fingerprint, keeping it for `retention`, or wait
+/// up to `wait_timeout` for the request that holds it to save its response.
+/// The claim lasts for `lease` unless renewed with `renew_lease`, so that the key of a
+/// request that never completed can be taken over.
 pub async fn try_processing(
-    pool: &PgPool,
+    store: &IdempotencyStore,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    owner: &IdempotencyKeyOwner,
+    request_fingerprint: &str,
+    retention: Duration,
+    lease: Duration,
+    wait_timeout: Duration,
 ) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
-    let n_inserted_rows = sqlx::query!(
-        r#"
-        INSERT INTO idempotency (
-            user_id,
-            idempotency_key,
-            created_at
-        )
-        VALUES ($1, $2, now())
-        ON CONFLICT DO NOTHING
-        "#,
-        user_id,
-        idempotency_key.as_ref()
-    )
-    .execute(&mut transaction)
-    .await?
-    .rows_affected();
-    if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
-    } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
-        Ok(NextAction::ReturnSavedResponse(saved_response))
+    let deadline = Instant::now() + wait_timeout;
+    let lease_token = Uuid::new_v4();
+    loop {
+        let claimed = match store {
+            IdempotencyStore::Postgres(pool) => {
+                postgres::claim_key(
+                    pool,
+                    idempotency_key,
+                    owner,
+                    request_fingerprint,
+                    retention,
+                    lease,
+                    lease_token,
+                )
+                .await?
+            }
+            IdempotencyStore::Redis(redis) => {
+                redis
+                    .claim_key(
+                        idempotency_key,
+                        owner,
+                        request_fingerprint,
+                        retention,
+                        lease,
+                        lease_token,
+                    )
+                    .await?
+            }
+        };
+        if claimed {
+            return Ok(NextAction::StartProcessing { lease_token });
+        }
+        match get_saved_request(store, idempotency_key, owner).await? {
+            Some(SavedRequest {
+                fingerprint: Some(fingerprint),
+                ..
+            }) if fingerprint != request_fingerprint => return Ok(NextAction::KeyReused),
+            Some(SavedRequest {
+                response: Some(saved_response),
+                ..
+            }) => {
+                return Ok(NextAction::ReturnSavedResponse(
+                    saved_response.into_http_response()?,
+                ))
+            }
+            _ => {}
+        }
+        // The key is either in progress, or was released or abandoned since we tried to claim it
+        if Instant::now() >= deadline {
+            return Ok(NextAction::InProgress);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
     }
 }
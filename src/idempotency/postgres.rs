@@ -0,0 +1,219 @@
+use super::persistence::{SavedRequest, SavedResponse};
+use super::{IdempotencyKey, IdempotencyKeyOwner};
+use sqlx::postgres::{types::PgInterval, PgHasArrayType};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+impl PgHasArrayType for HeaderPairRecord {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_header_pair")
+    }
+}
+
+/// Returns `false` if the key was already claimed.
+/// Expired keys, which the expiration worker didn't remove yet, are claimed again, and so
+/// are those whose request stopped renewing its lease before saving a response.
+/// `lease_token` tells the request holding the key from the one it took the key over from.
+pub(super) async fn claim_key(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+    request_fingerprint: &str,
+    retention: Duration,
+    lease: Duration,
+    lease_token: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let retention = to_interval(retention)?;
+    let lease = to_interval(lease)?;
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (
+            key_owner,
+            idempotency_key,
+            request_fingerprint,
+            created_at,
+            expires_at,
+            locked_until,
+            lease_token
+        )
+        VALUES ($1, $2, $3, now(), now() + $4, now() + $5, $6)
+        ON CONFLICT (key_owner, idempotency_key) DO UPDATE
+        SET
+            request_fingerprint = EXCLUDED.request_fingerprint,
+            created_at = EXCLUDED.created_at,
+            expires_at = EXCLUDED.expires_at,
+            locked_until = EXCLUDED.locked_until,
+            lease_token = EXCLUDED.lease_token
+        WHERE
+            idempotency.expires_at <= now() OR (
+                idempotency.response_status_code IS NULL AND
+                idempotency.locked_until <= now()
+            )
+        "#,
+        owner.to_string(),
+        idempotency_key.as_ref(),
+        request_fingerprint,
+        retention,
+        lease,
+        lease_token
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows > 0)
+}
+
+/// Keep the key of a request that is still processed for another `lease`, unless another
+/// request took it over.
+pub(super) async fn renew_lease(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+    lease: Duration,
+    lease_token: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET locked_until = now() + $3
+        WHERE
+            key_owner = $1 AND
+            idempotency_key = $2 AND
+            lease_token = $4 AND
+            response_status_code IS NULL
+        "#,
+        owner.to_string(),
+        idempotency_key.as_ref(),
+        to_interval(lease)?,
+        lease_token
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+fn to_interval(duration: Duration) -> Result<PgInterval, anyhow::Error> {
+    duration
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("Invalid idempotency key duration: {}", e))
+}
+
+pub(super) async fn get_saved_request(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+) -> Result<Option<SavedRequest>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+		SELECT
+			request_fingerprint,
+			response_status_code,
+			response_headers as "response_headers: Vec<HeaderPairRecord>",
+			response_body
+		FROM idempotency
+		WHERE
+			key_owner = $1 AND
+			idempotency_key = $2 AND
+			-- Keys saved before `expires_at` was added are left to the expiration worker
+			(expires_at IS NULL OR expires_at > now())
+		"#,
+        owner.to_string(),
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    let r = match r {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    // The response columns stay NULL while the first request is processed
+    let response = match (r.response_status_code, r.response_headers, r.response_body) {
+        (Some(status_code), Some(headers), Some(body)) => Some(SavedResponse {
+            status_code: status_code.try_into()?,
+            headers: headers
+                .into_iter()
+                .map(|HeaderPairRecord { name, value }| (name, value))
+                .collect(),
+            body,
+        }),
+        _ => None,
+    };
+    Ok(Some(SavedRequest {
+        fingerprint: r.request_fingerprint,
+        response,
+    }))
+}
+
+/// Only the request holding the key saves its response.
+pub(super) async fn save_response(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+    lease_token: Uuid,
+    response: &SavedResponse,
+) -> Result<(), anyhow::Error> {
+    let headers: Vec<_> = response
+        .headers
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect();
+    sqlx::query_unchecked!(
+        r#"
+		UPDATE idempotency
+        SET
+			response_status_code = $3,
+			response_headers = $4,
+			response_body = $5,
+			locked_until = NULL
+        WHERE
+			key_owner = $1 AND
+			idempotency_key = $2 AND
+			lease_token = $6
+		"#,
+        owner.to_string(),
+        idempotency_key.as_ref(),
+        response.status_code as i16,
+        headers,
+        &response.body,
+        lease_token
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Remove the key, unless another request took it over.
+pub(super) async fn release_key(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    owner: &IdempotencyKeyOwner,
+    lease_token: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE
+            key_owner = $1 AND
+            idempotency_key = $2 AND
+            lease_token = $3 AND
+            response_status_code IS NULL
+        "#,
+        owner.to_string(),
+        idempotency_key.as_ref(),
+        lease_token
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use super::persistence::{SavedRequest, SavedResponse};
+use super::{IdempotencyKey, IdempotencyKeyOwner};
+use once_cell::sync::Lazy;
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Renew the lock `KEYS[1]` for `ARGV[2]` milliseconds if it still holds the lease token
+/// `ARGV[1]`.
+static RENEW_LEASE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#,
+    )
+});
+
+/// Save the entry `ARGV[2]` at `KEYS[2]` and remove the lock `KEYS[1]` if it still holds the
+/// lease token `ARGV[1]`. The entry keeps the expiration set when it was claimed.
+static SAVE_RESPONSE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    redis.call("SET", KEYS[2], ARGV[2], "XX", "KEEPTTL")
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#,
+    )
+});
+
+/// Remove all `KEYS`, the first of which is the lock, if it still holds the lease token
+/// `ARGV[1]`.
+static RELEASE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", unpack(KEYS))
+end
+return 0
+"#,
+    )
+});
+
+/// Keeps the keys in Redis, which expires them by itself.
+#[derive(Clone)]
+pub struct RedisIdempotencyStore {
+    redis: ConnectionManager,
+    key_prefix: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    fingerprint: String,
+    /// `None` while the request is processed
+    response: Option<SavedResponse>,
+}
+
+impl RedisIdempotencyStore {
+    pub async fn new(redis_uri: &Secret<String>, key_prefix: &str) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())?;
+        Ok(Self {
+            redis: ConnectionManager::new(client).await?,
+            key_prefix: key_prefix.to_owned(),
+        })
+    }
+
+    fn redis_key(&self, idempotency_key: &IdempotencyKey, owner: &IdempotencyKeyOwner) -> String {
+        // Hashed so that the owner and the key, which can both contain ':', can't run into
+        // each other
+        let key = hex::encode(Sha256::digest(idempotency_key.as_ref().as_bytes()));
+        format!("{}:{}:{}", self.key_prefix, owner, key)
+    }
+
+    /// Held while the request of the key is processed, and renewed until it completes.
+    /// It holds the lease token of the request, so that a request whose key was taken over
+    /// doesn't renew or remove the lock of the one that took it.
+    fn lock_key(&self, idempotency_key: &IdempotencyKey, owner: &IdempotencyKeyOwner) -> String {
+        format!("{}:lock", self.redis_key(idempotency_key, owner))
+    }
+
+    /// Returns `false` if the key was already claimed.
+    /// A key whose request stopped renewing its lease before saving a response is taken over.
+    pub(super) async fn claim_key(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        owner: &IdempotencyKeyOwner,
+        request_fingerprint: &str,
+        retention: Duration,
+        lease: Duration,
+        lease_token: Uuid,
+    ) -> Result<bool, anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let locked: Option<String> = redis::cmd("SET")
+            .arg(self.lock_key(idempotency_key, owner))
+            .arg(lease_token.to_string())
+            .arg("NX")
+            .arg("PX")
+            .arg(lease.as_millis() as u64)
+            .query_async(&mut redis)
+            .await?;
+        if locked.is_none() {
+            return Ok(false);
+        }
+        // Only the lock holder writes an entry without response, so a saved one is final
+        if let Some(saved_request) = self.get_saved_request(idempotency_key, owner).await? {
+            if saved_request.response.is_some() {
+                RELEASE_SCRIPT
+                    .key(self.lock_key(idempotency_key, owner))
+                    .arg(lease_token.to_string())
+                    .invoke_async::<_, ()>(&mut redis)
+                    .await?;
+                return Ok(false);
+            }
+        }
+        let entry = serde_json::to_string(&Entry {
+            fingerprint: request_fingerprint.to_owned(),
+            response: None,
+        })?;
+        redis::cmd("SET")
+            .arg(self.redis_key(idempotency_key, owner))
+            .arg(entry)
+            .arg("PX")
+            .arg(retention.as_millis() as u64)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+        Ok(true)
+    }
+
+    pub(super) async fn renew_lease(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        owner: &IdempotencyKeyOwner,
+        lease: Duration,
+        lease_token: Uuid,
+    ) -> Result<(), anyhow::Error> {
+        RENEW_LEASE_SCRIPT
+            .key(self.lock_key(idempotency_key, owner))
+            .arg(lease_token.to_string())
+            .arg(lease.as_millis() as u64)
+            .invoke_async::<_, ()>(&mut self.redis.clone())
+            .await?;
+        Ok(())
+    }
+
+    pub(super) async fn get_saved_request(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        owner: &IdempotencyKeyOwner,
+    ) -> Result<Option<SavedRequest>, anyhow::Error> {
+        let entry: Option<String> = self
+            .redis
+            .clone()
+            .get(self.redis_key(idempotency_key, owner))
+            .await?;
+        let entry = match entry {
+            Some(entry) => serde_json::from_str::<Entry>(&entry)?,
+            None => return Ok(None),
+        };
+        Ok(Some(SavedRequest {
+            fingerprint: Some(entry.fingerprint),
+            response: entry.response,
+        }))
+    }
+
+    pub(super) async fn save_response(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        owner: &IdempotencyKeyOwner,
+        request_fingerprint: &str,
+        lease_token: Uuid,
+        response: SavedResponse,
+    ) -> Result<(), anyhow::Error> {
+        let entry = serde_json::to_string(&Entry {
+            fingerprint: request_fingerprint.to_owned(),
+            response: Some(response),
+        })?;
+        SAVE_RESPONSE_SCRIPT
+            .key(self.lock_key(idempotency_key, owner))
+            .key(self.redis_key(idempotency_key, owner))
+            .arg(lease_token.to_string())
+            .arg(entry)
+            .invoke_async::<_, ()>(&mut self.redis.clone())
+            .await?;
+        Ok(())
+    }
+
+    pub(super) async fn release_key(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        owner: &IdempotencyKeyOwner,
+        lease_token: Uuid,
+    ) -> Result<(), anyhow::Error> {
+        RELEASE_SCRIPT
+            .key(self.lock_key(idempotency_key, owner))
+            .key(self.redis_key(idempotency_key, owner))
+            .arg(lease_token.to_string())
+            .invoke_async::<_, ()>(&mut self.redis.clone())
+            .await?;
+        Ok(())
+    }
+}
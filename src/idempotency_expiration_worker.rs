@@ -1,6 +1,7 @@
 use crate::{
     configuration::{IdempotencySettings, Settings},
     get_connection_pool,
+    shutdown::Shutdown,
 };
 use anyhow::Context;
 use rand::Rng;
@@ -9,41 +10,59 @@ use std::time::Duration;
 
 const MAX_RETRIES: usize = 3;
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-    worker_loop(connection_pool, configuration.idempotency).await
+    worker_loop(connection_pool, configuration.idempotency, shutdown).await
 }
 
-async fn worker_loop(pool: PgPool, settings: IdempotencySettings) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    settings: IdempotencySettings,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     let mut retries = 0;
     let frequency = settings.expiration_frequency_secs as f32;
     let expiration_interval = Duration::from_secs(settings.expiration_secs)
         .try_into()
         .unwrap();
     // When server restarts wait half the expiration time to start working
-    tokio::time::sleep(Duration::from_secs_f32(frequency / 2.0)).await;
-    loop {
+    shutdown
+        .sleep(Duration::from_secs_f32(frequency / 2.0))
+        .await;
+    while !shutdown.is_triggered() {
         if try_execute_task(&pool, &expiration_interval).await.is_err() {
             retries += 1;
             if retries < MAX_RETRIES {
-                tokio::time::sleep(Duration::from_secs_f32(add_jitter(10.0))).await;
+                shutdown
+                    .sleep(Duration::from_secs_f32(add_jitter(10.0)))
+                    .await;
                 continue;
             } else {
                 retries = 0;
             }
         }
-        tokio::time::sleep(Duration::from_secs_f32(add_jitter(frequency))).await;
+        shutdown
+            .sleep(Duration::from_secs_f32(add_jitter(frequency)))
+            .await;
     }
+    Ok(())
 }
 
 /// Adds a random jittering around `secs` of +/- 10%.
-fn add_jitter(secs: f32) -> f32 {
+pub(crate) fn add_jitter(secs: f32) -> f32 {
     let mut rng = rand::thread_rng();
     let x = secs * 0.1;
     let jitter = rng.gen_range(-x..=x);
     (secs + jitter).max(0.0)
 }
 
+/// Remove the keys kept in Postgres past their retention, Redis expires its keys by itself.
+/// `expiration_interval` is the retention of the keys stored without one.
+/// The used subscription forms are always kept in Postgres and removed here too, since this
+/// worker runs whatever the session store backend.
 #[tracing::instrument(skip_all, err)]
 pub async fn try_execute_task(
     pool: &PgPool,
@@ -52,7 +71,7 @@ pub async fn try_execute_task(
     let n = sqlx::query!(
         r#"
 		DELETE FROM idempotency
-		wHERE (created_at + $1) < now()
+		WHERE COALESCE(expires_at, created_at + $1) < now()
 		"#,
         expiration_interval
     )
@@ -60,6 +79,16 @@ pub async fn try_execute_task(
     .await
     .context("Failed to clear expired idempotency keys from database.")?
     .rows_affected();
-    tracing::info!("Removed {} expired keys.", n);
+    let used_forms =
+        sqlx::query!(r#"DELETE FROM used_subscription_forms WHERE expires_at < now()"#)
+            .execute(pool)
+            .await
+            .context("Failed to clear expired subscription forms from database.")?
+            .rows_affected();
+    tracing::info!(
+        "Removed {} expired keys and {} used subscription forms.",
+        n,
+        used_forms
+    );
     Ok(())
 }
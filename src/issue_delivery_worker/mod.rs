@@ -1,45 +1,74 @@
+mod rate_limit;
+mod wake_up;
+
 use crate::{
     configuration::{IssueDeliverySettings, Settings},
     domain::SubscriberEmail,
     email_client::EmailClient,
     error_chain_fmt, get_connection_pool,
+    shutdown::Shutdown,
 };
 use anyhow::Context;
 use chrono::Utc;
 use rand::Rng;
+pub use rate_limit::{try_acquire_send_permit, SendPermit};
 use sqlx::{PgPool, Postgres, Transaction};
 use std::{str::FromStr, time::Duration};
 use tracing::{field::display, Span};
 use uuid::Uuid;
+use wake_up::{listen_for_tasks, wait_for_tasks};
+pub use wake_up::{notify_workers, ISSUE_DELIVERY_CHANNEL};
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client()?;
-    worker_loop(connection_pool, email_client, configuration.issue_delivery).await
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.issue_delivery,
+        shutdown,
+    )
+    .await
 }
 
+/// Tasks are never interrupted: on shutdown the current one is completed and
+/// committed before the loop exits.
 async fn worker_loop(
     pool: PgPool,
     email_client: EmailClient,
     settings: IssueDeliverySettings,
+    mut shutdown: Shutdown,
 ) -> Result<(), anyhow::Error> {
-    loop {
+    let mut listener = listen_for_tasks(&pool).await;
+    while !shutdown.is_triggered() {
         match try_execute_task(&pool, &email_client, &settings).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                tokio::select! {
+                    _ = wait_for_tasks(&pool, &mut listener, &settings) => {}
+                    _ = shutdown.triggered() => {}
+                }
             }
             Err(ExecutionError::UnexpectedError(_)) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                shutdown.sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::Throttled(wait)) => {
+                shutdown.sleep(wait).await;
             }
             Err(ExecutionError::ValidationError(_)) => {}
             Ok(ExecutionOutcome::TaskCompleted) => {}
         }
     }
+    Ok(())
 }
 
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    /// The send rate limit was reached, the task was left in the queue.
+    Throttled(Duration),
 }
 
 #[derive(thiserror::Error)]
@@ -77,6 +106,13 @@ pub async fn try_execute_task(
     Span::current()
         .record("newsletter_issue_id", &display(issue_id))
         .record("subscriber_email", &display(&email));
+    if let SendPermit::Throttled(wait) = try_acquire_send_permit(pool, settings).await? {
+        transaction
+            .rollback()
+            .await
+            .context("Failed to rollback transaction.")?;
+        return Ok(ExecutionOutcome::Throttled(wait));
+    }
     let mut do_delete = true;
     let result = match SubscriberEmail::from_str(&email) {
         Ok(email) => {
@@ -236,9 +272,12 @@ async fn retry_task(
         issue_id,
         email
     )
-    .execute(transaction)
+    .execute(&mut *transaction)
     .await
     .context("Failed to set task for retry. Skipping.")?;
+    notify_workers(transaction)
+        .await
+        .context("Failed to notify workers about the retry.")?;
     tracing::info!("Issue scheduled to retry after {} milliseconds.", backoff);
     Ok(())
 }
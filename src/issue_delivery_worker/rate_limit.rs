@@ -0,0 +1,112 @@
+use crate::configuration::IssueDeliverySettings;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::{field::display, Span};
+
+pub enum SendPermit {
+    Granted,
+    /// No token is available, try again after the given duration.
+    Throttled(Duration),
+}
+
+/// A token bucket stored in the `issue_delivery_rate_limit` table, so every worker
+/// instance draws from the same budget.
+struct TokenBucket {
+    name: &'static str,
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl TokenBucket {
+    fn from_settings(settings: &IssueDeliverySettings) -> [Self; 2] {
+        let per_second = settings.max_emails_per_second.get() as f64;
+        let per_hour = settings.max_emails_per_hour.get() as f64;
+        // Buckets are always locked in this order to avoid deadlocks between workers
+        [
+            Self {
+                name: "per_second",
+                capacity: per_second,
+                refill_per_sec: per_second,
+            },
+            Self {
+                name: "per_hour",
+                capacity: per_hour,
+                refill_per_sec: per_hour / 3600.0,
+            },
+        ]
+    }
+
+    /// Time needed to get a full token starting from `tokens`.
+    fn wait_for_token(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(((1.0 - tokens) / self.refill_per_sec).max(0.0))
+    }
+}
+
+/// Take one token from each send-rate bucket, or none of them if any is empty.
+#[tracing::instrument(
+    skip_all,
+    fields(throttled=tracing::field::Empty, wait_ms=tracing::field::Empty)
+)]
+pub async fn try_acquire_send_permit(
+    pool: &PgPool,
+    settings: &IssueDeliverySettings,
+) -> Result<SendPermit, anyhow::Error> {
+    let buckets = TokenBucket::from_settings(settings);
+    let mut transaction = pool.begin().await?;
+    let mut wait = Duration::ZERO;
+    for bucket in &buckets {
+        let tokens = sqlx::query!(
+            r#"
+            INSERT INTO issue_delivery_rate_limit (bucket, tokens, updated_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT (bucket) DO UPDATE
+            SET
+                tokens = LEAST(
+                    $2,
+                    issue_delivery_rate_limit.tokens + $3 * EXTRACT(
+                        EPOCH FROM now() - issue_delivery_rate_limit.updated_at
+                    )::DOUBLE PRECISION
+                ),
+                updated_at = now()
+            RETURNING tokens
+            "#,
+            bucket.name,
+            bucket.capacity,
+            bucket.refill_per_sec
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .context("Failed to refill the send rate bucket.")?
+        .tokens;
+        wait = wait.max(bucket.wait_for_token(tokens));
+    }
+    let permit = if wait.is_zero() {
+        let names = buckets
+            .iter()
+            .map(|b| b.name.to_string())
+            .collect::<Vec<_>>();
+        sqlx::query!(
+            r#"
+            UPDATE issue_delivery_rate_limit
+            SET tokens = tokens - 1
+            WHERE bucket = ANY($1)
+            "#,
+            &names
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to take a token from the send rate buckets.")?;
+        SendPermit::Granted
+    } else {
+        Span::current().record("wait_ms", &display(wait.as_millis()));
+        tracing::info!("Send rate limit reached, throttling delivery.");
+        SendPermit::Throttled(wait)
+    };
+    Span::current().record("throttled", &display(!wait.is_zero()));
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the send rate buckets.")?;
+    Ok(permit)
+}
@@ -0,0 +1,101 @@
+use crate::configuration::IssueDeliverySettings;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgListener, PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tracing::{field::display, Span};
+
+/// Channel used to tell idle workers that the queue has new tasks.
+pub const ISSUE_DELIVERY_CHANNEL: &str = "issue_delivery_queue";
+
+/// Wake up the idle workers.
+/// Postgres only delivers the notification once `transaction` commits.
+#[tracing::instrument(skip_all)]
+pub async fn notify_workers(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_notify($1, '')")
+        .bind(ISSUE_DELIVERY_CHANNEL)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Subscribe to queue notifications.
+/// Returns `None` if we can't listen, in which case the worker only polls.
+pub async fn listen_for_tasks(pool: &PgPool) -> Option<PgListener> {
+    let listener = async {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(ISSUE_DELIVERY_CHANNEL).await?;
+        Ok::<_, sqlx::Error>(listener)
+    }
+    .await;
+    match listener {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to listen for new tasks, falling back to polling."
+            );
+            None
+        }
+    }
+}
+
+/// Wait until a task may be ready: a notification arrives, the earliest scheduled
+/// retry becomes due or the poll interval elapses, whichever happens first.
+#[tracing::instrument(skip_all, fields(timeout_ms=tracing::field::Empty))]
+pub async fn wait_for_tasks(
+    pool: &PgPool,
+    listener: &mut Option<PgListener>,
+    settings: &IssueDeliverySettings,
+) {
+    let poll_interval = settings.poll_interval();
+    let timeout = match get_next_execute_after(pool).await {
+        Ok(next_execute_after) => time_until_due(next_execute_after, poll_interval),
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Falling back to the poll interval."
+            );
+            poll_interval
+        }
+    };
+    Span::current().record("timeout_ms", &display(timeout.as_millis()));
+    match listener {
+        Some(listener) => {
+            tokio::select! {
+                notification = listener.recv() => {
+                    if let Err(e) = notification {
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            error.message = %e,
+                            "Failed to receive queue notifications."
+                        );
+                        tokio::time::sleep(timeout).await;
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {}
+            }
+        }
+        None => tokio::time::sleep(timeout).await,
+    }
+}
+
+/// Tasks due in the past are locked by other workers, so we just poll for them.
+fn time_until_due(next_execute_after: Option<DateTime<Utc>>, poll_interval: Duration) -> Duration {
+    next_execute_after
+        .and_then(|t| (t - Utc::now()).to_std().ok())
+        .map_or(poll_interval, |d| d.min(poll_interval))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_next_execute_after(pool: &PgPool) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT MIN(execute_after) as next FROM issue_delivery_queue"#)
+        .fetch_one(pool)
+        .await
+        .context("Failed to get the next scheduled task.")?;
+    Ok(row.next)
+}
@@ -1,14 +1,23 @@
 #![allow(clippy::async_yields_async)]
 
+pub mod audit_log;
 pub mod authentication;
+pub mod cli;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod expiring_map;
 pub mod idempotency;
 pub mod idempotency_expiration_worker;
 pub mod issue_delivery_worker;
+pub mod metrics;
+pub mod newsletter_issues;
+pub mod rate_limit;
 pub mod routes;
+pub mod session_expiration_worker;
 pub mod session_state;
+pub mod session_store;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
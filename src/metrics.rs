@@ -0,0 +1,50 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex};
+
+/// Counters of this instance, served on `/metrics` in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    rejected_subscriptions: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn record_rejected_subscription(&self, reason: &'static str) {
+        *self
+            .rejected_subscriptions
+            .lock()
+            .unwrap()
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP zero2prod_rejected_subscriptions_total Subscription forms taken for bots.\n\
+             # TYPE zero2prod_rejected_subscriptions_total counter\n",
+        );
+        for (reason, count) in self.rejected_subscriptions.lock().unwrap().iter() {
+            writeln!(
+                text,
+                "zero2prod_rejected_subscriptions_total{{reason=\"{}\"}} {}",
+                reason, count
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+
+    #[test]
+    fn rejections_are_counted_per_reason() {
+        let metrics = Metrics::default();
+        metrics.record_rejected_subscription("honeypot");
+        metrics.record_rejected_subscription("honeypot");
+        metrics.record_rejected_subscription("too_fast");
+        let text = metrics.render();
+        assert!(text.contains("zero2prod_rejected_subscriptions_total{reason=\"honeypot\"} 2\n"));
+        assert!(text.contains("zero2prod_rejected_subscriptions_total{reason=\"too_fast\"} 1\n"));
+    }
+}
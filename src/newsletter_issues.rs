@@ -0,0 +1,172 @@
+use crate::{domain::NewsletterIssue, issue_delivery_worker::notify_workers};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Serialize, ToSchema)]
+pub struct StoredNewsletterIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub created_at: DateTime<Utc>,
+    /// Missing while the issue is a draft
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeliveryStatus {
+    pub newsletter_issue_id: Uuid,
+    #[schema(example = "in_progress")]
+    pub status: &'static str,
+    pub published_at: Option<DateTime<Utc>>,
+    /// Confirmed subscribers when the issue was published
+    pub recipients: i32,
+    /// Emails waiting to be sent, including the ones being retried
+    pub pending: i64,
+    /// Emails that failed at least once and are waiting for a retry
+    pub retrying: i64,
+}
+
+/// Store a draft, it is not sent until `publish_newsletter_issue` is called.
+#[tracing::instrument(skip_all)]
+pub async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue: &NewsletterIssue,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        newsletter_issue_id,
+        newsletter_issue.title(),
+        newsletter_issue.text_content(),
+        newsletter_issue.html_content()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+/// Queue a draft for delivery to every confirmed subscriber.
+/// Returns `false` if there is no draft with that id, e.g. it was already published.
+#[tracing::instrument(skip(transaction))]
+pub async fn publish_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<bool> {
+    // Marking it first locks the row, so concurrent requests can't both publish it
+    let n_published = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = now()
+        WHERE newsletter_issue_id = $1 AND published_at IS NULL
+        "#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the newsletter issue as published.")?
+    .rows_affected();
+    if n_published == 0 {
+        return Ok(false);
+    }
+    let n_recipients = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email,
+            n_retries,
+            execute_after
+        )
+        SELECT $1, email, 0, now()
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to enqueue delivery tasks.")?
+    .rows_affected();
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET n_recipients = $2 WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+        n_recipients as i32
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the number of recipients.")?;
+    notify_workers(transaction)
+        .await
+        .context("Failed to notify the delivery workers.")?;
+    Ok(true)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_newsletter_issue(
+    newsletter_issue_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<StoredNewsletterIssue>> {
+    sqlx::query_as!(
+        StoredNewsletterIssue,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, created_at, published_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the newsletter issue.")
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_delivery_status(
+    newsletter_issue_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<DeliveryStatus>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            i.published_at,
+            i.n_recipients,
+            COUNT(q.subscriber_email) AS "pending!",
+            COUNT(q.subscriber_email) FILTER (WHERE q.n_retries > 0) AS "retrying!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_delivery_queue q ON q.newsletter_issue_id = i.newsletter_issue_id
+        WHERE i.newsletter_issue_id = $1
+        GROUP BY i.newsletter_issue_id
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the delivery status.")?;
+    Ok(row.map(|r| {
+        let status = match (r.published_at, r.pending) {
+            (None, _) => "draft",
+            (Some(_), 0) => "completed",
+            (Some(_), _) => "in_progress",
+        };
+        DeliveryStatus {
+            newsletter_issue_id,
+            status,
+            published_at: r.published_at,
+            recipients: r.n_recipients.unwrap_or(0),
+            pending: r.pending,
+            retrying: r.retrying,
+        }
+    }))
+}
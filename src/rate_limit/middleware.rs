@@ -0,0 +1,155 @@
+use super::{RateLimitStatus, RateLimiter};
+use crate::{
+    audit_log::ClientIp,
+    domain::{EmailPolicy, SubscriberEmail},
+    routes::{too_many_requests, ApiError},
+    utils::{form_field, peek_body},
+};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    web, ResponseError,
+};
+use actix_web_lab::middleware::Next;
+
+/// The routes with a limit in `RateLimitSettings`, registered with `app_data` on the same
+/// resource as `rate_limited`.
+#[derive(Clone, Copy, Debug)]
+pub enum RateLimitedRoute {
+    /// The subscription form and its API counterpart, sharing their counters
+    Subscriptions,
+    PasswordReset,
+}
+
+impl RateLimitedRoute {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitedRoute::Subscriptions => "subscriptions",
+            RateLimitedRoute::PasswordReset => "password_reset",
+        }
+    }
+
+    /// The field of the request naming who gets an email.
+    fn recipient_field(&self) -> &'static str {
+        match self {
+            RateLimitedRoute::Subscriptions => "email",
+            RateLimitedRoute::PasswordReset => "username",
+        }
+    }
+}
+
+/// Reject the requests over the limits of the route with a 429.
+/// The JSON API answers with an `ErrorBody` and tells clients where they stand with the
+/// `RateLimit-*` headers, the forms with a page asking to come back later.
+/// If the counters are unavailable we let the requests through rather than blocking everyone.
+pub async fn rate_limited(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let route = match req.app_data::<RateLimitedRoute>().copied() {
+        Some(route) if !req.method().is_safe() => route,
+        _ => return next.call(req).await,
+    };
+    let body = peek_body(&mut req).await?;
+    let recipient = recipient(&body, route.recipient_field())
+        .and_then(|recipient| normalize_recipient(&req, route, &recipient));
+    let ip = req.extract::<ClientIp>().await?.0;
+    let limiter = req
+        .app_data::<web::Data<RateLimiter>>()
+        .expect("The rate limiter is not registered.")
+        .clone();
+    let status = match limiter.hit(route, &ip, recipient.as_deref()).await {
+        Ok(status) => status,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to count the request against the rate limits."
+            );
+            return next.call(req).await;
+        }
+    };
+    let is_api_request = req.path().starts_with("/api/");
+    if !status.allowed {
+        tracing::warn!(route = route.as_str(), %ip, "Rate limit exceeded.");
+        // Returned as an error, so that the idempotency key of the request is released
+        let response = if is_api_request {
+            let mut response = ApiError::TooManyRequests.error_response();
+            insert_rate_limit_headers(response.headers_mut(), &status);
+            response
+        } else {
+            too_many_requests(status.reset_after_secs)
+        };
+        let e = anyhow::anyhow!("The {} rate limit was exceeded", route.as_str());
+        return Err(InternalError::from_response(e, response).into());
+    }
+    let mut response = next.call(req).await?;
+    if is_api_request {
+        insert_rate_limit_headers(response.headers_mut(), &status);
+    }
+    Ok(response)
+}
+
+/// The recipient named by a form or a JSON body.
+fn recipient(body: &[u8], field: &str) -> Option<String> {
+    form_field(body, field).or_else(|| {
+        serde_json::from_slice::<serde_json::Value>(body)
+            .ok()?
+            .get(field)?
+            .as_str()
+            .map(ToOwned::to_owned)
+    })
+}
+
+/// Spell the recipient the way it is looked up, so that its variants share a counter.
+/// Invalid addresses get no email, so they are only counted against the client IP.
+fn normalize_recipient(
+    req: &ServiceRequest,
+    route: RateLimitedRoute,
+    recipient: &str,
+) -> Option<String> {
+    match route {
+        RateLimitedRoute::Subscriptions => {
+            let email_policy = req
+                .app_data::<web::Data<EmailPolicy>>()
+                .expect("The email policy is not registered.");
+            let email: SubscriberEmail = recipient.parse().ok()?;
+            // Subscriptions are unique regardless of case
+            Some(email_policy.normalize(email).as_ref().to_lowercase())
+        }
+        // Folding usernames can only make the limit stricter
+        RateLimitedRoute::PasswordReset => Some(recipient.trim().to_lowercase()),
+    }
+}
+
+fn insert_rate_limit_headers(headers: &mut HeaderMap, status: &RateLimitStatus) {
+    let mut insert = |name: &'static str, value: u64| {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    };
+    insert("ratelimit-limit", status.limit.into());
+    insert("ratelimit-remaining", status.remaining.into());
+    insert("ratelimit-reset", status.reset_after_secs);
+    if !status.allowed {
+        headers.insert(RETRY_AFTER, HeaderValue::from(status.reset_after_secs));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recipient;
+
+    #[test]
+    fn recipients_are_read_from_forms_and_json() {
+        let form = b"name=le%20guin&email=ursula_le_guin%40gmail.com";
+        let json = br#"{"name": "le guin", "email": "ursula_le_guin@gmail.com"}"#;
+        for body in [&form[..], &json[..]] {
+            assert_eq!(
+                recipient(body, "email").as_deref(),
+                Some("ursula_le_guin@gmail.com")
+            );
+        }
+        assert_eq!(recipient(b"name=le%20guin", "email"), None);
+    }
+}
@@ -0,0 +1,222 @@
+mod middleware;
+
+pub use middleware::{rate_limited, RateLimitedRoute};
+
+use crate::configuration::{RateLimitRule, RateLimitSettings, SessionStoreBackend};
+use crate::expiring_map::ExpiringMap;
+use anyhow::Result;
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{sync::Mutex, time::Duration};
+
+/// Counts the requests to the rate limited routes per client IP and per recipient.
+/// Each limit is a sliding window, estimated from the hits of the current and previous
+/// fixed windows.
+pub struct RateLimiter {
+    counters: HitCounters,
+    settings: RateLimitSettings,
+}
+
+/// Where the hits are counted, follows the session store backend like the login throttle.
+enum HitCounters {
+    Redis(ConnectionManager),
+    Postgres(PgPool),
+    Memory(Mutex<ExpiringMap<u32>>),
+}
+
+/// Where a request stands against the limits of its route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    /// The limit closest to being reached
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the current window ends
+    pub reset_after_secs: u64,
+}
+
+impl RateLimiter {
+    pub async fn new(
+        backend: SessionStoreBackend,
+        redis_uri: &Secret<String>,
+        pool: &PgPool,
+        settings: RateLimitSettings,
+    ) -> Result<Self> {
+        settings.validate()?;
+        let counters = match backend {
+            SessionStoreBackend::Redis => {
+                let client = redis::Client::open(redis_uri.expose_secret().as_str())?;
+                HitCounters::Redis(ConnectionManager::new(client).await?)
+            }
+            SessionStoreBackend::Postgres => HitCounters::Postgres(pool.clone()),
+            SessionStoreBackend::Memory => HitCounters::Memory(Mutex::default()),
+        };
+        Ok(Self { counters, settings })
+    }
+
+    fn rule(&self, route: RateLimitedRoute) -> RateLimitRule {
+        match route {
+            RateLimitedRoute::Subscriptions => self.settings.subscriptions,
+            RateLimitedRoute::PasswordReset => self.settings.password_reset,
+        }
+    }
+
+    /// Count a request from `ip`, sent to `recipient` when the request names one.
+    /// `recipient` is expected to be normalized already.
+    #[tracing::instrument(name = "Count rate limited request", skip(self, recipient))]
+    pub async fn hit(
+        &self,
+        route: RateLimitedRoute,
+        ip: &str,
+        recipient: Option<&str>,
+    ) -> Result<RateLimitStatus> {
+        let rule = self.rule(route);
+        let mut status = self
+            .hit_counter(
+                &format!("{}:ip:{}", route.as_str(), ip),
+                rule.max_requests_per_ip,
+                rule.window_secs,
+            )
+            .await?;
+        if let Some(recipient) = recipient {
+            // Hashing keeps addresses out of the counter keys
+            let recipient = hex::encode(Sha256::digest(recipient));
+            let recipient_status = self
+                .hit_counter(
+                    &format!("{}:recipient:{}", route.as_str(), recipient),
+                    rule.max_requests_per_recipient,
+                    rule.window_secs,
+                )
+                .await?;
+            if !recipient_status.allowed || recipient_status.remaining < status.remaining {
+                status = recipient_status;
+            }
+        }
+        Ok(status)
+    }
+
+    async fn hit_counter(
+        &self,
+        key: &str,
+        limit: u32,
+        window_secs: u64,
+    ) -> Result<RateLimitStatus> {
+        let now = Utc::now().timestamp() as u64;
+        let window = now / window_secs;
+        let elapsed = now % window_secs;
+        let key = |window: u64| format!("{}:{}:{}", self.settings.redis_key_prefix, key, window);
+        // A window is read until the end of the next one
+        let current = self
+            .counters
+            .increment(&key(window), 2 * window_secs)
+            .await?;
+        let previous = self.counters.get(&key(window.saturating_sub(1))).await?;
+        let hits = sliding_window_hits(previous, current, elapsed, window_secs);
+        Ok(RateLimitStatus {
+            allowed: hits <= limit,
+            limit,
+            remaining: limit.saturating_sub(hits),
+            reset_after_secs: window_secs - elapsed,
+        })
+    }
+}
+
+/// The hits of the last `window_secs`, assuming those of the previous window were evenly
+/// spread.
+fn sliding_window_hits(previous: u32, current: u32, elapsed_secs: u64, window_secs: u64) -> u32 {
+    let previous_weight = (window_secs - elapsed_secs) as f64 / window_secs as f64;
+    current + (previous as f64 * previous_weight) as u32
+}
+
+impl HitCounters {
+    async fn get(&self, key: &str) -> Result<u32> {
+        match self {
+            Self::Redis(redis) => {
+                let hits: Option<u32> = redis.clone().get(key).await?;
+                Ok(hits.unwrap_or(0))
+            }
+            Self::Postgres(pool) => {
+                let hits = sqlx::query_scalar!(
+                    r#"
+                    SELECT hits
+                    FROM rate_limit_counters
+                    WHERE counter_key = $1 AND expires_at > now()
+                    "#,
+                    key
+                )
+                .fetch_optional(pool)
+                .await?;
+                Ok(hits.unwrap_or(0) as u32)
+            }
+            Self::Memory(counters) => Ok(counters.lock().unwrap().get(key).copied().unwrap_or(0)),
+        }
+    }
+
+    /// Add a hit, a counter that doesn't exist yet lasts `ttl_secs`.
+    async fn increment(&self, key: &str, ttl_secs: u64) -> Result<u32> {
+        match self {
+            Self::Redis(redis) => {
+                let mut redis = redis.clone();
+                // Creating the counter with its expiration first means it can't outlive the window
+                redis::cmd("SET")
+                    .arg(key)
+                    .arg(0)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(ttl_secs)
+                    .query_async::<_, ()>(&mut redis)
+                    .await?;
+                Ok(redis.incr(key, 1).await?)
+            }
+            Self::Postgres(pool) => {
+                let expires_at = Utc::now() + chrono::Duration::seconds(ttl_secs as i64);
+                let hits = sqlx::query_scalar!(
+                    r#"
+                    INSERT INTO rate_limit_counters (counter_key, hits, expires_at)
+                    VALUES ($1, 1, $2)
+                    ON CONFLICT (counter_key) DO UPDATE
+                    SET
+                        hits = CASE WHEN rate_limit_counters.expires_at > now()
+                            THEN rate_limit_counters.hits + 1 ELSE 1 END,
+                        expires_at = CASE WHEN rate_limit_counters.expires_at > now()
+                            THEN rate_limit_counters.expires_at ELSE EXCLUDED.expires_at END
+                    RETURNING hits
+                    "#,
+                    key,
+                    expires_at
+                )
+                .fetch_one(pool)
+                .await?;
+                Ok(hits as u32)
+            }
+            Self::Memory(counters) => {
+                let mut counters = counters.lock().unwrap();
+                Ok(counters.increment(key, Duration::from_secs(ttl_secs)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sliding_window_hits, HitCounters};
+    use std::sync::Mutex;
+
+    #[test]
+    fn the_previous_window_fades_out_as_the_current_one_goes_by() {
+        assert_eq!(sliding_window_hits(10, 1, 0, 100), 11);
+        assert_eq!(sliding_window_hits(10, 1, 50, 100), 6);
+        assert_eq!(sliding_window_hits(10, 1, 99, 100), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_memory_counters_start_again() {
+        let counters = HitCounters::Memory(Mutex::default());
+        counters.increment("key", 0).await.unwrap();
+        assert_eq!(counters.get("key").await.unwrap(), 0);
+        assert_eq!(counters.increment("key", 60).await.unwrap(), 1);
+    }
+}
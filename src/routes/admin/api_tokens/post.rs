@@ -0,0 +1,204 @@
+use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    authentication::{self, ApiScope, UserId},
+    routes::TEMPLATES,
+    utils::{e500, see_other},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use chrono::{Duration, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_TOKEN_NAME_LENGTH: usize = 100;
+const MAX_TOKEN_LIFETIME_DAYS: i64 = 365;
+
+/// Each checked scope is sent as its own `scope` field, so the form is parsed by hand.
+#[derive(Debug, PartialEq)]
+struct NewApiToken {
+    name: String,
+    scopes: Vec<ApiScope>,
+    /// `None` for tokens that never expire
+    expires_in_days: Option<i64>,
+}
+
+impl TryFrom<Vec<(String, String)>> for NewApiToken {
+    type Error = String;
+
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut name = None;
+        let mut scopes = Vec::new();
+        let mut expires_in_days = None;
+        for (field, value) in fields {
+            match field.as_str() {
+                "name" => name = Some(value.trim().to_owned()),
+                "scope" => {
+                    let scope = value.parse::<ApiScope>()?;
+                    if !scopes.contains(&scope) {
+                        scopes.push(scope);
+                    }
+                }
+                "expires_in_days" if value == "never" => expires_in_days = None,
+                "expires_in_days" => match value.parse::<i64>() {
+                    Ok(days) if (1..=MAX_TOKEN_LIFETIME_DAYS).contains(&days) => {
+                        expires_in_days = Some(days)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Tokens can expire after 1 to {} days, or never.",
+                            MAX_TOKEN_LIFETIME_DAYS
+                        ))
+                    }
+                },
+                _ => {}
+            }
+        }
+        let name = name.unwrap_or_default();
+        if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+            return Err(format!(
+                "The name of the token must have between 1 and {} characters.",
+                MAX_TOKEN_NAME_LENGTH
+            ));
+        }
+        if scopes.is_empty() {
+            return Err("Select at least one scope.".into());
+        }
+        Ok(NewApiToken {
+            name,
+            scopes,
+            expires_in_days,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Create an API token", skip(form, pool, client_ip), fields(user_id=%*user_id))]
+pub async fn create_api_token(
+    user_id: web::ReqData<UserId>,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let new_token = match NewApiToken::try_from(form.0) {
+        Ok(new_token) => new_token,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/tokens"));
+        }
+    };
+    let expires_at = new_token
+        .expires_in_days
+        .map(|days| Utc::now() + Duration::days(days));
+    let (token_id, token) = authentication::create_api_token(
+        **user_id,
+        &new_token.name,
+        &new_token.scopes,
+        expires_at,
+        &pool,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        Some(**user_id),
+        AuditAction::ApiTokenCreated,
+        Some(&client_ip.0),
+        serde_json::json!({
+            "token_id": token_id,
+            "name": new_token.name,
+            "scopes": new_token.scopes,
+            "expires_at": expires_at,
+        }),
+    )
+    .await
+    .map_err(e500)?;
+    // Only the hash is stored, this is the only time the token is shown
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("name", &new_token.name);
+        context.insert("token", token.expose_secret());
+        TEMPLATES
+            .render("api_token_created.html", &context)
+            .unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(pool, client_ip), fields(user_id=%*user_id))]
+pub async fn revoke_api_token(
+    user_id: web::ReqData<UserId>,
+    token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token_id = token_id.into_inner();
+    match authentication::revoke_api_token(**user_id, token_id, &pool)
+        .await
+        .map_err(e500)?
+    {
+        Some(name) => {
+            record_audit_event(
+                pool.get_ref(),
+                Some(**user_id),
+                AuditAction::ApiTokenRevoked,
+                Some(&client_ip.0),
+                serde_json::json!({ "token_id": token_id, "name": name }),
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info(format!("The token {} has been revoked.", name)).send();
+        }
+        None => FlashMessage::error("The token doesn't exist.").send(),
+    }
+    Ok(see_other("/admin/tokens"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewApiToken;
+    use crate::authentication::ApiScope;
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn a_token_can_have_several_scopes() {
+        let new_token = NewApiToken::try_from(fields(&[
+            ("csrf_token", "abc"),
+            ("name", " CI "),
+            ("scope", "issues:read"),
+            ("scope", "issues:write"),
+            ("expires_in_days", "90"),
+        ]));
+        assert_eq!(
+            new_token,
+            Ok(NewApiToken {
+                name: "CI".into(),
+                scopes: vec![ApiScope::IssuesRead, ApiScope::IssuesWrite],
+                expires_in_days: Some(90),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_tokens_are_rejected() {
+        for invalid in [
+            fields(&[("name", "CI"), ("expires_in_days", "never")]),
+            fields(&[("name", " "), ("scope", "issues:read")]),
+            fields(&[("name", "CI"), ("scope", "users:write")]),
+            fields(&[
+                ("name", "CI"),
+                ("scope", "issues:read"),
+                ("expires_in_days", "0"),
+            ]),
+        ] {
+            assert!(NewApiToken::try_from(invalid).is_err());
+        }
+    }
+}
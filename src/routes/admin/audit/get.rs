@@ -0,0 +1,105 @@
+use crate::{
+    audit_log::{list_audit_events, AuditAction, AuditFilter},
+    routes::TEMPLATES,
+    utils::{e500, see_other},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/// The filters as submitted by the form, empty fields are ignored.
+#[derive(Default, Deserialize, Serialize)]
+pub struct QueryParams {
+    action: Option<String>,
+    actor: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+impl TryFrom<&QueryParams> for AuditFilter {
+    type Error = String;
+
+    fn try_from(params: &QueryParams) -> Result<Self, Self::Error> {
+        let non_empty = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_owned)
+        };
+        let parse_date = |value: Option<String>| {
+            value
+                .map(|v| {
+                    NaiveDate::parse_from_str(&v, "%Y-%m-%d")
+                        .map_err(|_| format!("{} is not a valid date.", v))
+                })
+                .transpose()
+        };
+        Ok(AuditFilter {
+            action: non_empty(&params.action).map(|a| a.parse()).transpose()?,
+            actor: non_empty(&params.actor),
+            since: parse_date(non_empty(&params.since))?,
+            until: parse_date(non_empty(&params.until))?,
+        })
+    }
+}
+
+pub async fn audit_page(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter: AuditFilter = match (&query.0).try_into() {
+        Ok(filter) => filter,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/audit"));
+        }
+    };
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let entries = list_audit_events(&filter, &pool).await.map_err(e500)?;
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        context.insert("entries", &entries);
+        context.insert("actions", &AuditAction::ALL);
+        context.insert("filter", &query.0);
+        TEMPLATES.render("audit.html", &context).unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(action: &str, since: &str) -> QueryParams {
+        QueryParams {
+            action: Some(action.into()),
+            actor: Some(" ".into()),
+            since: Some(since.into()),
+            until: None,
+        }
+    }
+
+    #[test]
+    fn empty_fields_are_ignored() {
+        let filter = AuditFilter::try_from(&params("", "")).unwrap();
+        assert!(filter.action.is_none());
+        assert!(filter.actor.is_none());
+        assert!(filter.since.is_none());
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert!(AuditFilter::try_from(&params("login", "")).is_err());
+        assert!(AuditFilter::try_from(&params("", "yesterday")).is_err());
+        let filter = AuditFilter::try_from(&params("logout", "2022-06-01")).unwrap();
+        assert_eq!(filter.action, Some(AuditAction::LoggedOut));
+        assert_eq!(filter.since, NaiveDate::from_ymd_opt(2022, 6, 1));
+    }
+}
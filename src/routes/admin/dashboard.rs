@@ -1,5 +1,11 @@
-use crate::{authentication::UserId, routes::TEMPLATES, utils::e500};
+use crate::{
+    authentication::{UserId, UserRole},
+    routes::TEMPLATES,
+    session_state::TypedSession,
+    utils::e500,
+};
 use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::{Context, Result};
 use sqlx::PgPool;
 use uuid::Uuid;
@@ -7,13 +13,20 @@ use uuid::Uuid;
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
     pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let username = get_username(*user_id.into_inner(), &pool)
-        .await
-        .map_err(e500)?;
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let user_id = user_id.into_inner();
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
     let html_body = {
         let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        context.insert("csrf_token", &session.csrf_token().map_err(e500)?);
         context.insert("username", &username);
+        context.insert("role", &user_id.role());
+        context.insert("can_publish", &(user_id.role() >= UserRole::Editor));
+        context.insert("can_manage_users", &(user_id.role() >= UserRole::Owner));
         TEMPLATES.render("admin_dashboard.html", &context).unwrap()
         // .context("Failed to construct the HTML email template.")?
     };
@@ -1,8 +1,34 @@
-use crate::{session_state::TypedSession, utils::see_other};
-use actix_web::HttpResponse;
+use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    authentication::{revoke_user_session, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn log_out(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = session.get_session_id().map_err(e500)?;
+    if let Some(session_id) = session_id {
+        revoke_user_session(**user_id, session_id, &pool)
+            .await
+            .map_err(e500)?;
+    }
+    record_audit_event(
+        pool.get_ref(),
+        Some(**user_id),
+        AuditAction::LoggedOut,
+        Some(&client_ip.0),
+        serde_json::json!({ "session_id": session_id }),
+    )
+    .await
+    .map_err(e500)?;
     session.log_out();
     FlashMessage::info("You have successfully logged out.").send();
     Ok(see_other("/login"))
@@ -1,13 +1,23 @@
+mod api_tokens;
+mod audit;
 mod dashboard;
 mod delivery_process;
 mod logout;
 mod newsletter;
 mod not_found;
 mod password;
+mod sessions;
+mod two_factor;
+mod users;
 
+pub use api_tokens::*;
+pub use audit::audit_page;
 pub use dashboard::admin_dashboard;
 pub use delivery_process::delivery_process;
 pub use logout::log_out;
 pub use newsletter::*;
-pub use not_found::not_found;
+pub use not_found::{forbidden, not_found, too_many_requests};
 pub use password::*;
+pub use sessions::*;
+pub use two_factor::*;
+pub use users::*;
@@ -1,17 +1,21 @@
-use crate::routes::TEMPLATES;
+use crate::{routes::TEMPLATES, session_state::TypedSession, utils::e500};
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 
-pub async fn publish_newsletter_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn publish_newsletter_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
     let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
     let idempotency_key = uuid::Uuid::new_v4().to_string();
     let html_body = {
         let mut context = tera::Context::new();
         context.insert("idempotency_key", &idempotency_key);
         context.insert("flash_msgs", &flash_msgs);
+        context.insert("csrf_token", &session.csrf_token().map_err(e500)?);
         TEMPLATES.render("newsletters.html", &context).unwrap()
     };
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(html_body)
+        .body(html_body))
 }
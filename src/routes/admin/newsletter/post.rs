@@ -1,23 +1,23 @@
 use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
     authentication::UserId,
     domain::NewsletterIssue,
     error_chain_fmt,
-    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    idempotency::IdempotencyConfig,
+    newsletter_issues::{insert_newsletter_issue, publish_newsletter_issue},
     utils::see_other,
 };
 use actix_web::{error::InternalError, web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
 use serde::Deserialize;
-use sqlx::{PgPool, Postgres, Transaction};
-use uuid::Uuid;
+use sqlx::PgPool;
 
 #[derive(Deserialize)]
 pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
-    idempotency_key: String,
 }
 
 #[derive(thiserror::Error)]
@@ -49,42 +49,54 @@ pub async fn publish_newsletter(
     user_id: web::ReqData<UserId>,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    client_ip: ClientIp,
 ) -> Result<HttpResponse, InternalError<NewsletterError>> {
     let user_id = user_id.into_inner();
     let FormData {
         title,
         text_content,
         html_content,
-        idempotency_key,
     } = form.0;
-    let idempotency_key: IdempotencyKey =
-        idempotency_key.try_into().map_err(newsletter_redirect)?;
     let newsletter_issue =
         NewsletterIssue::try_new(title, text_content, html_content).map_err(newsletter_redirect)?;
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+    let mut transaction = pool
+        .begin()
         .await
-        .map_err(newsletter_redirect)?
-    {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
-            return Ok(saved_response);
-        }
-    };
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(newsletter_redirect)?;
     let issue_id = insert_newsletter_issue(&mut transaction, &newsletter_issue)
         .await
         .context("Failed to insert newsletter_issue into db.")
         .map_err(newsletter_redirect)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    publish_newsletter_issue(&mut transaction, issue_id)
         .await
-        .context("Failed to enqueue delivery tasks")
         .map_err(newsletter_redirect)?;
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
+    record_audit_event(
+        &mut transaction,
+        Some(*user_id),
+        AuditAction::NewsletterPublished,
+        Some(&client_ip.0),
+        serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "title": newsletter_issue.title(),
+        }),
+    )
+    .await
+    .map_err(newsletter_redirect)?;
+    transaction
+        .commit()
         .await
+        .context("Failed to commit the newsletter issue.")
         .map_err(newsletter_redirect)?;
     success_message().send();
-    Ok(response)
+    Ok(see_other("/admin/newsletters"))
+}
+
+/// The form carries an idempotency key, so submitting it twice publishes the issue once.
+pub fn publish_newsletter_idempotency() -> IdempotencyConfig {
+    IdempotencyConfig::default()
+        .required()
+        .on_replay(|| success_message().send())
 }
 
 /// Redirect to the newsletters page with an error message.
@@ -103,54 +115,3 @@ fn success_message() -> FlashMessage {
                  emails will go out shortly.",
     )
 }
-
-#[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue: &NewsletterIssue,
-) -> Result<Uuid, sqlx::Error> {
-    let newsletter_issue_id = Uuid::new_v4();
-    sqlx::query!(
-        r#"
-        INSERT INTO newsletter_issues (
-            newsletter_issue_id,
-            title,
-            text_content,
-            html_content,
-            published_at
-        )
-        VALUES ($1, $2, $3, $4, now())
-        "#,
-        newsletter_issue_id,
-        newsletter_issue.title(),
-        newsletter_issue.text_content(),
-        newsletter_issue.html_content()
-    )
-    .execute(transaction)
-    .await?;
-    Ok(newsletter_issue_id)
-}
-
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email,
-            n_retries,
-            execute_after
-        )
-        SELECT $1, email, 0, now()
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
-}
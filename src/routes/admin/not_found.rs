@@ -1,5 +1,8 @@
 use crate::routes::TEMPLATES;
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{
+    http::header::{ContentType, RETRY_AFTER},
+    HttpResponse,
+};
 
 pub async fn not_found() -> HttpResponse {
     let html_body = TEMPLATES.render("404.html", &tera::Context::new()).unwrap();
@@ -7,3 +10,20 @@ pub async fn not_found() -> HttpResponse {
         .content_type(ContentType::html())
         .body(html_body)
 }
+
+pub fn forbidden() -> HttpResponse {
+    let html_body = TEMPLATES.render("403.html", &tera::Context::new()).unwrap();
+    HttpResponse::Forbidden()
+        .content_type(ContentType::html())
+        .body(html_body)
+}
+
+pub fn too_many_requests(retry_after_secs: u64) -> HttpResponse {
+    let mut context = tera::Context::new();
+    context.insert("retry_after_minutes", &retry_after_secs.div_ceil(60));
+    let html_body = TEMPLATES.render("429.html", &context).unwrap();
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, retry_after_secs))
+        .content_type(ContentType::html())
+        .body(html_body)
+}
@@ -1,14 +1,16 @@
-use crate::routes::TEMPLATES;
+use crate::{routes::TEMPLATES, session_state::TypedSession, utils::e500};
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
     let html_body = {
         let mut context = tera::Context::new();
         context.insert("flash_msgs", &flash_msgs);
+        context.insert("csrf_token", &session.csrf_token().map_err(e500)?);
         TEMPLATES.render("password.html", &context).unwrap()
     };
     Ok(HttpResponse::Ok()
@@ -1,6 +1,11 @@
 use crate::{
-    authentication::{self, validate_credentials, AuthError, Credentials, UserId},
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    authentication::{
+        self, validate_credentials, validate_new_password, AuthError, Credentials, UserId,
+    },
+    configuration::PasswordHashingSettings,
     routes::admin::dashboard::get_username,
+    session_state::TypedSession,
     utils::{e500, see_other},
 };
 use actix_web::{web, HttpResponse};
@@ -20,6 +25,9 @@ pub async fn change_password(
     user_id: web::ReqData<UserId>,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    session: TypedSession,
+    client_ip: ClientIp,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
@@ -29,8 +37,8 @@ pub async fn change_password(
         .send();
         return Ok(see_other("/admin/password"));
     }
-    if !(12..=128).contains(&form.new_password.expose_secret().len()) {
-        FlashMessage::error("New password should have between 12 and 128 characters.").send();
+    if let Err(e) = validate_new_password(&form.new_password) {
+        FlashMessage::error(e).send();
         return Ok(see_other("/admin/password"));
     }
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
@@ -38,7 +46,7 @@ pub async fn change_password(
         username,
         password: form.0.current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -47,9 +55,24 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e).into()),
         };
     }
-    authentication::change_password(*user_id, form.0.new_password, &pool)
-        .await
-        .map_err(e500)?;
+    authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        session.get_session_id().map_err(e500)?,
+        &hashing,
+        &pool,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        Some(*user_id),
+        AuditAction::PasswordChanged,
+        Some(&client_ip.0),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
     FlashMessage::info("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
 }
@@ -0,0 +1,33 @@
+use crate::{
+    authentication::{list_user_sessions, UserId},
+    routes::TEMPLATES,
+    session_state::TypedSession,
+    utils::e500,
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+pub async fn sessions_page(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let csrf_token = session.csrf_token().map_err(e500)?;
+    let sessions = list_user_sessions(**user_id, current_session_id, &pool)
+        .await
+        .map_err(e500)?;
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        context.insert("sessions", &sessions);
+        context.insert("csrf_token", &csrf_token);
+        TEMPLATES.render("sessions.html", &context).unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
@@ -0,0 +1,47 @@
+use crate::{
+    authentication::{revoke_other_user_sessions, revoke_user_session, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(name = "Revoke a session", skip(pool), fields(user_id=%*user_id))]
+pub async fn revoke_session(
+    user_id: web::ReqData<UserId>,
+    session_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if revoke_user_session(**user_id, session_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The session has been logged out.").send();
+    } else {
+        FlashMessage::error("The session doesn't exist.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "Revoke other sessions", skip(session, pool), fields(user_id=%*user_id))]
+pub async fn revoke_other_sessions(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    revoke_other_user_sessions(&mut transaction, **user_id, current_session_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the session revocations.")
+        .map_err(e500)?;
+    FlashMessage::info("You have been logged out everywhere else.").send();
+    Ok(see_other("/admin/sessions"))
+}
@@ -0,0 +1,44 @@
+use crate::{
+    authentication::{is_two_factor_enabled, start_two_factor_enrollment, TotpCipher, UserId},
+    routes::{admin::dashboard::get_username, TEMPLATES},
+    session_state::TypedSession,
+    utils::e500,
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use qrcode::{render::svg, QrCode};
+use sqlx::PgPool;
+
+pub async fn two_factor_page(
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    cipher: web::Data<TotpCipher>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let mut context = tera::Context::new();
+    context.insert("flash_msgs", &flash_msgs);
+    context.insert("csrf_token", &session.csrf_token().map_err(e500)?);
+    let enabled = is_two_factor_enabled(*user_id, &pool).await.map_err(e500)?;
+    context.insert("enabled", &enabled);
+    if !enabled {
+        let username = get_username(*user_id, &pool).await.map_err(e500)?;
+        let enrollment = start_two_factor_enrollment(*user_id, &username, &cipher, &pool)
+            .await
+            .map_err(e500)?;
+        let qr_code = QrCode::new(enrollment.uri.as_bytes())
+            .map_err(e500)?
+            .render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .build();
+        context.insert("qr_code", &qr_code);
+        context.insert("secret", &enrollment.secret);
+        context.insert("uri", &enrollment.uri);
+    }
+    let html_body = TEMPLATES.render("two_factor.html", &context).unwrap();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
@@ -0,0 +1,64 @@
+use crate::{
+    authentication::{self, reset_two_factor, verify_second_factor, TotpCipher, UserId},
+    routes::TEMPLATES,
+    utils::{e500, see_other},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+pub async fn enable_two_factor(
+    user_id: web::ReqData<UserId>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    cipher: web::Data<TotpCipher>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recovery_codes = authentication::enable_two_factor(**user_id, &form.0.code, &cipher, &pool)
+        .await
+        .map_err(e500)?;
+    let recovery_codes = match recovery_codes {
+        Some(codes) => codes,
+        None => {
+            FlashMessage::error("The authentication code is incorrect.").send();
+            return Ok(see_other("/admin/two_factor"));
+        }
+    };
+    // Recovery codes are only stored hashed, this is the only time they are shown
+    let recovery_codes = recovery_codes
+        .iter()
+        .map(|c| c.expose_secret().as_str())
+        .collect::<Vec<_>>();
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("recovery_codes", &recovery_codes);
+        TEMPLATES.render("recovery_codes.html", &context).unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+pub async fn disable_two_factor(
+    user_id: web::ReqData<UserId>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    cipher: web::Data<TotpCipher>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !verify_second_factor(**user_id, &form.0.code, &cipher, &pool)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The authentication code is incorrect.").send();
+        return Ok(see_other("/admin/two_factor"));
+    }
+    reset_two_factor(**user_id, &pool).await.map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/two_factor"))
+}
@@ -0,0 +1,70 @@
+use crate::{
+    authentication::{get_recent_lockouts, list_users, UserId, UserRole},
+    routes::TEMPLATES,
+    session_state::TypedSession,
+    utils::e500,
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sqlx::PgPool;
+
+#[derive(Serialize)]
+struct PendingInvitation {
+    email: String,
+    role: String,
+    expires_at: String,
+}
+
+pub async fn users_page(
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let users = list_users(&pool).await.map_err(e500)?;
+    let invitations = get_pending_invitations(&pool).await.map_err(e500)?;
+    let lockouts = get_recent_lockouts(&pool).await.map_err(e500)?;
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        context.insert("csrf_token", &session.csrf_token().map_err(e500)?);
+        context.insert("current_user_id", &user_id.into_inner().to_string());
+        context.insert("users", &users);
+        context.insert("invitations", &invitations);
+        context.insert("lockouts", &lockouts);
+        context.insert(
+            "roles",
+            &[UserRole::Viewer, UserRole::Editor, UserRole::Owner],
+        );
+        TEMPLATES.render("users.html", &context).unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<PendingInvitation>> {
+    let invitations = sqlx::query!(
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invitations
+        WHERE accepted_at IS NULL AND expires_at > now()
+        ORDER BY created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending invitations.")?
+    .into_iter()
+    .map(|r| PendingInvitation {
+        email: r.email,
+        role: r.role,
+        expires_at: r.expires_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+    })
+    .collect();
+    Ok(invitations)
+}
@@ -0,0 +1,7 @@
+mod get;
+mod post;
+
+pub use get::users_page;
+pub use post::{
+    activate_user, deactivate_user, invite_user, reset_user_two_factor, unlock_user, update_role,
+};
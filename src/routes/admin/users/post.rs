@@ -0,0 +1,303 @@
+use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    authentication::{
+        lift_lockouts, reset_two_factor, set_user_active, set_user_role, LoginThrottle,
+        SignedInvitation, UserId, UserRole,
+    },
+    configuration::AccountSettings,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    routes::TEMPLATES,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils::{e500, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: UserRole,
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Invite a new user",
+    skip(form, pool, email_client, base_url, hmac_secret, account_settings, client_ip),
+    fields(invited_by=%*user_id)
+)]
+pub async fn invite_user(
+    user_id: web::ReqData<UserId>,
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    account_settings: web::Data<AccountSettings>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email: SubscriberEmail = match form.0.email.parse() {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let expiration =
+        chrono::Duration::from_std(account_settings.invitation_expiration()).map_err(e500)?;
+    let expires_at = Utc::now() + expiration;
+    let invitation_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations
+            (invitation_id, email, role, invited_by, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, now(), $5)
+        "#,
+        invitation_id,
+        email.as_ref(),
+        form.0.role.as_str(),
+        **user_id,
+        expires_at
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the invitation.")
+    .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        Some(**user_id),
+        AuditAction::UserInvited,
+        Some(&client_ip.0),
+        serde_json::json!({
+            "invitation_id": invitation_id,
+            "email": email.as_ref(),
+            "role": form.0.role,
+        }),
+    )
+    .await
+    .map_err(e500)?;
+    let invitation = SignedInvitation::new(invitation_id, expires_at, &hmac_secret.0);
+    send_invitation_email(&email_client, &email, &base_url.0, &invitation)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("An invitation was sent to {}.", email)).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Send an invitation email", skip(email_client, invitation))]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    invitation: &SignedInvitation,
+) -> Result<(), anyhow::Error> {
+    let invitation_link = format!("{}{}", base_url, invitation.path());
+    let plain_body = format!(
+        "You have been invited to manage our newsletter!\nVisit {} to create your account.",
+        invitation_link
+    );
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("invitation_link", &invitation_link);
+        TEMPLATES
+            .render("invitation_email.html", &context)
+            .context("Failed to construct the HTML email template.")?
+    };
+    email_client
+        .send_email(email, "You have been invited!", &html_body, &plain_body)
+        .await
+        .context("Failed to send an invitation email.")
+}
+
+#[tracing::instrument(name = "Activate a user", skip(pool, client_ip), fields(admin_id=%*user_id))]
+pub async fn activate_user(
+    user_id: web::ReqData<UserId>,
+    target_user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    update_user_status(
+        **user_id,
+        target_user_id.into_inner(),
+        true,
+        &client_ip,
+        &pool,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Deactivate a user", skip(pool, client_ip), fields(admin_id=%*user_id))]
+pub async fn deactivate_user(
+    user_id: web::ReqData<UserId>,
+    target_user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_user_id = target_user_id.into_inner();
+    // Otherwise the last admin could lock everyone out
+    if target_user_id == **user_id {
+        FlashMessage::error("You can't deactivate your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    update_user_status(**user_id, target_user_id, false, &client_ip, &pool).await
+}
+
+#[derive(Deserialize)]
+pub struct RoleFormData {
+    role: UserRole,
+}
+
+#[tracing::instrument(
+    name = "Update a user's role",
+    skip(pool, form, client_ip),
+    fields(admin_id=%*user_id)
+)]
+pub async fn update_role(
+    user_id: web::ReqData<UserId>,
+    target_user_id: web::Path<Uuid>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_user_id = target_user_id.into_inner();
+    if target_user_id == **user_id {
+        FlashMessage::error("You can't change your own role.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    if set_user_role(target_user_id, form.0.role, &pool)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            pool.get_ref(),
+            Some(**user_id),
+            AuditAction::RoleChanged,
+            Some(&client_ip.0),
+            serde_json::json!({ "user_id": target_user_id, "role": form.0.role }),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info(format!("The user's role is now {}.", form.0.role)).send();
+    } else {
+        FlashMessage::error("The user doesn't exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+/// Let a user who lost their authenticator app and recovery codes log in again.
+#[tracing::instrument(
+    name = "Reset a user's two-factor authentication",
+    skip(pool, client_ip),
+    fields(admin_id=%*user_id)
+)]
+pub async fn reset_user_two_factor(
+    user_id: web::ReqData<UserId>,
+    target_user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_user_id = target_user_id.into_inner();
+    if reset_two_factor(target_user_id, &pool)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            pool.get_ref(),
+            Some(**user_id),
+            AuditAction::TwoFactorReset,
+            Some(&client_ip.0),
+            serde_json::json!({ "user_id": target_user_id }),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info("The user's two-factor authentication has been reset.").send();
+    } else {
+        FlashMessage::error("The user doesn't exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+async fn update_user_status(
+    admin_id: Uuid,
+    user_id: Uuid,
+    is_active: bool,
+    client_ip: &ClientIp,
+    pool: &PgPool,
+) -> Result<HttpResponse, actix_web::Error> {
+    if set_user_active(user_id, is_active, pool)
+        .await
+        .map_err(e500)?
+    {
+        let (status, action) = if is_active {
+            ("activated", AuditAction::UserActivated)
+        } else {
+            ("deactivated", AuditAction::UserDeactivated)
+        };
+        record_audit_event(
+            pool,
+            Some(admin_id),
+            action,
+            Some(&client_ip.0),
+            serde_json::json!({ "user_id": user_id }),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info(format!("The user has been {}.", status)).send();
+    } else {
+        FlashMessage::error("The user doesn't exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[derive(Deserialize)]
+pub struct UnlockFormData {
+    username: Option<String>,
+    client_ip: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Lift a login lockout",
+    skip(pool, throttle, form, client_ip),
+    fields(admin_id=%*user_id)
+)]
+pub async fn unlock_user(
+    user_id: web::ReqData<UserId>,
+    form: web::Form<UnlockFormData>,
+    pool: web::Data<PgPool>,
+    throttle: web::Data<LoginThrottle>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let UnlockFormData {
+        username,
+        client_ip: locked_ip,
+    } = form.0;
+    if username.is_none() && locked_ip.is_none() {
+        FlashMessage::error("Nothing to unlock.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    if let Some(username) = &username {
+        throttle.clear(username).await;
+    }
+    if let Some(locked_ip) = &locked_ip {
+        throttle.clear_ip(locked_ip).await;
+    }
+    lift_lockouts(username.as_deref(), locked_ip.as_deref(), &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        Some(**user_id),
+        AuditAction::LockoutLifted,
+        Some(&client_ip.0),
+        serde_json::json!({ "username": username, "client_ip": locked_ip }),
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info("The lockout has been lifted.").send();
+    Ok(see_other("/admin/users"))
+}
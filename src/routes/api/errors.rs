@@ -0,0 +1,148 @@
+use crate::{
+    authentication::ApiScope,
+    error_chain_fmt,
+    routes::{ConfirmationError, NewsletterError, SubscribeError},
+};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Errors of the JSON API, answered with an `ErrorBody` instead of a redirect.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("You don't have permission to do this.")]
+    Forbidden,
+    #[error("The API token needs the {} scope.", .0.as_str())]
+    MissingScope(ApiScope),
+    #[error("The X-CSRF-Token header is missing or invalid.")]
+    InvalidCsrfToken,
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("A request with the same idempotency key is still being processed.")]
+    RequestInProgress,
+    #[error("The idempotency key was already used for a different request, use a new key.")]
+    IdempotencyKeyReused,
+    #[error("Too many requests, try again later.")]
+    TooManyRequests,
+    #[error("Something went wrong.")]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "validation_error",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden => "forbidden",
+            ApiError::MissingScope(_) => "insufficient_scope",
+            ApiError::InvalidCsrfToken => "invalid_csrf_token",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::RequestInProgress => "request_in_progress",
+            ApiError::IdempotencyKeyReused => "idempotency_key_reused",
+            ApiError::TooManyRequests => "too_many_requests",
+            ApiError::Unexpected(_) => "internal_error",
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    error: ErrorDetails,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorDetails {
+    #[schema(example = "validation_error")]
+    code: &'static str,
+    /// Meant for humans, it can change between releases
+    message: String,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden | ApiError::MissingScope(_) | ApiError::InvalidCsrfToken => {
+                StatusCode::FORBIDDEN
+            }
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) | ApiError::RequestInProgress => StatusCode::CONFLICT,
+            ApiError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: ErrorDetails {
+                code: self.code(),
+                message: self.to_string(),
+            },
+        })
+    }
+}
+
+impl From<SubscribeError> for ApiError {
+    fn from(e: SubscribeError) -> Self {
+        match e {
+            SubscribeError::ValidationError(e) => ApiError::Validation(e),
+            SubscribeError::RejectedForm(_) => ApiError::Validation(e.to_string()),
+            SubscribeError::DuplicatedEmail { .. } => ApiError::Conflict(e.to_string()),
+            SubscribeError::UnexpectedError(e) => ApiError::Unexpected(e),
+        }
+    }
+}
+
+impl From<ConfirmationError> for ApiError {
+    fn from(e: ConfirmationError) -> Self {
+        match e {
+            ConfirmationError::UnkwownToken => ApiError::Unauthorized(e.to_string()),
+            ConfirmationError::UnexpectedError(e) => ApiError::Unexpected(e),
+        }
+    }
+}
+
+impl From<NewsletterError> for ApiError {
+    fn from(e: NewsletterError) -> Self {
+        match e {
+            NewsletterError::ValidationError(e) => ApiError::Validation(e),
+            NewsletterError::UnexpectedError(e) => ApiError::Unexpected(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_keep_their_meaning_in_the_api() {
+        let e: ApiError = SubscribeError::ValidationError("Invalid email.".into()).into();
+        assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(e.to_string(), "Invalid email.");
+
+        let e: ApiError = ConfirmationError::UnkwownToken.into();
+        assert_eq!(e.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(e.code(), "unauthorized");
+
+        let e: ApiError = NewsletterError::UnexpectedError(anyhow::anyhow!("db is down")).into();
+        assert_eq!(e.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        // The cause is logged, not sent to the client
+        assert_eq!(e.to_string(), "Something went wrong.");
+    }
+}
@@ -0,0 +1,189 @@
+use super::{require_role, ApiError};
+use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    authentication::{UserId, UserRole},
+    domain::NewsletterIssue,
+    newsletter_issues::{
+        get_delivery_status, get_newsletter_issue, insert_newsletter_issue,
+        publish_newsletter_issue,
+    },
+};
+use actix_web::{http::header::LOCATION, web, HttpResponse};
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateIssueRequest {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+fn issue_not_found() -> ApiError {
+    ApiError::NotFound("There is no newsletter issue with this id.".into())
+}
+
+/// Create a draft, it is only sent once published.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues",
+    tag = "issues",
+    security(("session" = []), ("bearer" = ["issues:write"])),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Requests retried with the same key get the first response")),
+    request_body = CreateIssueRequest,
+    responses(
+        (status = 201, body = StoredNewsletterIssue),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "Editors and owners only", body = ErrorBody),
+        (status = 409, description = "A request with the same idempotency key is in progress", body = ErrorBody),
+        (status = 422, description = "The idempotency key was used for a different request", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "Create a newsletter issue", skip_all, fields(user_id=%*user_id))]
+pub async fn api_create_issue(
+    user_id: web::ReqData<UserId>,
+    body: web::Json<CreateIssueRequest>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    require_role(&user_id, UserRole::Editor)?;
+    let CreateIssueRequest {
+        title,
+        text_content,
+        html_content,
+    } = body.0;
+    let newsletter_issue = NewsletterIssue::try_new(title, text_content, html_content)
+        .map_err(ApiError::Validation)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let issue_id = insert_newsletter_issue(&mut transaction, &newsletter_issue)
+        .await
+        .context("Failed to insert newsletter_issue into db.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the newsletter issue.")?;
+    let issue = get_newsletter_issue(issue_id, &pool)
+        .await?
+        .context("The newsletter issue was just created.")?;
+    Ok(HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/v1/issues/{}", issue_id)))
+        .json(issue))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{newsletter_issue_id}",
+    tag = "issues",
+    security(("session" = []), ("bearer" = ["issues:read"])),
+    params(("newsletter_issue_id" = Uuid, Path,)),
+    responses(
+        (status = 200, body = StoredNewsletterIssue),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "Get a newsletter issue", skip(pool))]
+pub async fn api_get_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue = get_newsletter_issue(issue_id.into_inner(), &pool)
+        .await?
+        .ok_or_else(issue_not_found)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+/// Send a draft to every confirmed subscriber.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues/{newsletter_issue_id}/publish",
+    tag = "issues",
+    security(("session" = []), ("bearer" = ["issues:write"])),
+    params(
+        ("newsletter_issue_id" = Uuid, Path,),
+        ("Idempotency-Key" = Option<String>, Header, description = "Requests retried with the same key get the first response"),
+    ),
+    responses(
+        (status = 202, description = "The emails are queued", body = DeliveryStatus),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "Editors and owners only", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "The issue was already published, or a request with the same idempotency key is in progress", body = ErrorBody),
+        (status = 422, description = "The idempotency key was used for a different request", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip(pool, client_ip),
+    fields(user_id=%*user_id)
+)]
+pub async fn api_publish_issue(
+    user_id: web::ReqData<UserId>,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, ApiError> {
+    require_role(&user_id, UserRole::Editor)?;
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    if !publish_newsletter_issue(&mut transaction, issue_id).await? {
+        return match get_newsletter_issue(issue_id, &pool).await? {
+            Some(_) => Err(ApiError::Conflict(
+                "The newsletter issue was already published.".into(),
+            )),
+            None => Err(issue_not_found()),
+        };
+    }
+    let issue = get_newsletter_issue(issue_id, &pool)
+        .await?
+        .ok_or_else(issue_not_found)?;
+    record_audit_event(
+        &mut transaction,
+        Some(**user_id),
+        AuditAction::NewsletterPublished,
+        Some(&client_ip.0),
+        serde_json::json!({ "newsletter_issue_id": issue_id, "title": issue.title }),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the publication.")?;
+    let status = get_delivery_status(issue_id, &pool)
+        .await?
+        .ok_or_else(issue_not_found)?;
+    Ok(HttpResponse::Accepted().json(status))
+}
+
+/// How many emails of an issue are still waiting to be sent.
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{newsletter_issue_id}/delivery",
+    tag = "issues",
+    security(("session" = []), ("bearer" = ["issues:read"])),
+    params(("newsletter_issue_id" = Uuid, Path,)),
+    responses(
+        (status = 200, body = DeliveryStatus),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "Get the delivery status", skip(pool))]
+pub async fn api_delivery_status(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let status = get_delivery_status(issue_id.into_inner(), &pool)
+        .await?
+        .ok_or_else(issue_not_found)?;
+    Ok(HttpResponse::Ok().json(status))
+}
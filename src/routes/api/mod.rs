@@ -0,0 +1,43 @@
+//! The JSON API, for our other services.
+//! Requests are authenticated like the admin pages, but errors are answered with an
+//! `ErrorBody` rather than a redirect.
+mod errors;
+mod issues;
+mod openapi;
+mod subscribers;
+mod subscriptions;
+
+pub use errors::{ApiError, ErrorBody};
+pub use issues::*;
+pub use openapi::{openapi_document, ApiDoc};
+pub use subscribers::*;
+pub use subscriptions::*;
+
+use crate::authentication::{UserId, UserRole};
+use actix_web::{web, HttpResponse};
+
+pub fn require_role(user_id: &UserId, required: UserRole) -> Result<(), ApiError> {
+    if user_id.role() >= required {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden)
+    }
+}
+
+/// Malformed bodies, query strings and paths get the same error format as the handlers.
+pub fn api_extractor_config(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(
+        web::JsonConfig::default().error_handler(|e, _| ApiError::Validation(e.to_string()).into()),
+    )
+    .app_data(
+        web::QueryConfig::default()
+            .error_handler(|e, _| ApiError::Validation(e.to_string()).into()),
+    )
+    .app_data(
+        web::PathConfig::default().error_handler(|e, _| ApiError::Validation(e.to_string()).into()),
+    );
+}
+
+pub async fn api_not_found() -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFound("There is no such endpoint.".into()))
+}
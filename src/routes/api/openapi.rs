@@ -0,0 +1,100 @@
+use super::{errors, issues, subscribers, subscriptions};
+use crate::newsletter_issues::{DeliveryStatus, StoredNewsletterIssue};
+use actix_web::HttpResponse;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        subscriptions::api_subscribe,
+        subscriptions::api_confirm_subscription,
+        subscribers::api_list_subscribers,
+        subscribers::api_get_subscriber,
+        issues::api_create_issue,
+        issues::api_get_issue,
+        issues::api_publish_issue,
+        issues::api_delivery_status,
+    ),
+    components(schemas(
+        errors::ErrorBody,
+        errors::ErrorDetails,
+        subscriptions::SubscribeRequest,
+        subscriptions::ConfirmRequest,
+        subscriptions::SubscriptionResponse,
+        subscribers::Subscriber,
+        subscribers::SubscriberList,
+        issues::CreateIssueRequest,
+        StoredNewsletterIssue,
+        DeliveryStatus,
+    )),
+    modifiers(&Authentication),
+    tags(
+        (name = "subscriptions", description = "Public, for signup forms"),
+        (name = "subscribers", description = "Requires authentication"),
+        (name = "issues", description = "Requires authentication"),
+    )
+)]
+pub struct ApiDoc;
+
+struct Authentication;
+
+impl Modify for Authentication {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "session",
+                SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                    "id",
+                    "The session cookie set by `POST /login`, requests changing state also need its CSRF token as `X-CSRF-Token`",
+                ))),
+            );
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .description(Some(
+                            "An API token created on `/admin/tokens`, it needs the listed scope",
+                        ))
+                        .build(),
+                ),
+            );
+        }
+    }
+}
+
+pub async fn openapi_document() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use utoipa::OpenApi;
+
+    #[test]
+    fn the_document_describes_every_endpoint() {
+        let document = ApiDoc::openapi();
+        let paths: Vec<_> = document.paths.paths.keys().map(String::as_str).collect();
+        assert_eq!(
+            paths,
+            [
+                "/api/v1/issues",
+                "/api/v1/issues/{newsletter_issue_id}",
+                "/api/v1/issues/{newsletter_issue_id}/delivery",
+                "/api/v1/issues/{newsletter_issue_id}/publish",
+                "/api/v1/subscribers",
+                "/api/v1/subscribers/{subscriber_id}",
+                "/api/v1/subscriptions",
+                "/api/v1/subscriptions/confirm",
+            ]
+        );
+        let components = document.components.unwrap();
+        assert!(components.schemas.contains_key("ErrorBody"));
+        assert!(components.security_schemes.contains_key("session"));
+        assert!(components.security_schemes.contains_key("bearer"));
+    }
+}
@@ -0,0 +1,142 @@
+use super::{require_role, ApiError};
+use crate::authentication::{UserId, UserRole};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(Deserialize, IntoParams)]
+pub struct ListSubscribersQuery {
+    /// `pending_confirmation` or `confirmed`
+    status: Option<String>,
+    /// At most 500, defaults to 50
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Subscriber {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    #[schema(example = "confirmed")]
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriberList {
+    subscribers: Vec<Subscriber>,
+    /// Subscribers matching the filter, across all pages
+    total: i64,
+}
+
+/// List the subscribers, oldest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    security(("session" = []), ("bearer" = ["subscribers:read"])),
+    params(ListSubscribersQuery),
+    responses(
+        (status = 200, body = SubscriberList),
+        (status = 400, description = "Invalid filter", body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "Editors and owners only", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "List subscribers", skip(query, pool), fields(user_id=%*user_id))]
+pub async fn api_list_subscribers(
+    user_id: web::ReqData<UserId>,
+    query: web::Query<ListSubscribersQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    require_role(&user_id, UserRole::Editor)?;
+    let ListSubscribersQuery {
+        status,
+        limit,
+        offset,
+    } = query.0;
+    if let Some(status) = &status {
+        if status != "pending_confirmation" && status != "confirmed" {
+            return Err(ApiError::Validation(format!(
+                "{} is not a valid subscription status.",
+                status
+            )));
+        }
+    }
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = offset.unwrap_or(0);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) || offset < 0 {
+        return Err(ApiError::Validation(format!(
+            "The limit must be between 1 and {} and the offset can't be negative.",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id AS subscriber_id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at, id
+        LIMIT $2 OFFSET $3
+        "#,
+        status,
+        limit,
+        offset
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscribers.")?;
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "total!" FROM subscriptions WHERE $1::TEXT IS NULL OR status = $1"#,
+        status
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to count the subscribers.")?;
+    Ok(HttpResponse::Ok().json(SubscriberList { subscribers, total }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    security(("session" = []), ("bearer" = ["subscribers:read"])),
+    params(("subscriber_id" = Uuid, Path,)),
+    responses(
+        (status = 200, body = Subscriber),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "Editors and owners only", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "Get a subscriber", skip(pool), fields(user_id=%*user_id))]
+pub async fn api_get_subscriber(
+    user_id: web::ReqData<UserId>,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    require_role(&user_id, UserRole::Editor)?;
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id AS subscriber_id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id.into_inner()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or_else(|| ApiError::NotFound("There is no subscriber with this id.".into()))?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
@@ -0,0 +1,114 @@
+use super::ApiError;
+use crate::{
+    audit_log::ClientIp,
+    domain::{EmailPolicy, NewSubscriber, SubscriptionToken},
+    email_client::EmailClient,
+    routes::{confirm_subscription, register_subscriber},
+    startup::ApplicationBaseUrl,
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Deserialize, ToSchema)]
+pub struct SubscribeRequest {
+    #[schema(example = "ursula_le_guin@gmail.com")]
+    email: String,
+    #[schema(example = "Ursula Le Guin")]
+    name: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ConfirmRequest {
+    /// The token of the link sent by email
+    subscription_token: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriptionResponse {
+    subscriber_id: Uuid,
+    #[schema(example = "pending_confirmation")]
+    status: &'static str,
+}
+
+/// Subscribe to the newsletter, a confirmation link is sent by email.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions",
+    tag = "subscriptions",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Requests retried with the same key get the first response")),
+    request_body = SubscribeRequest,
+    responses(
+        (status = 202, description = "The confirmation email was sent", body = SubscriptionResponse),
+        (status = 400, description = "Invalid email or name, or an email refused by the email policy", body = ErrorBody),
+        (status = 409, description = "The email is already subscribed, or a request with the same idempotency key is in progress", body = ErrorBody),
+        (status = 422, description = "The idempotency key was used for a different request", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(
+    name = "Adding a new subscriber through the API",
+    skip_all,
+    fields(subscriber_email=%body.email)
+)]
+pub async fn api_subscribe(
+    body: web::Json<SubscribeRequest>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    client_ip: ClientIp,
+    email_policy: web::Data<EmailPolicy>,
+) -> Result<HttpResponse, ApiError> {
+    let SubscribeRequest { email, name } = body.0;
+    let new_subscriber = NewSubscriber {
+        email: email_policy.normalize(email.parse().map_err(ApiError::Validation)?),
+        name: name.parse().map_err(ApiError::Validation)?,
+    };
+    email_policy
+        .check(&new_subscriber.email)
+        .map_err(ApiError::Validation)?;
+    let subscriber_id = register_subscriber(
+        new_subscriber,
+        None,
+        &client_ip,
+        &pool,
+        &email_client,
+        &base_url.0,
+    )
+    .await?;
+    Ok(HttpResponse::Accepted().json(SubscriptionResponse {
+        subscriber_id,
+        status: "pending_confirmation",
+    }))
+}
+
+/// Confirm a subscription with the token sent by email.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/confirm",
+    tag = "subscriptions",
+    request_body = ConfirmRequest,
+    responses(
+        (status = 200, description = "The subscription is confirmed", body = SubscriptionResponse),
+        (status = 400, description = "The token is malformed", body = ErrorBody),
+        (status = 401, description = "The token is unknown", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "Confirm a subscriber through the API", skip_all)]
+pub async fn api_confirm_subscription(
+    body: web::Json<ConfirmRequest>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, ApiError> {
+    let token: SubscriptionToken = body
+        .0
+        .subscription_token
+        .parse()
+        .map_err(ApiError::Validation)?;
+    let subscriber_id = confirm_subscription(&token, &client_ip, &pool).await?;
+    Ok(HttpResponse::Ok().json(SubscriptionResponse {
+        subscriber_id,
+        status: "confirmed",
+    }))
+}
@@ -0,0 +1,64 @@
+use crate::{
+    authentication::SignedInvitation,
+    routes::TEMPLATES,
+    startup::HmacSecret,
+    utils::{e500, see_other},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::{Context, Result};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub async fn accept_invitation_form(
+    invitation: web::Query<SignedInvitation>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match invitation.verify(&hmac_secret.0) {
+        Ok(invitation_id) => get_pending_invitation_email(invitation_id, &pool)
+            .await
+            .map_err(e500)?,
+        Err(_) => None,
+    };
+    let email = match email {
+        Some(email) => email,
+        None => {
+            FlashMessage::error("The invitation link is invalid or has expired.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        context.insert("email", &email);
+        context.insert("invitation_id", &invitation.invitation_id.to_string());
+        context.insert("expires_at", &invitation.expires_at);
+        context.insert("signature", &invitation.signature);
+        TEMPLATES.render("invitation.html", &context).unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[tracing::instrument(name = "Get pending invitation", skip(pool))]
+async fn get_pending_invitation_email(
+    invitation_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<String>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM user_invitations
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND expires_at > now()
+        "#,
+        invitation_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the invitation.")?;
+    Ok(row.map(|r| r.email))
+}
@@ -0,0 +1,133 @@
+use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    authentication::{
+        insert_user, validate_new_password, validate_username, SignedInvitation, UserRole,
+    },
+    configuration::PasswordHashingSettings,
+    startup::HmacSecret,
+    utils::{e500, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct FormData {
+    invitation_id: Uuid,
+    expires_at: i64,
+    signature: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(form, pool, hmac_secret, hashing, client_ip),
+    fields(invitation_id=%form.invitation_id, username=%form.username)
+)]
+pub async fn accept_invitation(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    hashing: web::Data<PasswordHashingSettings>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        invitation_id,
+        expires_at,
+        signature,
+        username,
+        password,
+        password_check,
+    } = form.0;
+    let invitation = SignedInvitation {
+        invitation_id,
+        expires_at,
+        signature,
+    };
+    if let Err(e) = invitation.verify(&hmac_secret.0) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/login"));
+    }
+    let validation = if password.expose_secret() != password_check.expose_secret() {
+        Err("You entered two different passwords - the field values must match.".to_string())
+    } else {
+        validate_username(&username).and_then(|_| validate_new_password(&password))
+    };
+    if let Err(e) = validation {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&invitation.path()));
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let (email, role) = match mark_invitation_accepted(&mut transaction, invitation_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => {
+            FlashMessage::error("The invitation link is invalid or has expired.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+    // Dropping the transaction on failure keeps the invitation pending
+    let user_id = match insert_user(
+        &mut transaction,
+        &username,
+        Some(&email),
+        password,
+        role,
+        &hashing,
+    )
+    .await
+    {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other(&invitation.path()));
+        }
+    };
+    record_audit_event(
+        &mut transaction,
+        Some(user_id),
+        AuditAction::InvitationAccepted,
+        Some(&client_ip.0),
+        serde_json::json!({ "invitation_id": invitation_id, "role": role }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new user.")
+        .map_err(e500)?;
+    FlashMessage::info("Your account has been created, you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+/// Returns the email and role of the invitation,
+/// or `None` if it was already used or has expired.
+#[tracing::instrument(name = "Mark invitation as accepted", skip(transaction))]
+async fn mark_invitation_accepted(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_id: Uuid,
+) -> Result<Option<(String, UserRole)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET accepted_at = now()
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND expires_at > now()
+        RETURNING email, role
+        "#,
+        invitation_id
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to mark the invitation as accepted.")?;
+    row.map(|r| Ok((r.email, r.role.parse().map_err(anyhow::Error::msg)?)))
+        .transpose()
+}
@@ -1,21 +1,26 @@
-use crate::{routes::TEMPLATES, session_state::TypedSession, utils::see_other};
+use crate::{
+    routes::TEMPLATES,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 
 pub async fn login_form(
     flash_messages: IncomingFlashMessages,
     session: TypedSession,
-) -> HttpResponse {
+) -> Result<HttpResponse, actix_web::Error> {
     if session.is_logged() {
-        return see_other("/admin/dashboard");
+        return Ok(see_other("/admin/dashboard"));
     }
     let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
     let html_body = {
         let mut context = tera::Context::new();
         context.insert("flash_msgs", &flash_msgs);
+        context.insert("csrf_token", &session.csrf_token().map_err(e500)?);
         TEMPLATES.render("login.html", &context).unwrap()
     };
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(html_body)
+        .body(html_body))
 }
@@ -1,14 +1,21 @@
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    audit_log::{record_audit_event, AuditAction},
+    authentication::{
+        create_user_session, is_two_factor_enabled, record_lockout, validate_credentials,
+        AuthError, Credentials, LoginThrottle,
+    },
+    configuration::PasswordHashingSettings,
     error_chain_fmt,
-    session_state::TypedSession,
+    session_state::{PendingTwoFactor, TypedSession},
     utils::see_other,
 };
-use actix_web::{error::InternalError, web, HttpResponse};
+use actix_web::{error::InternalError, http::header, web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
 use secrecy::Secret;
 use serde::Deserialize;
 use sqlx::PgPool;
+use uuid::Uuid;
 
 #[derive(Deserialize)]
 pub struct FormData {
@@ -31,31 +38,68 @@ impl std::fmt::Debug for LoginError {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, throttle, hashing, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    hashing: web::Data<PasswordHashingSettings>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let username = form.0.username;
     let credentials = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &pool).await {
+    tracing::Span::current().record("username", &tracing::field::display(&username));
+    let client_ip = throttle.client_ip(&request);
+    // Locked out attempts get the same answer as a wrong password, without checking it
+    if throttle.is_locked(&username, &client_ip).await {
+        audit_locked_out_login(&throttle, None, &username, &client_ip, &pool).await;
+        let e = anyhow::anyhow!("Too many failed login attempts");
+        return Err(login_redirect(LoginError::AuthError(e)));
+    }
+    match validate_credentials(credentials, &hashing, &pool).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            let two_factor = is_two_factor_enabled(user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            if two_factor {
+                session.renew();
+                session
+                    .insert_pending_two_factor(&PendingTwoFactor {
+                        user_id,
+                        username,
+                        started_at: Utc::now().timestamp(),
+                        failed_attempts: 0,
+                    })
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(see_other("/login/two_factor"));
+            }
+            throttle.clear(&username).await;
+            start_session(&session, user_id, &client_ip, &request, &pool)
+                .await
+                .map_err(login_redirect)?;
             Ok(see_other("/admin/dashboard"))
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    record_failed_login(
+                        &throttle,
+                        None,
+                        &username,
+                        &client_ip,
+                        "invalid_credentials",
+                        &pool,
+                    )
+                    .await;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             Err(login_redirect(e))
@@ -63,8 +107,103 @@ pub async fn login(
     }
 }
 
+/// Record the new session and log the user in with it.
+pub(super) async fn start_session(
+    session: &TypedSession,
+    user_id: Uuid,
+    client_ip: &str,
+    request: &HttpRequest,
+    pool: &PgPool,
+) -> Result<(), LoginError> {
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("");
+    let session_id = create_user_session(user_id, client_ip, user_agent, pool).await?;
+    record_audit_event(
+        pool,
+        Some(user_id),
+        AuditAction::LoginSucceeded,
+        Some(client_ip),
+        serde_json::json!({ "session_id": session_id }),
+    )
+    .await?;
+    session
+        .log_in(user_id, session_id)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))
+}
+
+/// Count the failure, record the lockouts it starts and slow the client down.
+pub(super) async fn record_failed_login(
+    throttle: &LoginThrottle,
+    actor_id: Option<Uuid>,
+    username: &str,
+    client_ip: &str,
+    reason: &str,
+    pool: &PgPool,
+) {
+    audit_failed_login(actor_id, username, client_ip, reason, pool).await;
+    let failure = throttle.record_failure(username, client_ip).await;
+    let lockouts = [
+        (failure.username_locked, Some(username), None),
+        (failure.ip_locked, None, Some(client_ip)),
+    ];
+    for (_, username, client_ip) in lockouts.iter().filter(|l| l.0) {
+        tracing::warn!(
+            ?username,
+            ?client_ip,
+            "Login locked out after too many failures."
+        );
+        if let Err(e) =
+            record_lockout(*username, *client_ip, throttle.lockout_duration(), pool).await
+        {
+            tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to record the lockout.");
+        }
+    }
+    tokio::time::sleep(failure.delay).await;
+}
+
+/// Only the first attempt of a lockout is audited, the following ones are just traced
+/// so that a client hammering a locked account can't grow the audit log.
+pub(super) async fn audit_locked_out_login(
+    throttle: &LoginThrottle,
+    actor_id: Option<Uuid>,
+    username: &str,
+    client_ip: &str,
+    pool: &PgPool,
+) {
+    if throttle.record_locked_attempt(username, client_ip).await {
+        audit_failed_login(actor_id, username, client_ip, "locked_out", pool).await;
+    } else {
+        tracing::info!(username, client_ip, "Locked out login attempt.");
+    }
+}
+
+/// Failing to audit a rejected attempt must not change the answer, so errors are only logged.
+async fn audit_failed_login(
+    actor_id: Option<Uuid>,
+    username: &str,
+    client_ip: &str,
+    reason: &str,
+    pool: &PgPool,
+) {
+    let payload = serde_json::json!({ "username": username, "reason": reason });
+    if let Err(e) = record_audit_event(
+        pool,
+        actor_id,
+        AuditAction::LoginFailed,
+        Some(client_ip),
+        payload,
+    )
+    .await
+    {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to audit the login failure.");
+    }
+}
+
 /// Redirect to the login page with an error message.
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+pub(super) fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     InternalError::from_response(e, see_other("/login"))
 }
@@ -0,0 +1,130 @@
+use super::post::{
+    audit_locked_out_login, login_redirect, record_failed_login, start_session, LoginError,
+};
+use crate::{
+    authentication::{verify_second_factor, LoginThrottle, TotpCipher},
+    routes::TEMPLATES,
+    session_state::{PendingTwoFactor, TypedSession},
+    utils::see_other,
+};
+use actix_web::{error::InternalError, http::header::ContentType, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+/// How long the user has to enter the code after the password check
+const PENDING_EXPIRATION_SECS: i64 = 300;
+const MAX_FAILED_ATTEMPTS: u8 = 5;
+
+pub async fn two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    if get_pending_two_factor(&session).is_none() {
+        return Err(restart_login());
+    }
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let csrf_token = session
+        .csrf_token()
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        context.insert("csrf_token", &csrf_token);
+        TEMPLATES.render("login_two_factor.html", &context).unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify two-factor login",
+    skip(form, pool, cipher, session, throttle, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn verify_two_factor(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    cipher: web::Data<TotpCipher>,
+    session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let mut pending = get_pending_two_factor(&session).ok_or_else(restart_login)?;
+    tracing::Span::current().record("user_id", &tracing::field::display(&pending.user_id));
+    let client_ip = throttle.client_ip(&request);
+    if throttle.is_locked(&pending.username, &client_ip).await {
+        session.remove_pending_two_factor();
+        audit_locked_out_login(
+            &throttle,
+            Some(pending.user_id),
+            &pending.username,
+            &client_ip,
+            &pool,
+        )
+        .await;
+        let e = anyhow::anyhow!("Too many failed login attempts");
+        return Err(login_redirect(LoginError::AuthError(e)));
+    }
+    let is_valid = verify_second_factor(pending.user_id, &form.0.code, &cipher, &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if is_valid {
+        throttle.clear(&pending.username).await;
+        start_session(&session, pending.user_id, &client_ip, &request, &pool)
+            .await
+            .map_err(login_redirect)?;
+        return Ok(see_other("/admin/dashboard"));
+    }
+    record_failed_login(
+        &throttle,
+        Some(pending.user_id),
+        &pending.username,
+        &client_ip,
+        "invalid_two_factor_code",
+        &pool,
+    )
+    .await;
+    pending.failed_attempts += 1;
+    if pending.failed_attempts >= MAX_FAILED_ATTEMPTS {
+        session.remove_pending_two_factor();
+        let e = anyhow::anyhow!("Too many failed two-factor attempts");
+        FlashMessage::error("Too many failed attempts, please log in again.").send();
+        return Err(InternalError::from_response(
+            LoginError::AuthError(e),
+            see_other("/login"),
+        ));
+    }
+    session
+        .insert_pending_two_factor(&pending)
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+    FlashMessage::error("The authentication code is incorrect.").send();
+    Ok(see_other("/login/two_factor"))
+}
+
+/// Only a recent login that passed the password check can get to the second step.
+fn get_pending_two_factor(session: &TypedSession) -> Option<PendingTwoFactor> {
+    match session.get_pending_two_factor() {
+        Ok(Some(pending))
+            if Utc::now().timestamp() - pending.started_at < PENDING_EXPIRATION_SECS =>
+        {
+            Some(pending)
+        }
+        _ => {
+            session.remove_pending_two_factor();
+            None
+        }
+    }
+}
+
+fn restart_login() -> InternalError<LoginError> {
+    let e = anyhow::anyhow!("There is no pending two-factor login");
+    InternalError::from_response(LoginError::AuthError(e), see_other("/login"))
+}
@@ -0,0 +1,8 @@
+use crate::metrics::Metrics;
+use actix_web::{web, HttpResponse};
+
+pub async fn metrics_route(metrics: web::Data<Metrics>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render())
+}
@@ -1,15 +1,23 @@
 mod admin;
+mod api;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
+mod metrics;
+mod password_reset;
 mod subscriptions;
 mod subscriptions_confirm;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
+pub use metrics::*;
 use once_cell::sync::Lazy;
+pub use password_reset::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
 use tera::Tera;
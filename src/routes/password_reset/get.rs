@@ -0,0 +1,55 @@
+use crate::{
+    authentication::is_valid_password_reset_token,
+    routes::TEMPLATES,
+    utils::{e500, see_other},
+};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+pub async fn password_reset_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        TEMPLATES.render("password_reset.html", &context).unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[derive(Deserialize)]
+pub struct Parameters {
+    token: Secret<String>,
+}
+
+pub async fn password_reset_confirm_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !is_valid_password_reset_token(&parameters.token, &pool)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The password reset link is invalid or has expired.").send();
+        return Ok(see_other("/login"));
+    }
+    let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("flash_msgs", &flash_msgs);
+        context.insert("token", parameters.token.expose_secret());
+        TEMPLATES
+            .render("password_reset_confirm.html", &context)
+            .unwrap()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
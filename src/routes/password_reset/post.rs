@@ -0,0 +1,162 @@
+use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    authentication::{
+        create_password_reset_token, get_password_reset_recipient, reset_password,
+        validate_new_password,
+    },
+    configuration::{AccountSettings, PasswordHashingSettings},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    routes::TEMPLATES,
+    startup::ApplicationBaseUrl,
+    utils::{e500, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::Instrument;
+
+#[derive(Deserialize)]
+pub struct RequestFormData {
+    username: String,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, pool, email_client, base_url, account_settings),
+    fields(username=%form.username)
+)]
+pub async fn request_password_reset(
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    account_settings: web::Data<AccountSettings>,
+) -> HttpResponse {
+    // Sending the email in the background keeps the response time the same whether the
+    // user exists or not
+    tokio::spawn(
+        async move {
+            if let Err(e) = send_password_reset(
+                &form.0.username,
+                &pool,
+                &email_client,
+                &base_url.0,
+                &account_settings,
+            )
+            .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a password reset email."
+                );
+            }
+        }
+        .in_current_span(),
+    );
+    FlashMessage::info(
+        "If the account exists and has an email address, a password reset link was sent to it.",
+    )
+    .send();
+    see_other("/login")
+}
+
+async fn send_password_reset(
+    username: &str,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    account_settings: &AccountSettings,
+) -> Result<(), anyhow::Error> {
+    let recipient = match get_password_reset_recipient(username, pool).await? {
+        Some(recipient) => recipient,
+        None => {
+            tracing::info!("No active user with an email address, skipping the password reset.");
+            return Ok(());
+        }
+    };
+    let email: SubscriberEmail = recipient.email.parse().map_err(anyhow::Error::msg)?;
+    let expires_at =
+        Utc::now() + chrono::Duration::from_std(account_settings.password_reset_expiration())?;
+    let token = create_password_reset_token(recipient.user_id, expires_at, pool).await?;
+    let reset_link = format!(
+        "{}/password_reset/confirm?token={}",
+        base_url,
+        token.expose_secret()
+    );
+    let plain_body = format!(
+        "Somebody asked to reset your password.\nVisit {} to choose a new one, \
+        or ignore this email if it wasn't you.",
+        reset_link
+    );
+    let html_body = {
+        let mut context = tera::Context::new();
+        context.insert("reset_link", &reset_link);
+        TEMPLATES
+            .render("password_reset_email.html", &context)
+            .context("Failed to construct the HTML email template.")?
+    };
+    email_client
+        .send_email(&email, "Reset your password", &html_body, &plain_body)
+        .await
+        .context("Failed to send a password reset email.")
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmFormData {
+    token: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(name = "Confirm a password reset", skip_all)]
+pub async fn confirm_password_reset(
+    form: web::Form<ConfirmFormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ConfirmFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form.0;
+    let form_path = format!("/password_reset/confirm?token={}", token.expose_secret());
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return Ok(see_other(&form_path));
+    }
+    if let Err(e) = validate_new_password(&new_password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&form_path));
+    }
+    let user_id = match reset_password(&token, new_password, &hashing, &pool)
+        .await
+        .map_err(e500)?
+    {
+        Some(user_id) => user_id,
+        None => {
+            FlashMessage::error("The password reset link is invalid or has expired.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+    record_audit_event(
+        pool.get_ref(),
+        Some(user_id),
+        AuditAction::PasswordReset,
+        Some(&client_ip.0),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info("Your password has been reset, you can now log in.").send();
+    Ok(see_other("/login"))
+}
@@ -0,0 +1,239 @@
+use crate::{
+    authentication::{sign, verify_signature},
+    configuration::BotProtectionSettings,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+
+/// Why a subscription form was taken for a bot.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotRejection {
+    /// The field hidden from people was filled
+    #[error("The form could not be verified, please submit it again.")]
+    Honeypot,
+    #[error("The form could not be verified, please submit it again.")]
+    InvalidFormSignature,
+    #[error("The form was submitted too quickly, please try again.")]
+    TooFast,
+    #[error("The form has expired, please submit it again.")]
+    ExpiredForm,
+    #[error("The form could not be verified, please submit it again.")]
+    ProofOfWork,
+    #[error("The form was already submitted, please fill it in again.")]
+    ReusedForm,
+}
+
+impl BotRejection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotRejection::Honeypot => "honeypot",
+            BotRejection::InvalidFormSignature => "invalid_form_signature",
+            BotRejection::TooFast => "too_fast",
+            BotRejection::ExpiredForm => "expired_form",
+            BotRejection::ProofOfWork => "proof_of_work",
+            BotRejection::ReusedForm => "reused_form",
+        }
+    }
+}
+
+/// The fields added to the subscription form to tell people from bots.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct BotCheck {
+    /// Honeypot, people leave it empty since they can't see it
+    pub website: String,
+    /// Unix timestamp at which the form was served
+    pub served_at: String,
+    /// Random, so that each form served gets its own signature
+    pub form_id: String,
+    pub form_signature: String,
+    pub pow_nonce: String,
+}
+
+impl BotCheck {
+    /// The fields of a form served at `served_at`.
+    pub fn new(served_at: i64, secret: &Secret<String>) -> Self {
+        let form_id = uuid::Uuid::new_v4().to_string();
+        Self {
+            served_at: served_at.to_string(),
+            form_signature: sign(secret, &Self::payload(served_at, &form_id)),
+            form_id,
+            ..Default::default()
+        }
+    }
+
+    fn payload(served_at: i64, form_id: &str) -> String {
+        format!("subscription_form:{}:{}", served_at, form_id)
+    }
+
+    /// Check a form submitted at `now` for `email`, before it is acted upon.
+    pub fn verify(
+        &self,
+        secret: &Secret<String>,
+        settings: &BotProtectionSettings,
+        email: &str,
+        now: i64,
+    ) -> Result<VerifiedForm, BotRejection> {
+        if !self.website.is_empty() {
+            return Err(BotRejection::Honeypot);
+        }
+        let served_at = self
+            .served_at
+            .parse::<i64>()
+            .map_err(|_| BotRejection::InvalidFormSignature)?;
+        if !verify_signature(
+            secret,
+            &Self::payload(served_at, &self.form_id),
+            &self.form_signature,
+        ) {
+            return Err(BotRejection::InvalidFormSignature);
+        }
+        let fill_secs = now - served_at;
+        if fill_secs < settings.min_fill_secs as i64 {
+            return Err(BotRejection::TooFast);
+        }
+        if fill_secs > settings.max_form_age_secs as i64 {
+            return Err(BotRejection::ExpiredForm);
+        }
+        if leading_zero_bits(&self.proof_of_work_hash(email, &self.pow_nonce))
+            < settings.proof_of_work_bits
+        {
+            return Err(BotRejection::ProofOfWork);
+        }
+        Ok(VerifiedForm {
+            form_signature: self.form_signature.clone(),
+            expires_at: Utc.timestamp(served_at + settings.max_form_age_secs as i64, 0),
+        })
+    }
+
+    /// The browser looks for a nonce giving a hash with enough leading zero bits, see
+    /// `subscriptions.html`. The email is part of it, so a solved form can't be reused
+    /// for other addresses.
+    fn proof_of_work_hash(&self, email: &str, nonce: &str) -> Vec<u8> {
+        let input = format!(
+            "{}:{}:{}:{}",
+            self.served_at, self.form_signature, email, nonce
+        );
+        Sha256::digest(input.as_bytes()).to_vec()
+    }
+}
+
+/// A form that passed the checks, it still has to be accepted once only.
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifiedForm {
+    form_signature: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl VerifiedForm {
+    /// Record the form as submitted along with the subscription, so that it is only used up
+    /// once the subscription is stored. Returns `false` if it was already submitted.
+    /// The record is kept until the form expires anyway.
+    #[tracing::instrument(name = "Recording a subscription form as used", skip_all)]
+    pub async fn mark_used(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<bool, sqlx::Error> {
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO used_subscription_forms (form_signature, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            self.form_signature,
+            self.expires_at
+        )
+        .execute(transaction)
+        .await?
+        .rows_affected();
+        Ok(inserted == 1)
+    }
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in bytes {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+    fn secret() -> Secret<String> {
+        Secret::new("some-secret".to_string())
+    }
+
+    fn settings(proof_of_work_bits: u32) -> BotProtectionSettings {
+        BotProtectionSettings {
+            min_fill_secs: 3,
+            max_form_age_secs: 3600,
+            proof_of_work_bits,
+        }
+    }
+
+    #[test]
+    fn forms_filled_by_people_are_accepted() {
+        let check = BotCheck::new(1000, &secret());
+        let form = check.verify(&secret(), &settings(0), EMAIL, 1010).unwrap();
+        // Recorded as used until the form expires anyway
+        assert_eq!(form.expires_at.timestamp(), 1000 + 3600);
+    }
+
+    #[test]
+    fn forms_filled_by_bots_are_rejected() {
+        let check = |f: fn(&mut BotCheck)| {
+            let mut check = BotCheck::new(1000, &secret());
+            f(&mut check);
+            check.verify(&secret(), &settings(0), EMAIL, 1010)
+        };
+        let honeypot = check(|c| c.website = "https://spam.example".into());
+        assert_eq!(honeypot, Err(BotRejection::Honeypot));
+        let backdated = check(|c| c.served_at = "900".into());
+        assert_eq!(backdated, Err(BotRejection::InvalidFormSignature));
+        let other_form = check(|c| c.form_id = uuid::Uuid::new_v4().to_string());
+        assert_eq!(other_form, Err(BotRejection::InvalidFormSignature));
+        let missing = check(|c| *c = BotCheck::default());
+        assert_eq!(missing, Err(BotRejection::InvalidFormSignature));
+        let too_fast = BotCheck::new(1009, &secret()).verify(&secret(), &settings(0), EMAIL, 1010);
+        assert_eq!(too_fast, Err(BotRejection::TooFast));
+        let expired = BotCheck::new(1000, &secret()).verify(&secret(), &settings(0), EMAIL, 5000);
+        assert_eq!(expired, Err(BotRejection::ExpiredForm));
+    }
+
+    #[test]
+    fn the_proof_of_work_needs_enough_leading_zero_bits() {
+        let mut check = BotCheck::new(1000, &secret());
+        let nonce = (0..)
+            .map(|n: u32| n.to_string())
+            .find(|n| leading_zero_bits(&check.proof_of_work_hash(EMAIL, n)) >= 8)
+            .unwrap();
+        assert_eq!(
+            check.verify(&secret(), &settings(8), EMAIL, 1010),
+            Err(BotRejection::ProofOfWork)
+        );
+        check.pow_nonce = nonce;
+        assert!(check.verify(&secret(), &settings(8), EMAIL, 1010).is_ok());
+        // The nonce was found for one email and doesn't vouch for others
+        assert!((0..16).any(|i| {
+            let email = format!("bot{}@example.com", i);
+            check.verify(&secret(), &settings(8), &email, 1010) == Err(BotRejection::ProofOfWork)
+        }));
+    }
+
+    #[test]
+    fn leading_zero_bits_are_counted_across_bytes() {
+        assert_eq!(leading_zero_bits(&[0x00, 0x10, 0xff]), 11);
+        assert_eq!(leading_zero_bits(&[0x80]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+}
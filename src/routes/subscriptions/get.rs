@@ -1,11 +1,24 @@
-use crate::routes::TEMPLATES;
-use actix_web::{http::header::ContentType, HttpResponse};
+use super::bot_protection::BotCheck;
+use crate::{configuration::BotProtectionSettings, routes::TEMPLATES, startup::HmacSecret};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::Utc;
 
-pub async fn subscriptions_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn subscriptions_form(
+    flash_messages: IncomingFlashMessages,
+    hmac_secret: web::Data<HmacSecret>,
+    bot_protection: web::Data<BotProtectionSettings>,
+) -> HttpResponse {
     let flash_msgs = flash_messages.iter().collect::<Vec<_>>();
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let bot_check = BotCheck::new(Utc::now().timestamp(), &hmac_secret.0);
     let html_body = {
         let mut context = tera::Context::new();
+        context.insert("idempotency_key", &idempotency_key);
+        context.insert("served_at", &bot_check.served_at);
+        context.insert("form_id", &bot_check.form_id);
+        context.insert("form_signature", &bot_check.form_signature);
+        context.insert("proof_of_work_bits", &bot_protection.proof_of_work_bits);
         context.insert("flash_msgs", &flash_msgs);
         TEMPLATES.render("subscriptions.html", &context).unwrap()
     };
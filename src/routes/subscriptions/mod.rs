@@ -1,5 +1,6 @@
+mod bot_protection;
 mod get;
 mod post;
 
 pub use get::subscriptions_form;
-pub use post::subscribe;
+pub use post::{register_subscriber, subscribe, subscribe_idempotency, SubscribeError};
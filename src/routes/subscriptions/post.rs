@@ -1,8 +1,14 @@
+use super::bot_protection::{BotCheck, BotRejection, VerifiedForm};
 use crate::{
-    domain::{NewSubscriber, SubscriptionToken},
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    configuration::BotProtectionSettings,
+    domain::{EmailPolicy, NewSubscriber, SubscriptionToken},
     email_client::EmailClient,
     error_chain_fmt,
+    idempotency::IdempotencyConfig,
+    metrics::Metrics,
     routes::TEMPLATES,
+    startup::HmacSecret,
     utils::see_other,
     ApplicationBaseUrl,
 };
@@ -18,6 +24,8 @@ use uuid::Uuid;
 pub struct FormData {
     email: String,
     name: String,
+    #[serde(flatten)]
+    bot_check: BotCheck,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -34,6 +42,8 @@ impl TryFrom<FormData> for NewSubscriber {
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error(transparent)]
+    RejectedForm(#[from] BotRejection),
     #[error("{msg} already exists, use another email.")]
     DuplicatedEmail {
         msg: String,
@@ -55,9 +65,20 @@ impl From<String> for SubscribeError {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(
+        form,
+        pool,
+        email_client,
+        base_url,
+        client_ip,
+        hmac_secret,
+        bot_protection,
+        metrics,
+        email_policy
+    ),
     fields(
         subcriber_email = tracing::field::Empty,
         subcriber_name = tracing::field::Empty
@@ -68,6 +89,11 @@ pub async fn subscribe(
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    client_ip: ClientIp,
+    hmac_secret: web::Data<HmacSecret>,
+    bot_protection: web::Data<BotProtectionSettings>,
+    metrics: web::Data<Metrics>,
+    email_policy: web::Data<EmailPolicy>,
 ) -> Result<HttpResponse, InternalError<SubscribeError>> {
     let form = match form {
         Ok(f) => {
@@ -78,51 +104,128 @@ pub async fn subscribe(
         Err(e) => Err(e.to_string()),
     }
     .map_err(subscriptions_redirect)?;
-    let new_subscriber = form.try_into().map_err(subscriptions_redirect)?;
+    let bot_check = form.bot_check.verify(
+        &hmac_secret.0,
+        &bot_protection,
+        &form.email,
+        Utc::now().timestamp(),
+    );
+    let mut new_subscriber: NewSubscriber = form.try_into().map_err(subscriptions_redirect)?;
+    new_subscriber.email = email_policy.normalize(new_subscriber.email);
+    let subscriber_email = new_subscriber.email.to_string();
+    let verified_form = match bot_check {
+        Ok(verified_form) => verified_form,
+        Err(BotRejection::Honeypot) => {
+            record_rejection(&metrics, BotRejection::Honeypot);
+            // Bots filling the honeypot are told they succeeded, so they don't adapt
+            FlashMessage::info(format!(
+                "A confirmation email was sent to {}",
+                subscriber_email
+            ))
+            .send();
+            return Ok(see_other("/subscriptions"));
+        }
+        Err(rejection) => {
+            record_rejection(&metrics, rejection);
+            return Err(subscriptions_redirect(SubscribeError::RejectedForm(
+                rejection,
+            )));
+        }
+    };
+    email_policy
+        .check(&new_subscriber.email)
+        .map_err(subscriptions_redirect)?;
+    register_subscriber(
+        new_subscriber,
+        Some(&verified_form),
+        &client_ip,
+        &pool,
+        &email_client,
+        &base_url.0,
+    )
+    .await
+    .map_err(|e| {
+        if let SubscribeError::RejectedForm(rejection) = &e {
+            record_rejection(&metrics, *rejection);
+        }
+        subscriptions_redirect(e)
+    })?;
+    FlashMessage::info(format!(
+        "A confirmation email was sent to {}",
+        subscriber_email
+    ))
+    .send();
+    Ok(see_other("/subscriptions"))
+}
+
+fn record_rejection(metrics: &Metrics, rejection: BotRejection) {
+    tracing::warn!(reason = rejection.as_str(), "Rejected a subscription form.");
+    metrics.record_rejected_subscription(rejection.as_str());
+}
+
+/// The form carries an idempotency key, so submitting it twice sends one confirmation email.
+pub fn subscribe_idempotency() -> IdempotencyConfig {
+    IdempotencyConfig::default()
+        .on_replay(|| FlashMessage::info("A confirmation email was sent.").send())
+}
+
+/// Store a pending subscriber and send them a confirmation link.
+/// Subscribing again before confirming sends a new link.
+/// `form` is used up only if the subscriber is stored and the email sent, so that a failed
+/// request can be submitted again.
+pub async fn register_subscriber(
+    new_subscriber: NewSubscriber,
+    form: Option<&VerifiedForm>,
+    client_ip: &ClientIp,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<Uuid, SubscribeError> {
     let mut transaction = pool
         .begin()
         .await
-        .context("Failed to acquire a Postgres connection from the pool.")
-        .map_err(subscriptions_redirect)?;
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    if let Some(form) = form {
+        if !form
+            .mark_used(&mut transaction)
+            .await
+            .context("Failed to record the subscription form as used.")?
+        {
+            return Err(BotRejection::ReusedForm.into());
+        }
+    }
     let subscriber_id = match check_existing_pending_subscriber(&mut transaction, &new_subscriber)
         .await
-        .context("Failed to check if new subscriber is present in the database.")
-        .map_err(subscriptions_redirect)?
+        .context("Failed to check if new subscriber is present in the database.")?
     {
         Some(id) => id,
         None => {
-            let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-                .await
-                .map_err(subscriptions_redirect)?;
+            let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber).await?;
+            record_audit_event(
+                &mut transaction,
+                None,
+                AuditAction::SubscriberAdded,
+                Some(&client_ip.0),
+                serde_json::json!({
+                    "subscriber_id": subscriber_id,
+                    "email": new_subscriber.email.as_ref(),
+                }),
+            )
+            .await?;
             subscriber_id
         }
     };
     let subscription_token = SubscriptionToken::new();
     store_token(&mut transaction, subscriber_id, subscription_token.as_ref())
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")
-        .map_err(subscriptions_redirect)?;
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+    // Sent before committing, a failed email leaves nothing stored
+    send_confirmation_email(email_client, new_subscriber, base_url, subscription_token).await?;
     transaction
         .commit()
         .await
-        .context("Failed to commit SQL transaction to store a new subscriber.")
-        .map_err(subscriptions_redirect)?;
-    let subscriber_email = new_subscriber.email.to_string();
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        subscription_token,
-    )
-    .await
-    .map_err(subscriptions_redirect)?;
-
-    FlashMessage::info(format!(
-        "A confirmation email was sent to {}",
-        subscriber_email
-    ))
-    .send();
-    Ok(see_other("/subscriptions"))
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    Ok(subscriber_id)
 }
 
 /// Redirect to the subscriptions page with an error message.
@@ -147,14 +250,14 @@ pub async fn check_existing_pending_subscriber(
         r#"
         SELECT id
         FROM subscriptions
-        wHERE email = $1 AND name = $2 AND status = 'pending_confirmation'
+        WHERE lower(email) = lower($1) AND name = $2 AND status = 'pending_confirmation'
         "#,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref()
     )
     .fetch_optional(transaction)
     .await?;
-    Ok(result.map(|r| (r.id)))
+    Ok(result.map(|r| r.id))
 }
 
 #[tracing::instrument(
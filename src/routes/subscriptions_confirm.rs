@@ -1,4 +1,8 @@
-use crate::{domain::SubscriptionToken, error_chain_fmt};
+use crate::{
+    audit_log::{record_audit_event, AuditAction, ClientIp},
+    domain::SubscriptionToken,
+    error_chain_fmt,
+};
 use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
 use anyhow::Context;
 use sqlx::PgPool;
@@ -32,17 +36,39 @@ impl ResponseError for ConfirmationError {
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, pool, client_ip)
+)]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
+    client_ip: ClientIp,
 ) -> Result<HttpResponse, ConfirmationError> {
-    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+    confirm_subscription(&parameters.subscription_token, &client_ip, &pool).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Returns the id of the confirmed subscriber.
+pub async fn confirm_subscription(
+    subscription_token: &SubscriptionToken,
+    client_ip: &ClientIp,
+    pool: &PgPool,
+) -> Result<Uuid, ConfirmationError> {
+    let subscriber_id = get_subscriber_id_from_token(pool, subscription_token)
         .await
         .context("Failed to retrieve subscriber_id associated with the provided token.")?
         .ok_or(ConfirmationError::UnkwownToken)?;
-    confirm_subscriber(&pool, subscriber_id).await?;
-    Ok(HttpResponse::Ok().finish())
+    confirm_subscriber(pool, subscriber_id).await?;
+    record_audit_event(
+        pool,
+        None,
+        AuditAction::SubscriberConfirmed,
+        Some(&client_ip.0),
+        serde_json::json!({ "subscriber_id": subscriber_id }),
+    )
+    .await?;
+    Ok(subscriber_id)
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
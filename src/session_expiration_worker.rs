@@ -0,0 +1,76 @@
+use crate::{
+    configuration::{SessionStoreSettings, Settings},
+    get_connection_pool,
+    idempotency_expiration_worker::add_jitter,
+    shutdown::Shutdown,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+
+const MAX_RETRIES: usize = 3;
+
+/// Removes the expired sessions and login failure counters kept in Postgres.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    worker_loop(connection_pool, configuration.session_store, shutdown).await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    settings: SessionStoreSettings,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let mut retries = 0;
+    let frequency = settings.expiration_frequency_secs as f32;
+    shutdown
+        .sleep(Duration::from_secs_f32(frequency / 2.0))
+        .await;
+    while !shutdown.is_triggered() {
+        if try_execute_task(&pool).await.is_err() {
+            retries += 1;
+            if retries < MAX_RETRIES {
+                shutdown
+                    .sleep(Duration::from_secs_f32(add_jitter(10.0)))
+                    .await;
+                continue;
+            } else {
+                retries = 0;
+            }
+        }
+        shutdown
+            .sleep(Duration::from_secs_f32(add_jitter(frequency)))
+            .await;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, err)]
+pub async fn try_execute_task(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let sessions = sqlx::query!(r#"DELETE FROM sessions WHERE expires_at < now()"#)
+        .execute(pool)
+        .await
+        .context("Failed to clear expired sessions from database.")?
+        .rows_affected();
+    let counters = sqlx::query!(r#"DELETE FROM login_failures WHERE expires_at < now()"#)
+        .execute(pool)
+        .await
+        .context("Failed to clear expired login failures from database.")?
+        .rows_affected();
+    let rate_limit_counters =
+        sqlx::query!(r#"DELETE FROM rate_limit_counters WHERE expires_at < now()"#)
+            .execute(pool)
+            .await
+            .context("Failed to clear expired rate limit counters from database.")?
+            .rows_affected();
+    tracing::info!(
+        "Removed {} expired sessions, {} login failure counters and {} rate limit counters.",
+        sessions,
+        counters,
+        rate_limit_counters
+    );
+    Ok(())
+}
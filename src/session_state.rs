@@ -1,12 +1,28 @@
 use actix_session::{Session, SessionExt};
 use actix_web::FromRequest;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
 use std::future::{ready, Ready};
 use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
+/// A login waiting for its second factor.
+#[derive(Serialize, Deserialize)]
+pub struct PendingTwoFactor {
+    pub user_id: Uuid,
+    /// Failed codes count against the login throttle of this username
+    pub username: String,
+    /// Unix timestamp of the password check
+    pub started_at: i64,
+    pub failed_attempts: u8,
+}
+
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const PENDING_TWO_FACTOR_KEY: &'static str = "pending_two_factor";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     pub fn new(s: Session) -> Self {
         Self(s)
@@ -24,6 +40,56 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Id of the session in the `user_sessions` table.
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    /// Start a new authenticated session for `user_id`.
+    pub fn log_in(&self, user_id: Uuid, session_id: Uuid) -> Result<(), serde_json::Error> {
+        self.renew();
+        self.0.remove(Self::PENDING_TWO_FACTOR_KEY);
+        // Tokens handed out before authenticating must not be reused after it
+        self.0.remove(Self::CSRF_TOKEN_KEY);
+        self.insert_user_id(user_id)?;
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn insert_pending_two_factor(
+        &self,
+        pending: &PendingTwoFactor,
+    ) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::PENDING_TWO_FACTOR_KEY, pending)
+    }
+
+    pub fn get_pending_two_factor(&self) -> Result<Option<PendingTwoFactor>, serde_json::Error> {
+        self.0.get(Self::PENDING_TWO_FACTOR_KEY)
+    }
+
+    pub fn remove_pending_two_factor(&self) {
+        self.0.remove(Self::PENDING_TWO_FACTOR_KEY);
+    }
+
+    /// Token that forms must send back, created the first time a form is rendered.
+    pub fn csrf_token(&self) -> Result<String, serde_json::Error> {
+        if let Some(token) = self.get_csrf_token()? {
+            return Ok(token);
+        }
+        let token: String = {
+            let mut rng = thread_rng();
+            std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(char::from)
+                .take(32)
+                .collect()
+        };
+        self.0.insert(Self::CSRF_TOKEN_KEY, &token)?;
+        Ok(token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, serde_json::Error> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
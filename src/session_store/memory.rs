@@ -0,0 +1,111 @@
+use super::{generate_session_key, SessionState};
+use crate::expiring_map::ExpiringMap;
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use std::sync::{Arc, Mutex};
+
+/// Keeps the sessions in the process memory, shared by all the workers of the server.
+#[derive(Clone, Default)]
+pub struct MemorySessionStore {
+    sessions: Arc<Mutex<ExpiringMap<SessionState>>>,
+}
+
+fn ttl(ttl: &Duration) -> std::time::Duration {
+    std::time::Duration::from_secs(ttl.whole_seconds().max(0) as u64)
+}
+
+fn insert_session(
+    sessions: &mut ExpiringMap<SessionState>,
+    session_state: SessionState,
+    ttl: &Duration,
+) -> SessionKey {
+    let session_key = generate_session_key();
+    sessions.insert(
+        session_key.as_ref().to_owned(),
+        session_state,
+        self::ttl(ttl),
+    );
+    session_key
+}
+
+#[async_trait::async_trait(?Send)]
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get(session_key.as_ref()).cloned())
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        Ok(insert_session(&mut sessions, session_state, ttl))
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(session_key.as_ref()).is_some() {
+            sessions.insert(
+                session_key.as_ref().to_owned(),
+                session_state,
+                self::ttl(ttl),
+            );
+            Ok(session_key)
+        } else {
+            Ok(insert_session(&mut sessions, session_state, ttl))
+        }
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        self.sessions.lock().unwrap().remove(session_key.as_ref());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn state() -> SessionState {
+        HashMap::from([("user_id".to_string(), "\"42\"".to_string())])
+    }
+
+    #[tokio::test]
+    async fn saved_sessions_can_be_loaded_until_they_expire() {
+        let store = MemorySessionStore::default();
+        let key = store.save(state(), &Duration::hours(1)).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), Some(state()));
+
+        let expired_key = store.save(state(), &Duration::ZERO).await.unwrap();
+        assert_eq!(store.load(&expired_key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn updating_an_expired_session_saves_it_under_a_new_key() {
+        let store = MemorySessionStore::default();
+        let expired_key = store.save(state(), &Duration::ZERO).await.unwrap();
+        let expired = expired_key.as_ref().to_owned();
+        let key = store
+            .update(expired_key, state(), &Duration::hours(1))
+            .await
+            .unwrap();
+        assert_ne!(key.as_ref(), expired);
+        assert_eq!(store.load(&key).await.unwrap(), Some(state()));
+    }
+
+    #[tokio::test]
+    async fn deleted_sessions_are_gone() {
+        let store = MemorySessionStore::default();
+        let key = store.save(state(), &Duration::hours(1)).await.unwrap();
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), None);
+    }
+}
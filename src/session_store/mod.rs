@@ -0,0 +1,99 @@
+mod memory;
+mod postgres;
+
+pub use memory::MemorySessionStore;
+pub use postgres::PostgresSessionStore;
+
+use crate::configuration::SessionStoreBackend;
+use actix_session::storage::{
+    LoadError, RedisSessionStore, SaveError, SessionKey, SessionStore, UpdateError,
+};
+use actix_web::cookie::time::Duration;
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+type SessionState = HashMap<String, String>;
+
+/// The session store picked in the configuration.
+#[derive(Clone)]
+pub enum AppSessionStore {
+    Redis(RedisSessionStore),
+    Postgres(PostgresSessionStore),
+    Memory(MemorySessionStore),
+}
+
+impl AppSessionStore {
+    pub async fn new(
+        backend: SessionStoreBackend,
+        redis_uri: &Secret<String>,
+        pool: &PgPool,
+    ) -> Result<Self, anyhow::Error> {
+        let store = match backend {
+            SessionStoreBackend::Redis => {
+                Self::Redis(RedisSessionStore::new(redis_uri.expose_secret()).await?)
+            }
+            SessionStoreBackend::Postgres => {
+                Self::Postgres(PostgresSessionStore::new(pool.clone()))
+            }
+            SessionStoreBackend::Memory => Self::Memory(MemorySessionStore::default()),
+        };
+        Ok(store)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl SessionStore for AppSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        match self {
+            Self::Redis(store) => store.load(session_key).await,
+            Self::Postgres(store) => store.load(session_key).await,
+            Self::Memory(store) => store.load(session_key).await,
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        match self {
+            Self::Redis(store) => store.save(session_state, ttl).await,
+            Self::Postgres(store) => store.save(session_state, ttl).await,
+            Self::Memory(store) => store.save(session_state, ttl).await,
+        }
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        match self {
+            Self::Redis(store) => store.update(session_key, session_state, ttl).await,
+            Self::Postgres(store) => store.update(session_key, session_state, ttl).await,
+            Self::Memory(store) => store.update(session_key, session_state, ttl).await,
+        }
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        match self {
+            Self::Redis(store) => store.delete(session_key).await,
+            Self::Postgres(store) => store.delete(session_key).await,
+            Self::Memory(store) => store.delete(session_key).await,
+        }
+    }
+}
+
+/// 64 random alphanumeric characters, as recommended by OWASP.
+fn generate_session_key() -> SessionKey {
+    std::iter::repeat_with(|| OsRng.sample(Alphanumeric))
+        .map(char::from)
+        .take(64)
+        .collect::<String>()
+        .try_into()
+        .expect("A 64 characters key is short enough for a cookie.")
+}
@@ -0,0 +1,121 @@
+use super::{generate_session_key, SessionState};
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+/// Keeps the sessions in the `sessions` table, expired ones are removed by
+/// `session_expiration_worker`.
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+    pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Only a hash is stored, so the table can't be used to hijack sessions.
+fn hash_session_key(session_key: &SessionKey) -> String {
+    hex::encode(Sha256::digest(session_key.as_ref().as_bytes()))
+}
+
+fn expires_at(ttl: &Duration) -> chrono::DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(ttl.whole_seconds())
+}
+
+#[async_trait::async_trait(?Send)]
+impl SessionStore for PostgresSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT state
+            FROM sessions
+            WHERE session_key_hash = $1 AND expires_at > now()
+            "#,
+            hash_session_key(session_key)
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to load the session.")
+        .map_err(LoadError::Other)?;
+        row.map(|r| serde_json::from_value(r.state))
+            .transpose()
+            .context("Failed to deserialize the session state.")
+            .map_err(LoadError::Deserialization)
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let state = serde_json::to_value(&session_state)
+            .context("Failed to serialize the session state.")
+            .map_err(SaveError::Serialization)?;
+        let session_key = generate_session_key();
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key_hash, state, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+            hash_session_key(&session_key),
+            state,
+            expires_at(ttl)
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to save the session.")
+        .map_err(SaveError::Other)?;
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let state = serde_json::to_value(&session_state)
+            .context("Failed to serialize the session state.")
+            .map_err(UpdateError::Serialization)?;
+        let n_updated = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET state = $2, expires_at = $3
+            WHERE session_key_hash = $1 AND expires_at > now()
+            "#,
+            hash_session_key(&session_key),
+            state,
+            expires_at(ttl)
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update the session.")
+        .map_err(UpdateError::Other)?
+        .rows_affected();
+        if n_updated > 0 {
+            return Ok(session_key);
+        }
+        // The session expired in the meantime, start a new one like the Redis store does
+        self.save(session_state, ttl).await.map_err(|e| match e {
+            SaveError::Serialization(e) => UpdateError::Serialization(e),
+            SaveError::Other(e) => UpdateError::Other(e),
+        })
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_key_hash = $1"#,
+            hash_session_key(session_key)
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete the session.")?;
+        Ok(())
+    }
+}
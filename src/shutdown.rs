@@ -0,0 +1,96 @@
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Create a linked pair of shutdown trigger and signal.
+pub fn shutdown_channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(sender), Shutdown(receiver))
+}
+
+/// Starts the shutdown of every task holding the linked `Shutdown`.
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        // Sending only fails when every `Shutdown` is gone, nobody is left to stop then
+        let _ = self.0.send(true);
+    }
+}
+
+/// Cancellation signal shared by the server and the background workers.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once the shutdown is triggered.
+    /// If the `ShutdownTrigger` is dropped without firing, it never resolves.
+    pub async fn triggered(&mut self) {
+        while !self.is_triggered() {
+            if self.0.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Sleep for `duration`, waking up early if the shutdown is triggered.
+    pub async fn sleep(&mut self, duration: Duration) {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = self.triggered() => {}
+        }
+    }
+}
+
+/// Wait for SIGTERM or Ctrl+C.
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler.");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler.")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sleep_is_interrupted_when_shutdown_is_triggered() {
+        let (trigger, mut shutdown) = shutdown_channel();
+        trigger.trigger();
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            shutdown.sleep(Duration::from_secs(60)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(shutdown.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_trigger_does_not_shutdown() {
+        let (trigger, mut shutdown) = shutdown_channel();
+        drop(trigger);
+        let result = tokio::time::timeout(Duration::from_millis(100), shutdown.triggered()).await;
+        assert!(result.is_err());
+        assert!(!shutdown.is_triggered());
+    }
+}
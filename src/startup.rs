@@ -1,23 +1,45 @@
 use crate::{
-    authentication::reject_anonymous_users,
-    configuration::{DatabaseSettings, Settings},
+    authentication::{
+        reject_anonymous_api_users, reject_anonymous_users, reject_forged_requests, require_editor,
+        require_owner, ApiScope, LoginThrottle, TotpCipher,
+    },
+    configuration::{
+        AccountSettings, BotProtectionSettings, DatabaseSettings, IdempotencySettings,
+        PasswordHashingSettings, Settings,
+    },
+    domain::EmailPolicy,
     email_client::EmailClient,
+    idempotency::{idempotent, IdempotencyConfig, IdempotencyStore},
+    metrics::Metrics,
+    rate_limit::{rate_limited, RateLimitedRoute, RateLimiter},
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, delivery_process,
-        health_check_route, home, log_out, login, login_form, not_found, publish_newsletter,
-        publish_newsletter_form, subscribe, subscriptions_form,
+        accept_invitation, accept_invitation_form, activate_user, admin_dashboard,
+        api_confirm_subscription, api_create_issue, api_delivery_status, api_extractor_config,
+        api_get_issue, api_get_subscriber, api_list_subscribers, api_not_found, api_publish_issue,
+        api_subscribe, api_tokens_page, audit_page, change_password, change_password_form, confirm,
+        confirm_password_reset, create_api_token, deactivate_user, delivery_process,
+        disable_two_factor, enable_two_factor, health_check_route, home, invite_user, log_out,
+        login, login_form, metrics_route, not_found, openapi_document, password_reset_confirm_form,
+        password_reset_form, publish_newsletter, publish_newsletter_form,
+        publish_newsletter_idempotency, request_password_reset, reset_user_two_factor,
+        revoke_api_token, revoke_other_sessions, revoke_session, sessions_page, subscribe,
+        subscribe_idempotency, subscriptions_form, two_factor_form, two_factor_page, unlock_user,
+        update_role, users_page, verify_two_factor,
     },
+    session_store::AppSessionStore,
+    shutdown::{wait_for_signal, Shutdown},
 };
-use actix_session::{storage::RedisSessionStore, SessionMiddleware};
+use actix_session::SessionMiddleware;
 use actix_web::{
+    body::MessageBody,
     cookie::Key,
-    dev::Server,
+    dev::{Server, ServiceFactory, ServiceRequest, ServiceResponse},
     web::{self, Data},
-    App, HttpServer,
+    App, HttpServer, Resource,
 };
 use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
 use actix_web_lab::middleware::from_fn;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::{net::TcpListener, time::Duration};
@@ -41,13 +63,57 @@ impl Application {
         );
         let listener = TcpListener::bind(&address)?;
         let port = listener.local_addr()?.port();
+        let backend = configuration.session_store.backend;
+        let session_store =
+            AppSessionStore::new(backend, &configuration.redis_uri, &connection_pool)
+                .await
+                .context("Failed to connect to the session store.")?;
+        let login_throttle = LoginThrottle::new(
+            backend,
+            &configuration.redis_uri,
+            &connection_pool,
+            configuration.login_throttle,
+        )
+        .await
+        .context("Failed to connect to the login failure counters.")?;
+        let rate_limiter = RateLimiter::new(
+            backend,
+            &configuration.redis_uri,
+            &connection_pool,
+            configuration.rate_limit,
+        )
+        .await
+        .context("Failed to set up the rate limit counters.")?;
+        configuration
+            .idempotency
+            .validate()
+            .context("Invalid idempotency settings.")?;
+        let public_idempotency_store = IdempotencyStore::new(
+            configuration.idempotency.public_backend,
+            &configuration.redis_uri,
+            &connection_pool,
+            &configuration.idempotency.redis_key_prefix,
+        )
+        .await
+        .context("Failed to connect to the idempotency store.")?;
+        let email_policy = EmailPolicy::new(&configuration.email_policy)
+            .context("Failed to load the email policy.")?;
         let server = run(
             listener,
             connection_pool,
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
-            configuration.redis_uri,
+            session_store,
+            configuration.application.drain_timeout_secs,
+            configuration.accounts,
+            login_throttle,
+            configuration.password_hashing,
+            configuration.idempotency,
+            public_idempotency_store,
+            rate_limiter,
+            configuration.bot_protection,
+            email_policy,
         )
         .await?;
         Ok(Self { port, server })
@@ -57,11 +123,48 @@ impl Application {
         self.port
     }
 
+    /// Run until SIGTERM or Ctrl+C, then wait for the in-flight requests to complete.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            handle.stop(true).await;
+        });
+        self.server.await
+    }
+
+    /// Stop accepting connections once `shutdown` is triggered and wait for the
+    /// in-flight requests to complete.
+    pub async fn run_until_shutdown(self, mut shutdown: Shutdown) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.triggered().await;
+            handle.stop(true).await;
+        });
         self.server.await
     }
 }
 
+/// A JSON API resource for logged in users. API tokens need `scope` to call it.
+fn api_resource(
+    path: &str,
+    scope: ApiScope,
+) -> Resource<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    web::resource(path)
+        .app_data(scope)
+        // The last middleware registered runs first
+        .wrap(from_fn(idempotent))
+        .wrap(from_fn(reject_anonymous_api_users))
+}
+
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
         .acquire_timeout(Duration::from_secs(2))
@@ -70,53 +173,226 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct HmacSecret(pub Secret<String>);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
+    session_store: AppSessionStore,
+    shutdown_timeout_secs: u64,
+    account_settings: AccountSettings,
+    login_throttle: LoginThrottle,
+    password_hashing: PasswordHashingSettings,
+    idempotency: IdempotencySettings,
+    public_idempotency_store: IdempotencyStore,
+    rate_limiter: RateLimiter,
+    bot_protection: BotProtectionSettings,
+    email_policy: EmailPolicy,
 ) -> Result<Server> {
+    let idempotency_store = Data::new(IdempotencyStore::Postgres(db_pool.clone()));
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let account_settings = Data::new(account_settings);
+    password_hashing
+        .params()
+        .context("Invalid password hashing settings.")?;
+    let password_hashing = Data::new(password_hashing);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let totp_cipher = Data::new(TotpCipher::new(&hmac_secret));
+    let hmac_secret = Data::new(HmacSecret(hmac_secret));
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
-    let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let login_throttle = Data::new(login_throttle);
+    let rate_limiter = Data::new(rate_limiter);
+    // The public endpoints have their own store and retention
+    let public_idempotency_store = Data::new(public_idempotency_store);
+    let public_idempotency_retention = idempotency.public_expiration();
+    let idempotency = Data::new(idempotency);
+    let bot_protection = Data::new(bot_protection);
+    let metrics = Data::new(Metrics::default());
+    let email_policy = Data::new(email_policy);
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
             .wrap(SessionMiddleware::new(
-                redis_store.clone(),
+                session_store.clone(),
                 secret_key.clone(),
             ))
             .wrap(TracingLogger::default())
             .route("/", web::get().to(home))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
+            .service(
+                web::scope("/login")
+                    .wrap(from_fn(reject_forged_requests))
+                    .route("", web::get().to(login_form))
+                    .route("", web::post().to(login))
+                    .route("/two_factor", web::get().to(two_factor_form))
+                    .route("/two_factor", web::post().to(verify_two_factor)),
+            )
             .route("/health_check", web::get().to(health_check_route))
-            .route("/subscribe", web::post().to(subscribe))
+            .route("/metrics", web::get().to(metrics_route))
+            .service(
+                web::resource("/subscribe")
+                    .app_data(subscribe_idempotency().retain_for(public_idempotency_retention))
+                    .app_data(public_idempotency_store.clone())
+                    .app_data(RateLimitedRoute::Subscriptions)
+                    // Replayed requests don't count against the rate limits
+                    .wrap(from_fn(rate_limited))
+                    .wrap(from_fn(idempotent))
+                    .route(web::post().to(subscribe)),
+            )
             .route("/subscriptions", web::get().to(subscriptions_form))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/invitations/accept", web::get().to(accept_invitation_form))
+            .route("/invitations/accept", web::post().to(accept_invitation))
+            .service(
+                web::resource("/password_reset")
+                    .app_data(RateLimitedRoute::PasswordReset)
+                    .wrap(from_fn(rate_limited))
+                    .route(web::get().to(password_reset_form))
+                    .route(web::post().to(request_password_reset)),
+            )
+            .route(
+                "/password_reset/confirm",
+                web::get().to(password_reset_confirm_form),
+            )
+            .route(
+                "/password_reset/confirm",
+                web::post().to(confirm_password_reset),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .configure(api_extractor_config)
+                    .route("/openapi.json", web::get().to(openapi_document))
+                    .service(
+                        web::resource("/subscriptions")
+                            .app_data(
+                                IdempotencyConfig::default()
+                                    .retain_for(public_idempotency_retention),
+                            )
+                            .app_data(public_idempotency_store.clone())
+                            .app_data(RateLimitedRoute::Subscriptions)
+                            .wrap(from_fn(rate_limited))
+                            .wrap(from_fn(idempotent))
+                            .route(web::post().to(api_subscribe)),
+                    )
+                    .route(
+                        "/subscriptions/confirm",
+                        web::post().to(api_confirm_subscription),
+                    )
+                    // JSON bodies can't be posted by cross-site forms, so there is no CSRF token
+                    .service(
+                        api_resource("/subscribers", ApiScope::SubscribersRead)
+                            .route(web::get().to(api_list_subscribers)),
+                    )
+                    .service(
+                        api_resource("/subscribers/{subscriber_id}", ApiScope::SubscribersRead)
+                            .route(web::get().to(api_get_subscriber)),
+                    )
+                    .service(
+                        api_resource("/issues", ApiScope::IssuesWrite)
+                            .route(web::post().to(api_create_issue)),
+                    )
+                    .service(
+                        api_resource("/issues/{issue_id}", ApiScope::IssuesRead)
+                            .route(web::get().to(api_get_issue)),
+                    )
+                    .service(
+                        api_resource("/issues/{issue_id}/publish", ApiScope::IssuesWrite)
+                            .route(web::post().to(api_publish_issue)),
+                    )
+                    .service(
+                        api_resource("/issues/{issue_id}/delivery", ApiScope::IssuesRead)
+                            .route(web::get().to(api_delivery_status)),
+                    )
+                    .service(
+                        // Anonymous clients can't tell which other endpoints exist
+                        web::scope("")
+                            .wrap(from_fn(reject_anonymous_api_users))
+                            .default_service(web::route().to(api_not_found)),
+                    ),
+            )
             .service(
                 web::scope("/admin")
+                    // The last middleware registered runs first
+                    .wrap(from_fn(reject_forged_requests))
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
+                    .route("/two_factor", web::get().to(two_factor_page))
+                    .route("/two_factor/enable", web::post().to(enable_two_factor))
+                    .route("/two_factor/disable", web::post().to(disable_two_factor))
                     .route("/logout", web::post().to(log_out))
-                    .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/delivery_process", web::get().to(delivery_process)),
+                    .route("/sessions", web::get().to(sessions_page))
+                    .route(
+                        "/sessions/revoke_others",
+                        web::post().to(revoke_other_sessions),
+                    )
+                    .route(
+                        "/sessions/{session_id}/revoke",
+                        web::post().to(revoke_session),
+                    )
+                    .route("/tokens", web::get().to(api_tokens_page))
+                    .route("/tokens", web::post().to(create_api_token))
+                    .route(
+                        "/tokens/{token_id}/revoke",
+                        web::post().to(revoke_api_token),
+                    )
+                    .route("/delivery_process", web::get().to(delivery_process))
+                    .service(
+                        web::resource("/newsletters")
+                            .app_data(publish_newsletter_idempotency())
+                            .wrap(from_fn(idempotent))
+                            .wrap(from_fn(require_editor))
+                            .route(web::get().to(publish_newsletter_form))
+                            .route(web::post().to(publish_newsletter)),
+                    )
+                    .service(
+                        web::resource("/audit")
+                            .wrap(from_fn(require_owner))
+                            .route(web::get().to(audit_page)),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(users_page))
+                            .route("/invite", web::post().to(invite_user))
+                            .route("/unlock", web::post().to(unlock_user))
+                            .route("/{user_id}/activate", web::post().to(activate_user))
+                            .route("/{user_id}/deactivate", web::post().to(deactivate_user))
+                            .route("/{user_id}/role", web::post().to(update_role))
+                            .route(
+                                "/{user_id}/reset_two_factor",
+                                web::post().to(reset_user_two_factor),
+                            ),
+                    ),
             )
             .service(actix_files::Files::new("/static", "./static"))
             .default_service(web::get().to(not_found))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(totp_cipher.clone())
+            .app_data(account_settings.clone())
+            .app_data(login_throttle.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(idempotency.clone())
+            .app_data(idempotency_store.clone())
+            .app_data(password_hashing.clone())
+            .app_data(bot_protection.clone())
+            .app_data(metrics.clone())
+            .app_data(email_policy.clone())
     })
+    // Signals are handled by `Application::run_until_stopped` or by the caller of
+    // `Application::run_until_shutdown`
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout_secs)
     .listen(listener)?
     .run();
     Ok(server)
@@ -1,8 +1,8 @@
-use actix_web::{http::header::LOCATION, HttpResponse};
+use actix_web::{dev::ServiceRequest, http::header::LOCATION, web, HttpResponse};
 
 /// Return a 400 with the user-representation of the validation error as body.
 /// The error root cause is preserved for logging purposes.
-pub fn e400<T: std::fmt::Debug + std::fmt::Display>(e: T) -> actix_web::Error
+pub fn e400<T>(e: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
 {
@@ -19,3 +19,35 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// Read the body of the request from a middleware, leaving it for the handler.
+pub async fn peek_body(req: &mut ServiceRequest) -> Result<web::Bytes, actix_web::Error> {
+    let body = req.extract::<web::Bytes>().await?;
+    // Put the body back for the handler
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body.clone());
+    req.set_payload(payload.into());
+    Ok(body)
+}
+
+/// Read a field of a url-encoded form from a middleware, leaving the body for the handler.
+/// Returns `None` if the body is not a form or doesn't have the field.
+pub async fn peek_form_field(
+    req: &mut ServiceRequest,
+    field: &str,
+) -> Result<Option<String>, actix_web::Error> {
+    let body = peek_body(req).await?;
+    Ok(form_field(&body, field))
+}
+
+/// The value of a field of a url-encoded form, `None` if `body` is not a form.
+pub fn form_field(body: &[u8], field: &str) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()
+        .and_then(|fields| {
+            fields
+                .into_iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, value)| value)
+        })
+}
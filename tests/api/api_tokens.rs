@@ -0,0 +1,288 @@
+use crate::{
+    helpers::{assert_is_redirect_to, spawn_app, TestApp},
+    roles::login_as,
+};
+use zero2prod::authentication::UserRole;
+
+/// Create a token from the admin UI and return it.
+async fn create_api_token(app: &TestApp, scopes: &[&str]) -> String {
+    let mut form = vec![("name", "CI"), ("expires_in_days", "30")];
+    form.extend(scopes.iter().map(|scope| ("scope", *scope)));
+    let response = app.post_form("admin/tokens", &form).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let start = html_page.find("z2p_").expect("The token is not shown");
+    html_page[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
+
+async fn get_with_token(app: &TestApp, route: &str, token: &str) -> reqwest::Response {
+    // A client without cookies, like a CI job
+    reqwest::Client::new()
+        .get(format!("{}/{}", &app.address, route))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_with_token(
+    app: &TestApp,
+    route: &str,
+    token: &str,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .post(format!("{}/{}", &app.address, route))
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, code: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], code);
+}
+
+fn issue_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[tokio::test]
+async fn api_tokens_authenticate_requests_without_a_session() {
+    // Arrange
+    let app = spawn_app().await;
+    let user_id = login_as(&app, UserRole::Editor).await;
+    let token = create_api_token(&app, &["issues:read", "issues:write"]).await;
+
+    // Act
+    let response = post_with_token(&app, "api/v1/issues", &token, &issue_request_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let response = get_with_token(
+        &app,
+        &format!(
+            "api/v1/issues/{}",
+            issue["newsletter_issue_id"].as_str().unwrap()
+        ),
+        &token,
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    // The creation of the token is audited
+    let created = sqlx::query!("SELECT actor_id FROM audit_log WHERE action = 'api_token.created'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(created.actor_id, Some(user_id));
+    let html_page = app.get_route("admin/tokens").await.text().await.unwrap();
+    assert!(html_page.contains("CI"));
+    assert!(html_page.contains("issues:read, issues:write"));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn api_tokens_are_limited_to_their_scopes() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let token = create_api_token(&app, &["issues:read"]).await;
+
+    // Act
+    let create = post_with_token(&app, "api/v1/issues", &token, &issue_request_body()).await;
+    let list = get_with_token(&app, "api/v1/subscribers", &token).await;
+
+    // Assert
+    assert_error(create, 403, "insufficient_scope").await;
+    assert_error(list, 403, "insufficient_scope").await;
+}
+
+#[tokio::test]
+async fn api_tokens_are_rejected_by_endpoints_without_a_scope() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let token = create_api_token(&app, &["subscribers:read", "issues:read", "issues:write"]).await;
+
+    // Act
+    let response = get_with_token(&app, "api/v1/does-not-exist", &token).await;
+
+    // Assert
+    assert_error(response, 403, "forbidden").await;
+}
+
+#[tokio::test]
+async fn api_tokens_cant_do_more_than_the_role_of_their_user() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Viewer).await;
+    let token = create_api_token(&app, &["issues:write"]).await;
+
+    // Act
+    let response = post_with_token(&app, "api/v1/issues", &token, &issue_request_body()).await;
+
+    // Assert
+    assert_error(response, 403, "forbidden").await;
+}
+
+#[tokio::test]
+async fn revoked_api_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let token = create_api_token(&app, &["subscribers:read"]).await;
+    let response = get_with_token(&app, "api/v1/subscribers", &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+
+    // Act
+    let response = app
+        .post_form(&format!("admin/tokens/{}/revoke", token_id), &[("", "")])
+        .await;
+    assert_is_redirect_to(&response, "/admin/tokens");
+
+    // Assert
+    let html_page = app.get_route("admin/tokens").await.text().await.unwrap();
+    assert!(html_page.contains("The token CI has been revoked."));
+    let response = get_with_token(&app, "api/v1/subscribers", &token).await;
+    assert_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn tokens_of_other_users_cant_be_revoked() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let token = create_api_token(&app, &["subscribers:read"]).await;
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+    login_as(&app, UserRole::Owner).await;
+
+    // Act
+    app.post_form(&format!("admin/tokens/{}/revoke", token_id), &[("", "")])
+        .await;
+
+    // Assert
+    let html_page = app.get_route("admin/tokens").await.text().await.unwrap();
+    assert!(html_page.contains("The token doesn't exist."));
+    let response = get_with_token(&app, "api/v1/subscribers", &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn expired_api_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let token = create_api_token(&app, &["subscribers:read"]).await;
+    sqlx::query!("UPDATE api_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = get_with_token(&app, "api/v1/subscribers", &token).await;
+
+    // Assert
+    assert_error(response, 401, "unauthorized").await;
+    let html_page = app.get_route("admin/tokens").await.text().await.unwrap();
+    assert!(html_page.contains("Expired"));
+}
+
+#[tokio::test]
+async fn api_tokens_of_deactivated_users_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let user_id = login_as(&app, UserRole::Editor).await;
+    let token = create_api_token(&app, &["subscribers:read"]).await;
+    sqlx::query!(
+        "UPDATE users SET is_active = false WHERE user_id = $1",
+        user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = get_with_token(&app, "api/v1/subscribers", &token).await;
+
+    // Assert
+    assert_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn unknown_api_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let unknown = get_with_token(&app, "api/v1/subscribers", "z2p_unknown").await;
+    let not_bearer = reqwest::Client::new()
+        .get(format!("{}/api/v1/subscribers", &app.address))
+        .basic_auth("user", Some("password"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_error(unknown, 401, "unauthorized").await;
+    assert_error(not_bearer, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn api_tokens_dont_open_the_admin_pages() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Owner).await;
+    let token = create_api_token(&app, &["subscribers:read", "issues:write"]).await;
+
+    // Act
+    let response = post_with_token(&app, "admin/newsletters", &token, &issue_request_body()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn invalid_api_tokens_are_not_created() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+
+    // Act
+    let response = app
+        .post_form("admin/tokens", &[("name", "CI"), ("expires_in_days", "30")])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/tokens");
+    let html_page = app.get_route("admin/tokens").await.text().await.unwrap();
+    assert!(html_page.contains("Select at least one scope."));
+    let n_tokens = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+}
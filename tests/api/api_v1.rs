@@ -0,0 +1,433 @@
+use crate::{
+    helpers::{spawn_app, spawn_app_with, TestApp},
+    newsletter::create_confirmed_subscriber,
+    roles::login_as,
+};
+use reqwest::Url;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::authentication::UserRole;
+
+fn issue_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, code: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], code);
+    assert!(body["error"]["message"].is_string());
+}
+
+/// Subscribe through the API and return the token sent by email.
+async fn api_subscribe(app: &TestApp, email: &str) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_json(
+            "api/v1/subscriptions",
+            &serde_json::json!({ "email": email, "name": "le guin" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link: Url = app.get_confirmation_links(email_request).html;
+    link.query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn create_issue(app: &TestApp) -> serde_json::Value {
+    let response = app.post_json("api/v1/issues", &issue_request_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn subscribing_through_the_api_returns_a_pending_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_json(
+            "api/v1/subscriptions",
+            &serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "le guin" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT id, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(body["subscriber_id"], saved.id.to_string());
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn invalid_subscriptions_are_rejected_with_a_json_error() {
+    // Arrange
+    let app = spawn_app().await;
+    api_subscribe(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act - Part 1 - Invalid email
+    let response = app
+        .post_json(
+            "api/v1/subscriptions",
+            &serde_json::json!({ "email": "not-an-email", "name": "le guin" }),
+        )
+        .await;
+    assert_error(response, 400, "validation_error").await;
+
+    // Act - Part 2 - Missing field
+    let response = app
+        .post_json(
+            "api/v1/subscriptions",
+            &serde_json::json!({ "name": "le guin" }),
+        )
+        .await;
+    assert_error(response, 400, "validation_error").await;
+
+    // Act - Part 3 - Email already used by another subscriber
+    let response = app
+        .post_json(
+            "api/v1/subscriptions",
+            &serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "ursula" }),
+        )
+        .await;
+    assert_error(response, 409, "conflict").await;
+}
+
+#[tokio::test]
+async fn subscriptions_outside_the_allowed_domains_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_policy.allowed_domains = vec!["example.com".into()]).await;
+
+    // Act
+    let response = app
+        .post_json(
+            "api/v1/subscriptions",
+            &serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "le guin" }),
+        )
+        .await;
+
+    // Assert
+    assert_error(response, 400, "validation_error").await;
+    api_subscribe(&app, "ursula@mail.example.com").await;
+}
+
+#[tokio::test]
+async fn subscriptions_can_be_confirmed_through_the_api() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = api_subscribe(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = app
+        .post_json(
+            "api/v1/subscriptions/confirm",
+            &serde_json::json!({ "subscription_token": token }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "confirmed");
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unknown_subscription_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_json(
+            "api/v1/subscriptions/confirm",
+            &serde_json::json!({ "subscription_token": "a".repeat(25) }),
+        )
+        .await;
+
+    // Assert
+    assert_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn anonymous_users_get_a_json_401_on_protected_endpoints() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let list = app.get_route("api/v1/subscribers").await;
+    let create = app.post_json("api/v1/issues", &issue_request_body()).await;
+    let unknown = app.get_route("api/v1/does-not-exist").await;
+
+    // Assert
+    assert_error(list, 401, "unauthorized").await;
+    assert_error(create, 401, "unauthorized").await;
+    assert_error(unknown, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn editors_can_list_and_fetch_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    api_subscribe(&app, "ursula_le_guin@gmail.com").await;
+    login_as(&app, UserRole::Editor).await;
+
+    // Act - Part 1 - Filter and paginate
+    let response = app
+        .get_route("api/v1/subscribers?status=confirmed&limit=1")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total"], 2);
+    let subscribers = body["subscribers"].as_array().unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0]["status"], "confirmed");
+
+    // Act - Part 2 - Fetch one of them
+    let subscriber_id = subscribers[0]["subscriber_id"].as_str().unwrap();
+    let response = app
+        .get_route(&format!("api/v1/subscribers/{}", subscriber_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], subscribers[0]["email"]);
+
+    // Act - Part 3 - Invalid filter
+    let response = app.get_route("api/v1/subscribers?status=unknown").await;
+    assert_error(response, 400, "validation_error").await;
+
+    // Act - Part 4 - Unknown subscriber
+    let response = app
+        .get_route(&format!("api/v1/subscribers/{}", uuid::Uuid::new_v4()))
+        .await;
+    assert_error(response, 404, "not_found").await;
+}
+
+#[tokio::test]
+async fn viewers_cant_list_subscribers_or_create_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Viewer).await;
+
+    // Act
+    let list = app.get_route("api/v1/subscribers").await;
+    let create = app.post_json("api/v1/issues", &issue_request_body()).await;
+
+    // Assert
+    assert_error(list, 403, "forbidden").await;
+    assert_error(create, 403, "forbidden").await;
+}
+
+#[tokio::test]
+async fn issues_can_be_drafted_published_and_tracked() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login_as(&app, UserRole::Editor).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Create a draft
+    let issue = create_issue(&app).await;
+    assert!(issue["published_at"].is_null());
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+    let response = app
+        .get_route(&format!("api/v1/issues/{}/delivery", issue_id))
+        .await;
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["status"], "draft");
+
+    // Act - Part 2 - Publish it
+    let response = app
+        .post_json(&format!("api/v1/issues/{}/publish", issue_id), &())
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["status"], "in_progress");
+    assert_eq!(status["recipients"], 1);
+    assert_eq!(status["pending"], 1);
+
+    // Act - Part 3 - Deliver it
+    app.dispatch_all_pending_emails().await;
+    let response = app
+        .get_route(&format!("api/v1/issues/{}/delivery", issue_id))
+        .await;
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["status"], "completed");
+    assert_eq!(status["pending"], 0);
+
+    // Act - Part 4 - It can't be published twice
+    let response = app
+        .post_json(&format!("api/v1/issues/{}/publish", issue_id), &())
+        .await;
+    assert_error(response, 409, "conflict").await;
+    // Mock verifies on Drop that we haven't sent the newsletter email twice
+}
+
+#[tokio::test]
+async fn publishing_an_unknown_issue_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_json(
+            &format!("api/v1/issues/{}/publish", uuid::Uuid::new_v4()),
+            &(),
+        )
+        .await;
+
+    // Assert
+    assert_error(response, 404, "not_found").await;
+}
+
+#[tokio::test]
+async fn session_requests_changing_state_need_the_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue = create_issue(&app).await;
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+    let publish_url = format!("{}/api/v1/issues/{}/publish", &app.address, issue_id);
+
+    // Act
+    let missing = app.api_client.post(&publish_url).send().await.unwrap();
+    let forged = app
+        .api_client
+        .post(&publish_url)
+        .header("X-CSRF-Token", "forged")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_error(missing, 403, "invalid_csrf_token").await;
+    assert_error(forged, 403, "invalid_csrf_token").await;
+    let response = app
+        .get_route(&format!("api/v1/issues/{}/delivery", issue_id))
+        .await;
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["status"], "draft");
+}
+
+#[tokio::test]
+async fn invalid_issues_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+
+    // Act
+    let response = app
+        .post_json(
+            "api/v1/issues",
+            &serde_json::json!({ "title": "", "text_content": "text", "html_content": "<p>html</p>" }),
+        )
+        .await;
+    let malformed = app
+        .api_client
+        .post(format!("{}/api/v1/issues", &app.address))
+        .header("Content-Type", "application/json")
+        .header("X-CSRF-Token", app.csrf_token().await)
+        .body("{\"title\":")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_error(response, 400, "validation_error").await;
+    assert_error(malformed, 400, "validation_error").await;
+}
+
+#[tokio::test]
+async fn unknown_endpoints_return_a_json_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let response = app.get_route("api/v1/does-not-exist").await;
+
+    // Assert
+    assert_error(response, 404, "not_found").await;
+}
+
+#[tokio::test]
+async fn the_openapi_document_describes_every_endpoint() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_route("api/v1/openapi.json").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let document: serde_json::Value = response.json().await.unwrap();
+    let paths = document["paths"].as_object().unwrap();
+    for path in [
+        "/api/v1/subscriptions",
+        "/api/v1/subscriptions/confirm",
+        "/api/v1/subscribers",
+        "/api/v1/subscribers/{subscriber_id}",
+        "/api/v1/issues",
+        "/api/v1/issues/{newsletter_issue_id}",
+        "/api/v1/issues/{newsletter_issue_id}/publish",
+        "/api/v1/issues/{newsletter_issue_id}/delivery",
+    ] {
+        assert!(paths.contains_key(path), "{} is not documented", path);
+    }
+    assert!(document["components"]["securitySchemes"]["session"].is_object());
+}
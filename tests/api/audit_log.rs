@@ -0,0 +1,210 @@
+use crate::{
+    helpers::{assert_is_redirect_to, spawn_app, TestApp},
+    roles::login_as,
+};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::authentication::UserRole;
+
+struct AuditEvent {
+    actor_id: Option<Uuid>,
+    client_ip: Option<String>,
+    payload: serde_json::Value,
+}
+
+async fn audit_events(app: &TestApp, action: &str) -> Vec<AuditEvent> {
+    sqlx::query!(
+        "SELECT actor_id, client_ip, payload FROM audit_log WHERE action = $1 ORDER BY event_id",
+        action
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| AuditEvent {
+        actor_id: r.actor_id,
+        client_ip: r.client_ip,
+        payload: r.payload,
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn logins_and_logouts_are_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+
+    // Act
+    app.do_login().await;
+    app.post_logout().await;
+
+    // Assert
+    let failures = audit_events(&app, "login.failed").await;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].actor_id, None);
+    assert_eq!(failures[0].payload["username"], app.test_user.username);
+    assert_eq!(failures[0].payload["reason"], "invalid_credentials");
+    let logins = audit_events(&app, "login.succeeded").await;
+    assert_eq!(logins.len(), 1);
+    assert_eq!(logins[0].actor_id, Some(app.test_user.user_id));
+    assert_eq!(logins[0].client_ip.as_deref(), Some("127.0.0.1"));
+    let logouts = audit_events(&app, "logout").await;
+    assert_eq!(logouts.len(), 1);
+    assert_eq!(
+        logouts[0].payload["session_id"],
+        logins[0].payload["session_id"]
+    );
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_is_audited_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act - Submit the form twice
+    app.post_publish_newsletters(&newsletter_request_body).await;
+    app.post_publish_newsletters(&newsletter_request_body).await;
+
+    // Assert
+    let events = audit_events(&app, "newsletter.published").await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].actor_id, Some(app.test_user.user_id));
+    assert_eq!(events[0].payload["title"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn new_subscribers_are_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let events = audit_events(&app, "subscriber.added").await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].payload["email"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn user_management_is_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor_id = login_as(&app, UserRole::Editor).await;
+    app.do_login().await;
+
+    // Act
+    let response = app.post_update_role(editor_id, "viewer").await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let response = app.post_deactivate_user(editor_id).await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Assert
+    let role_changes = audit_events(&app, "user.role_changed").await;
+    assert_eq!(role_changes.len(), 1);
+    assert_eq!(role_changes[0].actor_id, Some(app.test_user.user_id));
+    assert_eq!(role_changes[0].payload["user_id"], editor_id.to_string());
+    assert_eq!(role_changes[0].payload["role"], "viewer");
+    let deactivations = audit_events(&app, "user.deactivated").await;
+    assert_eq!(deactivations.len(), 1);
+    assert_eq!(deactivations[0].payload["user_id"], editor_id.to_string());
+}
+
+#[tokio::test]
+async fn owners_can_filter_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": "a-brand-new-password",
+        "new_password_check": "a-brand-new-password",
+    }))
+    .await;
+
+    // Act
+    let all_events = app.get_audit_log_html("").await;
+    let password_changes = app
+        .get_audit_log_html("?action=password.changed&actor=&since=&until=")
+        .await;
+    let logins_of_someone_else = app
+        .get_audit_log_html("?action=login.succeeded&actor=someone-else")
+        .await;
+
+    // Assert
+    assert!(all_events.contains("<td>login.succeeded</td>"));
+    assert!(all_events.contains("<td>password.changed</td>"));
+    assert!(all_events.contains(&app.test_user.username));
+    assert!(password_changes.contains("<td>password.changed</td>"));
+    assert!(!password_changes.contains("<td>login.succeeded</td>"));
+    assert!(!logins_of_someone_else.contains("<td>login.succeeded</td>"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected_with_a_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let response = app.get_audit_log("?since=yesterday").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/audit");
+    let html_page = app.get_audit_log_html("").await;
+    assert!(html_page.contains("yesterday is not a valid date."));
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+
+    // Act
+    let response = app.get_audit_log("").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_audit_log_is_append_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let update = sqlx::query!("UPDATE audit_log SET action = 'tampered'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    assert_eq!(audit_events(&app, "login.succeeded").await.len(), 1);
+}
@@ -0,0 +1,268 @@
+use crate::helpers::{assert_is_redirect_to, form_value, spawn_app, spawn_app_with, TestApp};
+use sha2::{Digest, Sha256};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::configuration::SessionStoreBackend;
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+/// The signed fields of a fresh form, and a nonce solving its proof of work for `email`
+/// but not for `other_email`.
+async fn solved_form(app: &TestApp, email: &str, other_email: &str) -> (String, u32) {
+    let html_page = app.get_subscriptions_html().await;
+    let served_at = form_value(&html_page, "served_at");
+    let form_signature = form_value(&html_page, "form_signature");
+    let fields = format!(
+        "name=le%20guin&served_at={}&form_id={}&form_signature={}",
+        served_at,
+        form_value(&html_page, "form_id"),
+        form_signature
+    );
+    // Solved like the script of the page does
+    let solves = |email: &str, nonce: u32| {
+        let input = format!("{}:{}:{}:{}", served_at, form_signature, email, nonce);
+        Sha256::digest(input)[0] == 0
+    };
+    let nonce = (0..)
+        .find(|nonce| solves(email, *nonce) && !solves(other_email, *nonce))
+        .unwrap();
+    (fields, nonce)
+}
+
+async fn count_subscribers(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn expect_no_email(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn rejected_subscriptions(app: &TestApp, reason: &str) -> bool {
+    let metrics = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    metrics.contains(&format!(
+        "zero2prod_rejected_subscriptions_total{{reason=\"{}\"}} 1\n",
+        reason
+    ))
+}
+
+#[tokio::test]
+async fn bots_filling_the_honeypot_are_told_they_subscribed() {
+    // Arrange
+    let app = spawn_app().await;
+    expect_no_email(&app).await;
+
+    // Act
+    let body = format!("{}&website=https%3A%2F%2Fspam.example", BODY);
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions");
+    let html_page = app.get_subscriptions_html().await;
+    assert!(html_page.contains("A confirmation email was sent to ursula_le_guin@gmail.com"));
+    assert_eq!(count_subscribers(&app).await, 0);
+    assert!(rejected_subscriptions(&app, "honeypot").await);
+}
+
+#[tokio::test]
+async fn forms_submitted_too_quickly_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| c.bot_protection.min_fill_secs = 60).await;
+    expect_no_email(&app).await;
+
+    // Act
+    let response = app.post_subscriptions(BODY.into()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions");
+    let html_page = app.get_subscriptions_html().await;
+    assert!(html_page.contains("The form was submitted too quickly, please try again."));
+    assert_eq!(count_subscribers(&app).await, 0);
+    assert!(rejected_subscriptions(&app, "too_fast").await);
+}
+
+#[tokio::test]
+async fn forms_without_a_valid_signature_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    expect_no_email(&app).await;
+    let served_at = chrono::Utc::now().timestamp() - 60;
+
+    // Act
+    let body = format!("{}&served_at={}&form_signature=forged", BODY, served_at);
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions");
+    let html_page = app.get_subscriptions_html().await;
+    assert!(html_page.contains("The form could not be verified, please submit it again."));
+    assert_eq!(count_subscribers(&app).await, 0);
+    assert!(rejected_subscriptions(&app, "invalid_form_signature").await);
+}
+
+#[tokio::test]
+async fn the_proof_of_work_is_checked_when_enabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.bot_protection.proof_of_work_bits = 8).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let (body, nonce) = solved_form(&app, EMAIL, "tolkien@gmail.com").await;
+    let body = format!("{}&email=ursula_le_guin%40gmail.com", body);
+
+    // Act
+    let unsolved = app.post_subscriptions(body.clone()).await;
+    let solved = app
+        .post_subscriptions(format!("{}&pow_nonce={}", body, nonce))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&unsolved, "/subscriptions");
+    assert_is_redirect_to(&solved, "/subscriptions");
+    assert!(rejected_subscriptions(&app, "proof_of_work").await);
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn a_solved_form_cannot_be_replayed_for_other_emails() {
+    // Arrange
+    let app = spawn_app_with(|c| c.bot_protection.proof_of_work_bits = 8).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let (fields, nonce) = solved_form(&app, EMAIL, "tolkien@gmail.com").await;
+
+    // Act - Submitted for the other email first, so the form is still unused
+    let mut responses = Vec::new();
+    for email in ["tolkien%40gmail.com", "ursula_le_guin%40gmail.com"] {
+        let body = format!("{}&email={}&pow_nonce={}", fields, email, nonce);
+        responses.push(app.post_subscriptions(body).await);
+    }
+
+    // Assert
+    for response in &responses {
+        assert_is_redirect_to(response, "/subscriptions");
+    }
+    assert!(rejected_subscriptions(&app, "proof_of_work").await);
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.email, EMAIL);
+}
+
+#[tokio::test]
+async fn forms_can_be_submitted_once() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let fields = app.subscription_form_fields().await;
+
+    // Act
+    for email in ["ursula_le_guin%40gmail.com", "tolkien%40gmail.com"] {
+        let body = format!("name=le%20guin&email={}{}", email, fields);
+        app.post_subscriptions(body).await;
+    }
+
+    // Assert
+    let html_page = app.get_subscriptions_html().await;
+    assert!(html_page.contains("The form was already submitted, please fill it in again."));
+    assert_eq!(count_subscribers(&app).await, 1);
+    assert!(rejected_subscriptions(&app, "reused_form").await);
+}
+
+#[tokio::test]
+async fn forms_can_be_submitted_again_after_a_failed_attempt() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let fields = app.subscription_form_fields().await;
+
+    // Act - Part 1 - Invalid name
+    let body = format!("name=&email=ursula_le_guin%40gmail.com{}", fields);
+    app.post_subscriptions(body).await;
+
+    // Act - Part 2 - The confirmation email can't be sent
+    app.post_subscriptions(format!("{}{}", BODY, fields)).await;
+    assert_eq!(count_subscribers(&app).await, 0);
+
+    // Act - Part 3 - Succeeds with the same form
+    let response = app.post_subscriptions(format!("{}{}", BODY, fields)).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions");
+    let html_page = app.get_subscriptions_html().await;
+    assert!(html_page.contains("A confirmation email was sent to ursula_le_guin@gmail.com"));
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn used_forms_are_removed_once_expired_whatever_the_session_store() {
+    // Arrange
+    let app = spawn_app_with(|c| c.session_store.backend = SessionStoreBackend::Redis).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(BODY.into()).await;
+    let expired =
+        sqlx::query!("UPDATE used_subscription_forms SET expires_at = now() - interval '1 second'")
+            .execute(&app.db_pool)
+            .await
+            .unwrap()
+            .rows_affected();
+    assert_eq!(expired, 1);
+
+    // Act - The idempotency expiration worker runs with every session store backend
+    app.remove_expired_idempotency_keys().await;
+
+    // Assert
+    let n_forms =
+        sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM used_subscription_forms"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_forms, 0);
+}
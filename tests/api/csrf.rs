@@ -0,0 +1,137 @@
+use crate::helpers::{api_client, assert_is_redirect_to, csrf_token, spawn_app};
+
+#[tokio::test]
+async fn admin_forms_without_a_csrf_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard().await.text().await.unwrap();
+    assert!(html_page.contains("The form has expired, please submit it again."));
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn login_without_a_csrf_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login().await.text().await.unwrap();
+    assert!(html_page.contains("The form has expired, please submit it again."));
+}
+
+#[tokio::test]
+async fn the_csrf_token_is_accepted_as_a_form_field() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.csrf_token().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "csrf_token": &token,
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_csrf_token_of_another_session_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let attacker = api_client();
+    let token = csrf_token(&attacker, &app.address).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", app.address))
+        .form(&serde_json::json!({ "csrf_token": &token }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn forms_include_the_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let token = app.csrf_token().await;
+
+    for page in [
+        "admin/dashboard",
+        "admin/password",
+        "admin/newsletters",
+        "admin/users",
+    ] {
+        // Act
+        let html_page = app.get_route(page).await.text().await.unwrap();
+
+        // Assert
+        assert!(
+            html_page.contains(&format!(r#"name="csrf_token" value="{}""#, token)),
+            "{}",
+            page
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_new_csrf_token_is_issued_after_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.csrf_token().await;
+
+    // Act
+    app.do_login().await;
+
+    // Assert
+    assert_ne!(app.csrf_token().await, token);
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", app.address))
+        .form(&serde_json::json!({ "csrf_token": &token }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
@@ -8,11 +8,14 @@ use uuid::Uuid;
 use wiremock::MockServer;
 use zero2prod::{
     configuration::{
-        get_configuration, DatabaseSettings, IdempotencySettings, IssueDeliverySettings,
+        get_configuration, DatabaseSettings, IdempotencyBackend, IdempotencySettings,
+        IssueDeliverySettings, LoginThrottleSettings, PasswordHashingSettings, SessionStoreBackend,
+        Settings,
     },
     email_client::EmailClient,
     get_connection_pool, idempotency_expiration_worker,
     issue_delivery_worker::{self, ExecutionOutcome},
+    session_expiration_worker,
     telemetry::{get_subscriber, init_subscriber},
     Application,
 };
@@ -40,6 +43,8 @@ pub struct TestApp {
     pub email_client: EmailClient,
     pub issue_delivery_settings: IssueDeliverySettings,
     pub idempotency_settings: IdempotencySettings,
+    pub login_throttle_settings: LoginThrottleSettings,
+    pub password_hashing_settings: PasswordHashingSettings,
 }
 
 pub struct ConfirmationLinks {
@@ -48,8 +53,9 @@ pub struct ConfirmationLinks {
 }
 
 pub struct TestUser {
-    user_id: Uuid,
+    pub user_id: Uuid,
     pub username: String,
+    pub email: String,
     pub password: String,
 }
 
@@ -58,6 +64,7 @@ impl TestUser {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
+            email: format!("{}@example.com", Uuid::new_v4()),
             password: Uuid::new_v4().to_string(),
         }
     }
@@ -73,10 +80,11 @@ impl TestUser {
         .unwrap()
         .to_string();
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash)
-            Values ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, email, password_hash, role)
+            Values ($1, $2, $3, $4, 'owner')",
             self.user_id,
             self.username,
+            self.email,
             password_hash,
         )
         .execute(pool)
@@ -86,17 +94,22 @@ impl TestUser {
 }
 
 impl TestApp {
+    pub async fn execute_delivery_task(&self) -> ExecutionOutcome {
+        issue_delivery_worker::try_execute_task(
+            &self.db_pool,
+            &self.email_client,
+            &self.issue_delivery_settings,
+        )
+        .await
+        .unwrap()
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue = issue_delivery_worker::try_execute_task(
-                &self.db_pool,
-                &self.email_client,
-                &self.issue_delivery_settings,
-            )
-            .await
-            .unwrap()
-            {
-                break;
+            match self.execute_delivery_task().await {
+                ExecutionOutcome::EmptyQueue => break,
+                ExecutionOutcome::Throttled(wait) => tokio::time::sleep(wait).await,
+                ExecutionOutcome::TaskCompleted => {}
             }
         }
     }
@@ -110,6 +123,12 @@ impl TestApp {
             .unwrap();
     }
 
+    pub async fn remove_expired_sessions(&self) {
+        session_expiration_worker::try_execute_task(&self.db_pool)
+            .await
+            .unwrap();
+    }
+
     pub async fn get_route(&self, route: &str) -> reqwest::Response {
         self.api_client
             .get(format!("{}/{}", &self.address, route))
@@ -118,6 +137,36 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_form<Body>(&self, route: &str, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize + ?Sized,
+    {
+        self.api_client
+            .post(format!("{}/{}", &self.address, route))
+            .form(body)
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_json<Body>(&self, route: &str, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize + ?Sized,
+    {
+        self.api_client
+            .post(format!("{}/{}", &self.address, route))
+            .json(body)
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn csrf_token(&self) -> String {
+        csrf_token(&self.api_client, &self.address).await
+    }
+
     pub async fn get_subscriptions(&self) -> reqwest::Response {
         self.get_route("subscriptions").await
     }
@@ -126,7 +175,12 @@ impl TestApp {
         self.get_subscriptions().await.text().await.unwrap()
     }
 
-    pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+    /// Submit the subscription form, with the fields signed by a freshly served form unless
+    /// the body has its own.
+    pub async fn post_subscriptions(&self, mut body: String) -> reqwest::Response {
+        if !body.contains("form_signature=") {
+            body.push_str(&self.subscription_form_fields().await);
+        }
         self.api_client
             .post(format!("{}/subscribe", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
@@ -136,6 +190,17 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// The signed fields of a freshly served subscription form, to append to a body.
+    pub async fn subscription_form_fields(&self) -> String {
+        let html_page = self.get_subscriptions_html().await;
+        format!(
+            "&served_at={}&form_id={}&form_signature={}",
+            form_value(&html_page, "served_at"),
+            form_value(&html_page, "form_id"),
+            form_value(&html_page, "form_signature")
+        )
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         let get_link = |s: &str| {
@@ -152,8 +217,8 @@ impl TestApp {
             confirmation_link.set_port(Some(self.port)).unwrap();
             confirmation_link
         };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
@@ -164,6 +229,7 @@ impl TestApp {
         self.api_client
             .post(format!("{}/login", &self.address))
             .form(body)
+            .header("X-CSRF-Token", self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -200,6 +266,7 @@ impl TestApp {
         self.api_client
             .post(format!("{}/admin/newsletters", &self.address))
             .form(body)
+            .header("X-CSRF-Token", self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -223,6 +290,88 @@ impl TestApp {
         self.api_client
             .post(format!("{}/admin/password", &self.address))
             .form(body)
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.get_route("admin/users").await
+    }
+
+    pub async fn get_audit_log(&self, query: &str) -> reqwest::Response {
+        self.get_route(&format!("admin/audit{}", query)).await
+    }
+
+    pub async fn get_audit_log_html(&self, query: &str) -> String {
+        self.get_audit_log(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.get_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_invite_user(&self, email: &str, role: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users/invite", &self.address))
+            .form(&serde_json::json!({ "email": email, "role": role }))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_deactivate_user(&self, user_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/deactivate",
+                &self.address, user_id
+            ))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_update_role(&self, user_id: Uuid, role: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users/{}/role", &self.address, user_id))
+            .form(&serde_json::json!({ "role": role }))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invitations/accept", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset(&self, username: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/password_reset", &self.address))
+            .form(&serde_json::json!({ "username": username }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password_reset/confirm", &self.address))
+            .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -231,6 +380,7 @@ impl TestApp {
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/logout", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -238,6 +388,11 @@ impl TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after tweaking its test configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // Set up tracing
     Lazy::force(&TRACING);
 
@@ -258,6 +413,24 @@ pub async fn spawn_app() -> TestApp {
         c.issue_delivery.max_retries = 2;
         // Set idempotency expiration to 1 seconds
         c.idempotency.expiration_secs = 1;
+        // Keep the login failures of each test apart, and don't wait on them
+        c.login_throttle.redis_key_prefix = Uuid::new_v4().to_string();
+        c.login_throttle.max_failures_per_ip = 10;
+        c.login_throttle.delay_base_ms = 1;
+        c.login_throttle.max_delay_ms = 10;
+        // Sessions don't need to outlive the test
+        c.session_store.backend = SessionStoreBackend::Memory;
+        // Tests that aren't about rate limits send many requests from the same client
+        c.rate_limit.subscriptions.max_requests_per_ip = 1000;
+        c.rate_limit.subscriptions.max_requests_per_recipient = 1000;
+        c.rate_limit.password_reset.max_requests_per_ip = 1000;
+        c.rate_limit.password_reset.max_requests_per_recipient = 1000;
+        // Tests look for the keys of the public endpoints in Postgres
+        c.idempotency.public_backend = IdempotencyBackend::Postgres;
+        // Tests submit forms right after getting them
+        c.bot_protection.min_fill_secs = 0;
+        c.idempotency.redis_key_prefix = Uuid::new_v4().to_string();
+        configure(&mut c);
         c
     };
 
@@ -269,13 +442,9 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let client = api_client();
     let email_client = configuration
         .email_client
         .client()
@@ -291,11 +460,45 @@ pub async fn spawn_app() -> TestApp {
         email_client,
         issue_delivery_settings: configuration.issue_delivery,
         idempotency_settings: configuration.idempotency,
+        login_throttle_settings: configuration.login_throttle,
+        password_hashing_settings: configuration.password_hashing,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
 }
 
+/// Fetch the CSRF token of the client's session from one of its forms.
+pub async fn csrf_token(client: &reqwest::Client, address: &str) -> String {
+    // Logged in users are sent to the dashboard, and expired sessions back to the login
+    let mut path = "/login".to_string();
+    let html_page = loop {
+        let response = client
+            .get(format!("{}{}", address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        match response.headers().get("Location") {
+            Some(location) => path = location.to_str().unwrap().to_string(),
+            None => break response.text().await.unwrap(),
+        }
+    };
+    let start = html_page
+        .find(r#"name="csrf_token" value=""#)
+        .expect("No CSRF token in the page.")
+        + r#"name="csrf_token" value=""#.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_string()
+}
+
+/// Client that keeps its own session cookies and doesn't follow redirects.
+pub fn api_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     // Create database
     let mut connection = PgConnection::connect_with(&config.without_db())
@@ -324,3 +527,16 @@ pub fn assert_is_redirect_to(response: &Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
+
+/// The value of the input named `name` in an HTML page.
+pub fn form_value(html_page: &str, name: &str) -> String {
+    let start = html_page
+        .find(&format!(r#"name="{}""#, name))
+        .unwrap_or_else(|| panic!("The page has no {} field", name));
+    html_page[start..]
+        .split(r#"value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap_or_else(|| panic!("The {} field has no value", name))
+        .to_owned()
+}
@@ -0,0 +1,767 @@
+use crate::{
+    helpers::{assert_is_redirect_to, form_value, spawn_app, spawn_app_with, TestApp},
+    roles::login_as,
+};
+use std::time::Duration;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::{
+    authentication::UserRole,
+    configuration::{get_configuration, IdempotencyBackend},
+    idempotency::{
+        get_saved_response, release_key, renew_lease, save_response, try_processing,
+        IdempotencyKey, IdempotencyKeyOwner, IdempotencyStore, NextAction,
+    },
+};
+
+fn issue_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn post_json_with_key(
+    app: &TestApp,
+    route: &str,
+    body: &serde_json::Value,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/{}", &app.address, route))
+        .header("Idempotency-Key", idempotency_key)
+        .header("X-CSRF-Token", app.csrf_token().await)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn api_requests_reusing_a_key_get_the_saved_response() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let response1 = post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+    let response2 = post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 201);
+    assert_eq!(response2.status().as_u16(), 201);
+    assert_eq!(
+        response1.headers().get("Location"),
+        response2.headers().get("Location")
+    );
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn requests_without_a_key_are_processed_every_time() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+
+    // Act
+    app.post_json("api/v1/issues", &issue_request_body()).await;
+    app.post_json("api/v1/issues", &issue_request_body()).await;
+
+    // Assert
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_scoped_to_their_user() {
+    // Arrange
+    let app = spawn_app().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    login_as(&app, UserRole::Editor).await;
+    post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+    login_as(&app, UserRole::Editor).await;
+    post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+
+    // Assert
+    assert_eq!(count_issues(&app).await, 2);
+    let owners = sqlx::query!("SELECT key_owner FROM idempotency ORDER BY key_owner")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(owners.len(), 2);
+    assert!(owners.iter().all(|r| r.key_owner.starts_with("user:")));
+}
+
+#[tokio::test]
+async fn failed_requests_can_be_retried_with_the_same_key() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let invalid_body = serde_json::json!({
+        "title": "",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+
+    // Act
+    let response1 =
+        post_json_with_key(&app, "api/v1/issues", &invalid_body, &idempotency_key).await;
+    let response2 = post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 400);
+    assert_eq!(response2.status().as_u16(), 201);
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn abandoned_keys_can_be_retried_once_their_lease_runs_out() {
+    // Arrange - A request claimed the key, then died without saving or releasing it
+    let app = spawn_app_with(|c| c.idempotency.processing_timeout_secs = 1).await;
+    let user_id = login_as(&app, UserRole::Editor).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let store = IdempotencyStore::Postgres(app.db_pool.clone());
+    let next_action = try_processing(
+        &store,
+        &idempotency_key.clone().try_into().unwrap(),
+        &IdempotencyKeyOwner::User(user_id),
+        "abandoned request",
+        Duration::from_secs(3600),
+        Duration::from_secs(1),
+        Duration::ZERO,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(next_action, NextAction::StartProcessing { .. }));
+
+    // Act
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let response = post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn requests_whose_key_was_taken_over_leave_it_to_the_new_holder() {
+    let app = spawn_app().await;
+    let redis_uri = get_configuration().unwrap().redis_uri;
+    for backend in [IdempotencyBackend::Postgres, IdempotencyBackend::Redis] {
+        // Arrange
+        let key_prefix = uuid::Uuid::new_v4().to_string();
+        let store = IdempotencyStore::new(backend, &redis_uri, &app.db_pool, &key_prefix)
+            .await
+            .unwrap();
+        let idempotency_key: IdempotencyKey = uuid::Uuid::new_v4().to_string().try_into().unwrap();
+        let owner = IdempotencyKeyOwner::Client("127.0.0.1".into());
+        let claim = || {
+            try_processing(
+                &store,
+                &idempotency_key,
+                &owner,
+                "slow request",
+                Duration::from_secs(3600),
+                Duration::from_secs(1),
+                Duration::ZERO,
+            )
+        };
+        let first_lease = match claim().await.unwrap() {
+            NextAction::StartProcessing { lease_token } => lease_token,
+            _ => panic!("The key should have been claimed"),
+        };
+        // The first request's lease runs out and a retry takes the key over
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(matches!(
+            claim().await.unwrap(),
+            NextAction::StartProcessing { .. }
+        ));
+
+        // Act - The first request is still running and eventually fails
+        renew_lease(
+            &store,
+            &idempotency_key,
+            &owner,
+            Duration::from_secs(3600),
+            first_lease,
+        )
+        .await
+        .unwrap();
+        save_response(
+            &store,
+            &idempotency_key,
+            &owner,
+            "slow request",
+            first_lease,
+            actix_web::HttpResponse::Ok().finish(),
+        )
+        .await
+        .unwrap();
+        release_key(&store, &idempotency_key, &owner, first_lease)
+            .await
+            .unwrap();
+
+        // Assert - The retry still holds the key, on the lease it took
+        assert!(matches!(claim().await.unwrap(), NextAction::InProgress));
+        let saved_response = get_saved_response(&store, &idempotency_key, &owner)
+            .await
+            .unwrap();
+        assert!(saved_response.is_none());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(matches!(
+            claim().await.unwrap(),
+            NextAction::StartProcessing { .. }
+        ));
+    }
+}
+
+#[tokio::test]
+async fn invalid_idempotency_keys_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+
+    // Act
+    let response = post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &"a".repeat(60),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "validation_error");
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn the_newsletter_form_requires_an_idempotency_key() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let response = app.post_publish_newsletters(&issue_request_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn anonymous_subscriptions_are_keyed_by_client() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let body = serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "le guin" });
+
+    // Act
+    let response1 = post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key).await;
+    let response2 = post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key).await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    let owner = sqlx::query!("SELECT key_owner FROM idempotency")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .key_owner;
+    assert_eq!(owner, "client:127.0.0.1");
+    // Mock verifies on Drop that we have sent the confirmation email **once**
+}
+
+#[tokio::test]
+async fn submitting_the_subscription_form_twice_sends_one_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let html_page = app.get_subscriptions_html().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&idempotency_key={}\
+        &served_at={}&form_id={}&form_signature={}",
+        form_value(&html_page, "idempotency_key"),
+        form_value(&html_page, "served_at"),
+        form_value(&html_page, "form_id"),
+        form_value(&html_page, "form_signature")
+    );
+
+    // Act
+    let response1 = app.post_subscriptions(body.clone()).await;
+    let response2 = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_is_redirect_to(&response1, "/subscriptions");
+    assert_is_redirect_to(&response2, "/subscriptions");
+    let html_page = app.get_subscriptions_html().await;
+    assert!(html_page.contains("A confirmation email was sent."));
+}
+
+#[tokio::test]
+async fn api_tokens_have_their_own_idempotency_keys() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let response = app
+        .post_form(
+            "admin/tokens",
+            &[
+                ("name", "CI"),
+                ("scope", "issues:write"),
+                ("expires_in_days", "30"),
+            ],
+        )
+        .await;
+    let html_page = response.text().await.unwrap();
+    let start = html_page.find("z2p_").unwrap();
+    let token: String = html_page[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/issues", &app.address))
+        .bearer_auth(&token)
+        .header("Idempotency-Key", &idempotency_key)
+        .json(&issue_request_body())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(count_issues(&app).await, 2);
+    let n_token_keys = sqlx::query!(
+        r#"SELECT COUNT(*) AS "n!" FROM idempotency WHERE key_owner LIKE 'api_token:%'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_token_keys, 1);
+}
+
+/// Mock the email server so that each subscription takes `delay` to be processed.
+async fn mock_slow_confirmation_email(app: &TestApp, delay: Duration) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(delay))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn concurrent_requests_with_the_same_key_wait_for_the_first_one() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_slow_confirmation_email(&app, Duration::from_millis(500)).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let body = serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "le guin" });
+
+    // Act
+    let (response1, response2) = tokio::join!(
+        post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key),
+        post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key)
+    );
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 202);
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    // Mock verifies on Drop that we have sent the confirmation email **once**
+}
+
+#[tokio::test]
+async fn keys_of_requests_outlasting_their_lease_are_not_taken_over() {
+    // Arrange - The first request renews its lease while it is processed
+    let app = spawn_app_with(|c| {
+        c.idempotency.public_backend = IdempotencyBackend::Redis;
+        c.idempotency.processing_timeout_secs = 1;
+    })
+    .await;
+    mock_slow_confirmation_email(&app, Duration::from_secs(2)).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let body = serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "le guin" });
+
+    // Act
+    let (response1, response2) = tokio::join!(
+        post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key),
+        post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key)
+    );
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 202);
+    // Mock verifies on Drop that we have sent the confirmation email **once**
+}
+
+#[tokio::test]
+async fn requests_waiting_too_long_for_the_same_key_are_asked_to_retry() {
+    // Arrange
+    let app = spawn_app_with(|c| c.idempotency.wait_timeout_millis = 100).await;
+    mock_slow_confirmation_email(&app, Duration::from_secs(1)).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let body = serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "le guin" });
+
+    // Act
+    let (response1, response2) = tokio::join!(
+        post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key),
+        post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key)
+    );
+
+    // Assert
+    let mut statuses = [response1.status().as_u16(), response2.status().as_u16()];
+    statuses.sort_unstable();
+    assert_eq!(statuses, [202, 409]);
+    let conflict = if response1.status().as_u16() == 409 {
+        response1
+    } else {
+        response2
+    };
+    assert_eq!(conflict.headers().get("Retry-After").unwrap(), "1");
+    let error: serde_json::Value = conflict.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "request_in_progress");
+    // A retry once the first request is done gets its response
+    let retry = post_json_with_key(&app, "api/v1/subscriptions", &body, &idempotency_key).await;
+    assert_eq!(retry.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn forms_submitted_while_their_key_is_in_progress_get_a_conflict() {
+    // Arrange
+    let app = spawn_app_with(|c| c.idempotency.wait_timeout_millis = 100).await;
+    app.do_login().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    // Claimed by a request that is still running
+    sqlx::query!(
+        "INSERT INTO idempotency (key_owner, idempotency_key, created_at) VALUES ($1, $2, now())",
+        format!("user:{}", app.test_user.user_id),
+        idempotency_key
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_publish_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers().get("Retry-After").unwrap(), "1");
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_request_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let mut other_body = issue_request_body();
+    other_body["title"] = "Another title".into();
+
+    // Act
+    let response1 = post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+    let response2 = post_json_with_key(&app, "api/v1/issues", &other_body, &idempotency_key).await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 201);
+    assert_eq!(response2.status().as_u16(), 422);
+    let body: serde_json::Value = response2.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "idempotency_key_reused");
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn reusing_a_form_key_for_a_different_newsletter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let newsletter = |title: &str| {
+        serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key,
+        })
+    };
+
+    // Act
+    let response1 = app
+        .post_publish_newsletters(&newsletter("Newsletter title"))
+        .await;
+    let response2 = app
+        .post_publish_newsletters(&newsletter("Another title"))
+        .await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 303);
+    assert_eq!(response2.status().as_u16(), 422);
+    assert!(response2
+        .text()
+        .await
+        .unwrap()
+        .contains("already used for a different request"));
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+fn subscription_body() -> serde_json::Value {
+    serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "le guin" })
+}
+
+#[tokio::test]
+async fn public_keys_are_kept_for_their_own_retention() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.idempotency.expiration_secs = 3600;
+        c.idempotency.public_expiration_secs = 1;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    login_as(&app, UserRole::Editor).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriptions", &app.address))
+        .header("Idempotency-Key", &idempotency_key)
+        .json(&subscription_body())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    app.remove_expired_idempotency_keys().await;
+
+    // Assert
+    let owners = sqlx::query!("SELECT key_owner FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(owners.len(), 1);
+    assert!(owners[0].key_owner.starts_with("user:"));
+}
+
+#[tokio::test]
+async fn expired_keys_are_processed_again_before_the_worker_removes_them() {
+    // Arrange
+    let app = spawn_app_with(|c| c.idempotency.expiration_secs = 1).await;
+    login_as(&app, UserRole::Editor).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+
+    // Act - The expiration worker doesn't run
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let response = post_json_with_key(
+        &app,
+        "api/v1/issues",
+        &issue_request_body(),
+        &idempotency_key,
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn public_keys_can_be_kept_in_redis() {
+    // Arrange
+    let app = spawn_app_with(|c| c.idempotency.public_backend = IdempotencyBackend::Redis).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let mut other_body = subscription_body();
+    other_body["name"] = "tolkien".into();
+
+    // Act
+    let response1 = post_json_with_key(
+        &app,
+        "api/v1/subscriptions",
+        &subscription_body(),
+        &idempotency_key,
+    )
+    .await;
+    let response2 = post_json_with_key(
+        &app,
+        "api/v1/subscriptions",
+        &subscription_body(),
+        &idempotency_key,
+    )
+    .await;
+    let response3 =
+        post_json_with_key(&app, "api/v1/subscriptions", &other_body, &idempotency_key).await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 202);
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    assert_eq!(response3.status().as_u16(), 422);
+    let n_keys = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_keys, 0);
+    // Mock verifies on Drop that we have sent the confirmation email **once**
+}
+
+#[tokio::test]
+async fn keys_kept_in_redis_expire_by_themselves() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.idempotency.public_backend = IdempotencyBackend::Redis;
+        c.idempotency.public_expiration_secs = 1;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    post_json_with_key(
+        &app,
+        "api/v1/subscriptions",
+        &subscription_body(),
+        &idempotency_key,
+    )
+    .await;
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let response = post_json_with_key(
+        &app,
+        "api/v1/subscriptions",
+        &subscription_body(),
+        &idempotency_key,
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    // Mock verifies on Drop that the key was processed again once expired
+}
@@ -0,0 +1,215 @@
+use crate::helpers::{api_client, assert_is_redirect_to, csrf_token, spawn_app, TestApp};
+use std::collections::HashMap;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+/// Invite `email` as the test user and return the link sent by email.
+pub async fn invite(app: &TestApp, email: &str, role: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.do_login().await;
+    let response = app.post_invite_user(email, role).await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request).html
+}
+
+/// Form body accepting the invitation `link` with the given credentials.
+pub fn accept_form(link: &reqwest::Url, username: &str, password: &str) -> HashMap<String, String> {
+    let mut form = link.query_pairs().into_owned().collect::<HashMap<_, _>>();
+    form.insert("username".into(), username.into());
+    form.insert("password".into(), password.into());
+    form.insert("password_check".into(), password.into());
+    form
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_users().await;
+    let invite_response = app.post_invite_user("ursula@domain.com", "editor").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&invite_response, "/login");
+}
+
+#[tokio::test]
+async fn invited_users_can_create_an_account_and_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Invite
+    let link = invite(&app, "ursula@domain.com", "editor").await;
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("An invitation was sent to ursula@domain.com."));
+    assert!(html_page.contains("Pending invitations"));
+    app.post_logout().await;
+
+    // Act - Part 2 - Visit the link
+    let response = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("ursula@domain.com"));
+
+    // Act - Part 3 - Create the account
+    let response = app
+        .post_accept_invitation(&accept_form(&link, &username, &password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 4 - Login
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &username,
+            "password": &password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn invitations_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let password = Uuid::new_v4().to_string();
+    let link = invite(&app, "ursula@domain.com", "editor").await;
+    app.post_logout().await;
+    app.post_accept_invitation(&accept_form(&link, "first", &password))
+        .await;
+
+    // Act
+    let response = app
+        .post_accept_invitation(&accept_form(&link, "second", &password))
+        .await;
+    let get_response = app.api_client.get(link).send().await.unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&get_response, "/login");
+    let html_page = app.get_login().await.text().await.unwrap();
+    assert!(html_page.contains("The invitation link is invalid or has expired."));
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "second",
+            "password": &password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn tampered_invitation_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = invite(&app, "ursula@domain.com", "editor").await;
+    app.post_logout().await;
+    let mut form = accept_form(&link, "ursula", &Uuid::new_v4().to_string());
+    let expires_at = form["expires_at"].parse::<i64>().unwrap();
+    form.insert("expires_at".into(), (expires_at + 3600).to_string());
+
+    // Act
+    let response = app.post_accept_invitation(&form).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login().await.text().await.unwrap();
+    assert!(html_page.contains("The invitation link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn expired_invitations_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = invite(&app, "ursula@domain.com", "editor").await;
+    app.post_logout().await;
+    sqlx::query!("UPDATE user_invitations SET expires_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_accept_invitation(&accept_form(&link, "ursula", &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn deactivated_users_are_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let password = Uuid::new_v4().to_string();
+    let link = invite(&app, "ursula@domain.com", "editor").await;
+    app.post_logout().await;
+    app.post_accept_invitation(&accept_form(&link, "ursula", &password))
+        .await;
+    // The invited user logs in with a separate client to keep their own session
+    let ursula = api_client();
+    let response = ursula
+        .post(format!("{}/login", app.address))
+        .header("X-CSRF-Token", csrf_token(&ursula, &app.address).await)
+        .form(&serde_json::json!({ "username": "ursula", "password": &password }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let ursula_id = sqlx::query!("SELECT user_id FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id;
+
+    // Act
+    app.do_login().await;
+    let response = app.post_deactivate_user(ursula_id).await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Assert
+    let response = ursula
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let response = ursula
+        .post(format!("{}/login", app.address))
+        .header("X-CSRF-Token", csrf_token(&ursula, &app.address).await)
+        .form(&serde_json::json!({ "username": "ursula", "password": &password }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_cannot_deactivate_themselves() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let response = app.post_deactivate_user(app.test_user.user_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("You can't deactivate your own account."));
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,4 +1,5 @@
 use crate::helpers::{assert_is_redirect_to, spawn_app};
+use std::time::Duration;
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -24,6 +25,24 @@ async fn an_error_flash_message_is_set_on_failure() {
     assert!(!html_page.contains(r#"Authentication failed"#));
 }
 
+#[tokio::test]
+async fn unknown_usernames_are_rejected_with_the_password_of_another_user() {
+    // Arrange - Unknown usernames are checked against the hash of an existing user
+    let app = spawn_app().await;
+
+    // Act
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": &app.test_user.password
+    });
+    let response = app.post_login(&login_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
 #[tokio::test]
 async fn redirect_to_admin_dashboard_after_login_success() {
     // Arrange
@@ -38,3 +57,48 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = response.text().await.unwrap();
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_on_login() {
+    // Arrange - The test user is stored with a cheaper hash than the configured one
+    let app = spawn_app().await;
+    let expected_params = format!(
+        "m={},t={},p={}",
+        app.password_hashing_settings.memory_size_kib,
+        app.password_hashing_settings.iterations,
+        app.password_hashing_settings.parallelism
+    );
+    let get_password_hash = || async {
+        sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            app.test_user.user_id
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash
+    };
+    assert!(!get_password_hash().await.contains(&expected_params));
+
+    // Act
+    let response = app.do_login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert - The hash is upgraded in the background
+    let mut password_hash = get_password_hash().await;
+    for _ in 0..50 {
+        if password_hash.contains(&expected_params) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        password_hash = get_password_hash().await;
+    }
+    assert!(
+        password_hash.contains(&expected_params),
+        "{}",
+        password_hash
+    );
+    app.post_logout().await;
+    let response = app.do_login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,177 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::authentication::{create_user, UserRole};
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..app.login_throttle_settings.max_failures_per_username {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Act - Login with the right password
+    let response = app.do_login().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login().await.text().await.unwrap();
+    assert!(html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn only_the_first_locked_out_attempt_is_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..app.login_throttle_settings.max_failures_per_username {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Act
+    for _ in 0..3 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Assert
+    let count = sqlx::query!(
+        r#"SELECT count(*) as "count!" FROM audit_log
+        WHERE action = 'login.failed' AND payload->>'reason' = 'locked_out'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn a_successful_login_clears_the_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    let max_failures = app.login_throttle_settings.max_failures_per_username;
+    for _ in 0..max_failures - 1 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    assert_is_redirect_to(&app.do_login().await, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act
+    for _ in 0..max_failures - 1 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = app.do_login().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_client_is_locked_out_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..app.login_throttle_settings.max_failures_per_ip {
+        fail_login(&app, &Uuid::new_v4().to_string()).await;
+    }
+
+    // Act
+    let response = app.do_login().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn lockouts_are_listed_for_owners_and_can_be_lifted() {
+    // Arrange
+    let app = spawn_app().await;
+    let locked_username = Uuid::new_v4().to_string();
+    for _ in 0..app.login_throttle_settings.max_failures_per_username {
+        fail_login(&app, &locked_username).await;
+    }
+    let count = sqlx::query!(
+        "SELECT count(*) as \"count!\" FROM login_lockouts WHERE username = $1",
+        locked_username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(count, 1);
+    app.do_login().await;
+
+    // Act - Part 1 - The lockout is visible
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("Recent login lockouts"));
+    assert!(html_page.contains(&locked_username));
+
+    // Act - Part 2 - Lift it
+    let response = app
+        .post_form(
+            "admin/users/unlock",
+            &serde_json::json!({ "username": &locked_username }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("The lockout has been lifted."));
+
+    // Assert
+    let is_active = sqlx::query!(
+        "SELECT locked_until > now() as \"is_active!\" FROM login_lockouts WHERE username = $1",
+        locked_username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .is_active;
+    assert!(!is_active);
+}
+
+#[tokio::test]
+async fn a_lifted_lockout_allows_logging_in_again() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..app.login_throttle_settings.max_failures_per_username {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    assert_is_redirect_to(&app.do_login().await, "/login");
+    let (username, password) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+    create_user(
+        &username,
+        None,
+        Secret::new(password.clone()),
+        UserRole::Owner,
+        &app.password_hashing_settings,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+    app.post_login(&serde_json::json!({
+        "username": &username,
+        "password": &password,
+    }))
+    .await;
+
+    // Act
+    app.post_form(
+        "admin/users/unlock",
+        &serde_json::json!({ "username": &app.test_user.username }),
+    )
+    .await;
+    app.post_logout().await;
+    let response = app.do_login().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
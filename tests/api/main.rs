@@ -1,9 +1,24 @@
 mod admin_dashboard;
+mod api_tokens;
+mod api_v1;
+mod audit_log;
+mod bot_protection;
 mod change_password;
+mod csrf;
 mod delivery_process;
 mod health_check;
 mod helpers;
+mod idempotency;
+mod invitations;
 mod login;
+mod login_throttle;
 mod newsletter;
+mod password_reset;
+mod rate_limit;
+mod roles;
+mod session_store;
+mod sessions;
 mod subscriptions;
 mod subscriptions_confirm;
+mod two_factor;
+mod users;
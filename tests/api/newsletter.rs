@@ -4,11 +4,13 @@ use fake::{
     Fake,
 };
 use reqwest::Response;
-use std::time::Duration;
+use sqlx::postgres::PgListener;
+use std::{num::NonZeroU32, time::Duration};
 use wiremock::{
     matchers::{any, method, path},
     Mock, ResponseTemplate,
 };
+use zero2prod::issue_delivery_worker::{ExecutionOutcome, ISSUE_DELIVERY_CHANNEL};
 
 /// Use the public API of the application under test to create
 /// an unconfirmed subscriber
@@ -16,7 +18,7 @@ use wiremock::{
 async fn create_unconfirmed_subscriber(app: &TestApp) -> (String, String, ConfirmationLinks) {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email
     }))
@@ -30,7 +32,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> (String, String, Confir
         .mount_as_scoped(&app.email_server)
         .await;
 
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -42,7 +44,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> (String, String, Confir
         .unwrap()
         .pop()
         .unwrap();
-    (name, email, app.get_confirmation_links(&email_request))
+    (name, email, app.get_confirmation_links(email_request))
 }
 
 /// Returns: name and email
@@ -227,6 +229,22 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     // Mock verifies on Drop that we have sent the newsletter email **once**
 }
 
+#[tokio::test]
+async fn publishing_a_newsletter_wakes_up_the_delivery_workers() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut listener = PgListener::connect_with(&app.db_pool).await.unwrap();
+    listener.listen(ISSUE_DELIVERY_CHANNEL).await.unwrap();
+    app.do_login().await;
+
+    // Act
+    publis_newsletter(&app).await;
+
+    // Assert
+    let notification = tokio::time::timeout(Duration::from_secs(1), listener.recv()).await;
+    assert!(notification.is_ok(), "The workers were not notified.");
+}
+
 #[tokio::test]
 async fn newsletters_deliver_retries_on_external_error() {
     // Arrange
@@ -281,6 +299,40 @@ async fn newsletters_deliver_skip_retries_after_max_retries_setting() {
     assert!(saved.is_none());
 }
 
+#[tokio::test]
+async fn newsletters_deliver_is_throttled_by_the_send_rate_limit() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.issue_delivery_settings.max_emails_per_second = NonZeroU32::new(1).unwrap();
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.do_login().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Submit newsletter
+    publis_newsletter(&app).await;
+    let outcome_1 = app.execute_delivery_task().await;
+    let outcome_2 = app.execute_delivery_task().await;
+
+    // Assert
+    assert!(matches!(outcome_1, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(outcome_2, ExecutionOutcome::Throttled(_)));
+    let row = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(row.n, 1);
+
+    // Act - The remaining email goes out once the bucket refills
+    app.dispatch_all_pending_emails().await;
+}
+
 #[tokio::test]
 async fn idempotency_keys_are_removed_after_they_expire() {
     // Arrange
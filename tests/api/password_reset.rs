@@ -0,0 +1,217 @@
+use crate::helpers::{api_client, assert_is_redirect_to, csrf_token, spawn_app, TestApp};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+/// Reset emails are sent in the background, wait for `n` of them to arrive.
+async fn wait_for_emails(app: &TestApp, n: usize) -> Vec<wiremock::Request> {
+    for _ in 0..50 {
+        let requests = app.email_server.received_requests().await.unwrap();
+        if requests.len() >= n {
+            return requests;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Expected {} emails to be sent.", n);
+}
+
+/// Request a password reset for the test user and return the token from the email.
+async fn request_reset_token(app: &TestApp) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let n_sent = app.email_server.received_requests().await.unwrap().len();
+    let response = app.post_password_reset(&app.test_user.username).await;
+    assert_is_redirect_to(&response, "/login");
+    let email_request = &wait_for_emails(app, n_sent + 1).await[n_sent];
+    let link = app.get_confirmation_links(email_request).html;
+    assert_eq!(link.path(), "/password_reset/confirm");
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+fn confirm_body(token: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "new_password": password,
+        "new_password_check": password,
+    })
+}
+
+#[tokio::test]
+async fn the_response_does_not_reveal_if_the_username_exists() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let known = app.post_password_reset(&app.test_user.username).await;
+    let known_html = app.get_login().await.text().await.unwrap();
+    let unknown = app.post_password_reset("nobody").await;
+    let unknown_html = app.get_login().await.text().await.unwrap();
+
+    // Assert
+    assert_is_redirect_to(&known, "/login");
+    assert_is_redirect_to(&unknown, "/login");
+    assert_eq!(known_html, unknown_html);
+    assert!(known_html.contains("a password reset link was sent"));
+    wait_for_emails(&app, 1).await;
+}
+
+#[tokio::test]
+async fn a_reset_link_sets_a_new_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+    let token = request_reset_token(&app).await;
+
+    // Act - Part 1 - Visit the link
+    let response = app
+        .get_route(&format!("password_reset/confirm?token={}", token))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 2 - Choose a new password
+    let response = app
+        .post_confirm_password_reset(&confirm_body(&token, &new_password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login().await.text().await.unwrap();
+    assert!(html_page.contains("Your password has been reset"));
+
+    // Assert
+    let response = app.do_login().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn reset_links_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+    app.post_confirm_password_reset(&confirm_body(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Act
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_confirm_password_reset(&confirm_body(&token, &new_password))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login().await.text().await.unwrap();
+    assert!(html_page.contains("The password reset link is invalid or has expired."));
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .get_route(&format!("password_reset/confirm?token={}", token))
+        .await;
+    let post_response = app
+        .post_confirm_password_reset(&confirm_body(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&post_response, "/login");
+    let response = app.do_login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn new_password_must_have_between_12_and_128_characters() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+
+    for password in ["short", &"a".repeat(129)] {
+        // Act
+        let response = app
+            .post_confirm_password_reset(&confirm_body(&token, password))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(
+            &response,
+            &format!("/password_reset/confirm?token={}", token),
+        );
+        let html_page = app
+            .get_route(&format!("password_reset/confirm?token={}", token))
+            .await
+            .text()
+            .await
+            .unwrap();
+        assert!(html_page.contains("New password should have between 12 and 128 characters."));
+    }
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_out_existing_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_device = api_client();
+    let response = other_device
+        .post(format!("{}/login", app.address))
+        .header(
+            "X-CSRF-Token",
+            csrf_token(&other_device, &app.address).await,
+        )
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let token = request_reset_token(&app).await;
+
+    // Act
+    app.post_confirm_password_reset(&confirm_body(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    let response = other_device
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,225 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::configuration::{RateLimitRule, SessionStoreBackend};
+
+fn rule(max_requests_per_ip: u32, max_requests_per_recipient: u32) -> RateLimitRule {
+    RateLimitRule {
+        max_requests_per_ip,
+        max_requests_per_recipient,
+        window_secs: 3600,
+    }
+}
+
+async fn mock_confirmation_emails(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+fn subscription_body(email: &str) -> serde_json::Value {
+    serde_json::json!({ "email": email, "name": "le guin" })
+}
+
+#[tokio::test]
+async fn subscription_forms_over_the_ip_limit_get_a_page_asking_to_wait() {
+    // Arrange
+    let app = spawn_app_with(|c| c.rate_limit.subscriptions = rule(2, 10)).await;
+    mock_confirmation_emails(&app).await;
+
+    // Act
+    let mut responses = Vec::new();
+    for email in ["ursula", "tolkien", "herbert"] {
+        let body = format!("name=le%20guin&email={}%40gmail.com", email);
+        responses.push(app.post_subscriptions(body).await);
+    }
+
+    // Assert
+    let limited = responses.pop().unwrap();
+    assert!(responses.iter().all(|r| r.status().as_u16() == 303));
+    assert_eq!(limited.status().as_u16(), 429);
+    assert!(limited.headers().get("Retry-After").is_some());
+    let html_page = limited.text().await.unwrap();
+    assert!(html_page.contains("Please try again in"));
+    let n_subscriptions = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscriptions, 2);
+}
+
+#[tokio::test]
+async fn subscriptions_for_the_same_email_are_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| c.rate_limit.subscriptions = rule(10, 1)).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response1 = app
+        .post_json(
+            "api/v1/subscriptions",
+            &subscription_body("ursula_le_guin@gmail.com"),
+        )
+        .await;
+    let response2 = app
+        .post_json(
+            "api/v1/subscriptions",
+            &subscription_body("Ursula_Le_Guin@gmail.com"),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 429);
+    let headers = response2.headers();
+    assert_eq!(headers.get("RateLimit-Limit").unwrap(), "1");
+    assert_eq!(headers.get("RateLimit-Remaining").unwrap(), "0");
+    assert!(headers.get("Retry-After").is_some());
+    let body: serde_json::Value = response2.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "too_many_requests");
+    // Mock verifies on Drop that we have sent the confirmation email **once**
+}
+
+#[tokio::test]
+async fn spelling_variants_of_an_email_share_their_limit() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.rate_limit.subscriptions = rule(10, 3);
+        c.email_policy.local_part.strip_subaddress = true;
+    })
+    .await;
+    mock_confirmation_emails(&app).await;
+
+    // Act
+    let mut statuses = Vec::new();
+    for email in [
+        "ursula_le_guin@gmail.com",
+        "Ursula_Le_Guin+news@GMAIL.com",
+        " ursula_le_guin@ｇｍａｉｌ.com",
+        "ursula_le_guin+books@gmail.com",
+    ] {
+        let response = app
+            .post_json("api/v1/subscriptions", &subscription_body(email))
+            .await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses, [202, 202, 202, 429]);
+}
+
+#[tokio::test]
+async fn api_clients_are_told_how_many_requests_they_have_left() {
+    // Arrange
+    let app = spawn_app_with(|c| c.rate_limit.subscriptions = rule(5, 3)).await;
+    mock_confirmation_emails(&app).await;
+
+    // Act
+    let response = app
+        .post_json(
+            "api/v1/subscriptions",
+            &subscription_body("ursula_le_guin@gmail.com"),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let headers = response.headers();
+    // The recipient limit is the closest to being reached
+    assert_eq!(headers.get("RateLimit-Limit").unwrap(), "3");
+    assert_eq!(headers.get("RateLimit-Remaining").unwrap(), "2");
+    let reset: u64 = headers
+        .get("RateLimit-Reset")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(reset <= 3600);
+}
+
+#[tokio::test]
+async fn replayed_subscriptions_dont_count_against_the_limits() {
+    // Arrange
+    let app = spawn_app_with(|c| c.rate_limit.subscriptions = rule(1, 1)).await;
+    mock_confirmation_emails(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let mut statuses = Vec::new();
+    for _ in 0..2 {
+        let response = app
+            .api_client
+            .post(format!("{}/api/v1/subscriptions", &app.address))
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&subscription_body("ursula_le_guin@gmail.com"))
+            .send()
+            .await
+            .unwrap();
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses, [202, 202]);
+}
+
+#[tokio::test]
+async fn password_reset_requests_are_rate_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| c.rate_limit.password_reset = rule(10, 1)).await;
+    mock_confirmation_emails(&app).await;
+
+    // Act
+    let response1 = app.post_password_reset(&app.test_user.username).await;
+    let response2 = app.post_password_reset(&app.test_user.username).await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 303);
+    assert_eq!(response2.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn rate_limits_can_be_counted_in_postgres() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.session_store.backend = SessionStoreBackend::Postgres;
+        c.rate_limit.subscriptions = rule(1, 10);
+    })
+    .await;
+    mock_confirmation_emails(&app).await;
+
+    // Act
+    let response1 = app
+        .post_json(
+            "api/v1/subscriptions",
+            &subscription_body("ursula_le_guin@gmail.com"),
+        )
+        .await;
+    let response2 = app
+        .post_json(
+            "api/v1/subscriptions",
+            &subscription_body("tolkien@gmail.com"),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 429);
+    let n_counters = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM rate_limit_counters"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    // One per IP, and one per email
+    assert_eq!(n_counters, 3);
+}
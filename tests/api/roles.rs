@@ -0,0 +1,168 @@
+use crate::{
+    helpers::{api_client, assert_is_redirect_to, csrf_token, spawn_app, TestApp},
+    invitations::{accept_form, invite},
+};
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::authentication::{create_user, UserRole};
+
+/// Create a user with `role` and log in as them instead of the test user.
+pub async fn login_as(app: &TestApp, role: UserRole) -> Uuid {
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+    let user_id = create_user(
+        &username,
+        None,
+        Secret::new(password.clone()),
+        role,
+        &app.password_hashing_settings,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &username,
+            "password": &password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    user_id
+}
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn viewers_can_only_check_the_delivery_process() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Viewer).await;
+
+    // Act
+    let dashboard = app.get_admin_dashboard().await;
+    let delivery_process = app.get_delivery_process().await;
+    let newsletters = app.get_publish_newsletters().await;
+    let publish = app
+        .post_publish_newsletters(&newsletter_request_body())
+        .await;
+    let users = app.get_users().await;
+
+    // Assert
+    assert_eq!(dashboard.status().as_u16(), 200);
+    let html_page = dashboard.text().await.unwrap();
+    assert!(!html_page.contains("/admin/newsletters"));
+    assert!(!html_page.contains("/admin/users"));
+    assert_eq!(delivery_process.status().as_u16(), 200);
+    assert_eq!(newsletters.status().as_u16(), 403);
+    assert!(newsletters
+        .text()
+        .await
+        .unwrap()
+        .contains("You don't have permission to access this page"));
+    assert_eq!(publish.status().as_u16(), 403);
+    assert_eq!(users.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn editors_can_publish_but_not_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    login_as(&app, UserRole::Editor).await;
+
+    // Act
+    let newsletters = app.get_publish_newsletters().await;
+    let users = app.get_users().await;
+    let invite = app.post_invite_user("ursula@domain.com", "owner").await;
+    let update_role = app.post_update_role(app.test_user.user_id, "viewer").await;
+
+    // Assert
+    assert_eq!(newsletters.status().as_u16(), 200);
+    assert_eq!(users.status().as_u16(), 403);
+    assert_eq!(invite.status().as_u16(), 403);
+    assert_eq!(update_role.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn role_changes_apply_to_existing_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer_id = login_as(&app, UserRole::Viewer).await;
+    assert_eq!(app.get_publish_newsletters().await.status().as_u16(), 403);
+    let owner = api_client();
+    let response = owner
+        .post(format!("{}/login", app.address))
+        .header("X-CSRF-Token", csrf_token(&owner, &app.address).await)
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act
+    let response = owner
+        .post(format!("{}/admin/users/{}/role", app.address, viewer_id))
+        .header("X-CSRF-Token", csrf_token(&owner, &app.address).await)
+        .form(&serde_json::json!({ "role": "editor" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert_eq!(app.get_publish_newsletters().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn owners_cannot_change_their_own_role() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let response = app.post_update_role(app.test_user.user_id, "viewer").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("You can't change your own role."));
+    assert_eq!(app.get_users().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn invited_users_get_the_role_of_their_invitation() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+    let link = invite(&app, "ursula@domain.com", "viewer").await;
+    app.post_logout().await;
+
+    // Act
+    app.post_accept_invitation(&accept_form(&link, &username, &password))
+        .await;
+    app.post_login(&serde_json::json!({
+        "username": &username,
+        "password": &password,
+    }))
+    .await;
+
+    // Assert
+    assert!(app
+        .get_admin_dashboard()
+        .await
+        .text()
+        .await
+        .unwrap()
+        .contains("Role: viewer"));
+    assert_eq!(app.get_publish_newsletters().await.status().as_u16(), 403);
+}
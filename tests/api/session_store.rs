@@ -0,0 +1,112 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+use uuid::Uuid;
+use zero2prod::configuration::SessionStoreBackend;
+
+async fn spawn_app_with_backend(backend: SessionStoreBackend) -> TestApp {
+    spawn_app_with(|c| c.session_store.backend = backend).await
+}
+
+async fn count_sessions(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn sessions_can_be_kept_in_postgres() {
+    // Arrange
+    let app = spawn_app_with_backend(SessionStoreBackend::Postgres).await;
+
+    // Act - Part 1 - Login
+    let response = app.do_login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 2 - The session is found again on the next request
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(count_sessions(&app).await > 0);
+
+    // Act - Part 3 - Logout
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logins_are_throttled_with_the_postgres_backend() {
+    // Arrange
+    let app = spawn_app_with_backend(SessionStoreBackend::Postgres).await;
+    for _ in 0..app.login_throttle_settings.max_failures_per_username {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": &app.test_user.username,
+                "password": Uuid::new_v4().to_string(),
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Act - Login with the right password
+    let response = app.do_login().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login().await.text().await.unwrap();
+    assert!(html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn expired_sessions_and_login_failures_are_removed() {
+    // Arrange
+    let app = spawn_app_with_backend(SessionStoreBackend::Postgres).await;
+    app.do_login().await;
+    let live_sessions = count_sessions(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (session_key_hash, state, expires_at)
+        VALUES ('expired', '{}', now() - interval '1 minute')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO login_failures (counter_key, failures, expires_at)
+        VALUES ('expired', 3, now() - interval '1 minute')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.remove_expired_sessions().await;
+
+    // Assert
+    assert_eq!(count_sessions(&app).await, live_sessions);
+    let failures = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM login_failures"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failures, 0);
+}
+
+#[tokio::test]
+async fn sessions_can_still_be_kept_in_redis() {
+    // Arrange
+    let app = spawn_app_with_backend(SessionStoreBackend::Redis).await;
+
+    // Act
+    let response = app.do_login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_sessions(&app).await, 0);
+}
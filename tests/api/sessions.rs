@@ -0,0 +1,217 @@
+use crate::helpers::{api_client, assert_is_redirect_to, csrf_token, spawn_app, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::authentication::{create_user, UserRole};
+
+/// Log the test user in from another client.
+async fn login_from_other_device(app: &TestApp, user_agent: &str) -> reqwest::Client {
+    let other_device = api_client();
+    let response = other_device
+        .post(format!("{}/login", app.address))
+        .header(
+            "X-CSRF-Token",
+            csrf_token(&other_device, &app.address).await,
+        )
+        .header("User-Agent", user_agent)
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    other_device
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn login_as_other_user(app: &TestApp) -> reqwest::Client {
+    let (username, password) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+    create_user(
+        &username,
+        None,
+        Secret::new(password.clone()),
+        UserRole::Viewer,
+        &app.password_hashing_settings,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+    let client = api_client();
+    let response = client
+        .post(format!("{}/login", app.address))
+        .header("X-CSRF-Token", csrf_token(&client, &app.address).await)
+        .form(&serde_json::json!({ "username": &username, "password": &password }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_session_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_id = $1 ORDER BY created_at",
+        app.test_user.user_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.session_id)
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_route("admin/sessions").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sessions_are_listed_with_their_device() {
+    // Arrange
+    let app = spawn_app().await;
+    login_from_other_device(&app, "Test Browser 1.0").await;
+    app.do_login().await;
+
+    // Act
+    let html_page = app.get_route("admin/sessions").await.text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("Test Browser 1.0"));
+    assert!(html_page.contains("Current session"));
+    assert_eq!(html_page.matches("/revoke\"").count(), 1);
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_device = login_from_other_device(&app, "Test Browser 1.0").await;
+    app.do_login().await;
+    let other_session_id = get_session_ids(&app).await[0];
+
+    // Act
+    let response = app
+        .post_form(
+            &format!("admin/sessions/{}/revoke", other_session_id),
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    // Assert
+    assert_is_redirect_to(&get_dashboard(&app, &other_device).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+    let html_page = app.get_route("admin/sessions").await.text().await.unwrap();
+    assert!(!html_page.contains("Test Browser 1.0"));
+}
+
+#[tokio::test]
+async fn you_cannot_revoke_the_sessions_of_other_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_user = login_as_other_user(&app).await;
+    app.do_login().await;
+    let other_session_id = sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_id <> $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .session_id;
+
+    // Act
+    let response = app
+        .post_form(
+            &format!("admin/sessions/{}/revoke", other_session_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_route("admin/sessions").await.text().await.unwrap();
+    assert!(html_page.contains("The session doesn't exist."));
+    assert_eq!(
+        get_dashboard(&app, &other_user).await.status().as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn logging_out_everywhere_else_keeps_the_current_session() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_device = login_from_other_device(&app, "Test Browser 1.0").await;
+    let another_device = login_from_other_device(&app, "Test Browser 2.0").await;
+    app.do_login().await;
+
+    // Act
+    let response = app
+        .post_form("admin/sessions/revoke_others", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    // Assert
+    assert_is_redirect_to(&get_dashboard(&app, &other_device).await, "/login");
+    assert_is_redirect_to(&get_dashboard(&app, &another_device).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_the_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_device = login_from_other_device(&app, "Test Browser 1.0").await;
+    app.do_login().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&get_dashboard(&app, &other_device).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_revokes_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    let revoked = sqlx::query!(
+        r#"SELECT revoked_at IS NOT NULL as "revoked!" FROM user_sessions WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .revoked;
+    assert!(revoked);
+}
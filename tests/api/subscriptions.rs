@@ -1,4 +1,4 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
 use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
@@ -35,6 +35,11 @@ async fn subscribe_persist_the_new_subscribe() {
     // Arrange
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
 
     // Act
     app.post_subscriptions(body.into()).await;
@@ -227,3 +232,118 @@ async fn subscribe_fails_when_email_already_exists() {
         html_page
     );
 }
+
+#[tokio::test]
+async fn subscribe_rejects_disposable_and_role_addresses() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_policy.block_role_addresses = true).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let test_cases = vec![
+        (
+            "name=Ursula&email=ursula%40mailinator.com",
+            "ursula@mailinator.com is a disposable address, please use a permanent one.",
+        ),
+        (
+            "name=Ursula&email=postmaster%40gmail.com",
+            "postmaster@gmail.com is a shared mailbox, please use a personal address.",
+        ),
+    ];
+    for (body, expected) in test_cases {
+        // Act
+        let response = app.post_subscriptions(body.into()).await;
+        assert_is_redirect_to(&response, "/subscriptions");
+
+        // Assert
+        let html_page = app.get_subscriptions_html().await;
+        assert!(html_page.contains(expected), "Current page: {}", html_page);
+    }
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn subscribe_stores_unicode_domains_in_ascii_lowercase() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = "name=le%20guin&email=ursula%40B%C3%BCcher.Example";
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@xn--bcher-kva.example");
+}
+
+#[tokio::test]
+async fn addresses_differing_by_case_are_the_same_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Act
+    let body = "name=ursula&email=Ursula_Le_Guin%40Gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions");
+    let html_page = app.get_subscriptions_html().await;
+    assert!(
+        html_page.contains("Ursula_Le_Guin@gmail.com already exists, use another email."),
+        "Current page: {}",
+        html_page
+    );
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+}
+
+#[tokio::test]
+async fn local_parts_are_normalized_when_configured() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.email_policy.local_part.lowercase = true;
+        c.email_policy.local_part.strip_subaddress = true;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = "name=le%20guin&email=Ursula%2BNews%40Gmail.com";
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@gmail.com");
+}
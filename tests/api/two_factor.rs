@@ -0,0 +1,240 @@
+use crate::helpers::{api_client, assert_is_redirect_to, csrf_token, spawn_app, TestApp};
+use hmac::{Hmac, Mac};
+use secrecy::Secret;
+use sha1::Sha1;
+use uuid::Uuid;
+use zero2prod::authentication::{create_user, UserRole};
+
+/// TOTP code `offset` steps away from the current one.
+fn totp_code(secret: &[u8], offset: i64) -> String {
+    let step = chrono::Utc::now().timestamp() / 30 + offset;
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[19] & 0xf) as usize;
+    let code = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    format!("{:06}", code % 1_000_000)
+}
+
+fn text_between<'a>(html: &'a str, start: &str, end: &str) -> Vec<&'a str> {
+    html.split(start)
+        .skip(1)
+        .map(|s| s.split(end).next().unwrap())
+        .collect()
+}
+
+/// Enable 2FA for the logged in user, returning the secret and the recovery codes.
+async fn enable_two_factor(app: &TestApp) -> (Vec<u8>, Vec<String>) {
+    let html_page = app
+        .get_route("admin/two_factor")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<svg"));
+    let secret = text_between(&html_page, "<code>", "</code>")[0];
+    let secret = data_encoding::BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .unwrap();
+    let response = app
+        .post_form(
+            "admin/two_factor/enable",
+            &[("code", totp_code(&secret, 0))],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = text_between(&html_page, "<li>", "</li>")
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    assert_eq!(recovery_codes.len(), 10);
+    (secret, recovery_codes)
+}
+
+async fn verify_code(app: &TestApp, code: &str) -> reqwest::Response {
+    app.post_form("login/two_factor", &[("code", code)]).await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_enroll() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_route("admin/two_factor").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn enrollment_requires_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.get_route("admin/two_factor").await;
+
+    // Act
+    let response = app
+        .post_form("admin/two_factor/enable", &[("code", "000000")])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html_page = app
+        .get_route("admin/two_factor")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The authentication code is incorrect."));
+    app.post_logout().await;
+    assert_is_redirect_to(&app.do_login().await, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn login_requires_the_second_factor_once_enabled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - Password
+    let response = app.do_login().await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Code
+    let response = verify_code(&app, &totp_code(&secret, 1)).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_second_step_requires_the_password_first() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let form = app.get_route("login/two_factor").await;
+    let response = verify_code(&app, "123456").await;
+
+    // Assert
+    assert_is_redirect_to(&form, "/login");
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn totp_codes_cannot_be_replayed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.post_logout().await;
+    let code = totp_code(&secret, 1);
+    app.do_login().await;
+    assert_is_redirect_to(&verify_code(&app, &code).await, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act
+    app.do_login().await;
+    let response = verify_code(&app, &code).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
+
+#[tokio::test]
+async fn recovery_codes_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - First use
+    app.do_login().await;
+    let response = verify_code(&app, &recovery_codes[0].to_uppercase()).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act - Part 2 - Second use
+    app.do_login().await;
+    let response = verify_code(&app, &recovery_codes[0]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let response = verify_code(&app, &recovery_codes[1]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_restart_the_login() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.post_logout().await;
+    app.do_login().await;
+
+    // Act
+    for _ in 0..4 {
+        let response = verify_code(&app, "000000").await;
+        assert_is_redirect_to(&response, "/login/two_factor");
+    }
+    let response = verify_code(&app, "000000").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let response = verify_code(&app, &totp_code(&secret, 1)).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_can_reset_the_second_factor_of_other_users() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+    let owner_name = Uuid::new_v4().to_string();
+    let owner_password = Uuid::new_v4().to_string();
+    create_user(
+        &owner_name,
+        None,
+        Secret::new(owner_password.clone()),
+        UserRole::Owner,
+        &app.password_hashing_settings,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+    let owner = api_client();
+    owner
+        .post(format!("{}/login", app.address))
+        .header("X-CSRF-Token", csrf_token(&owner, &app.address).await)
+        .form(&[("username", &owner_name), ("password", &owner_password)])
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = owner
+        .post(format!(
+            "{}/admin/users/{}/reset_two_factor",
+            app.address, app.test_user.user_id
+        ))
+        .header("X-CSRF-Token", csrf_token(&owner, &app.address).await)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert_is_redirect_to(&app.do_login().await, "/admin/dashboard");
+}
@@ -0,0 +1,83 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::authentication::{create_user, delete_user, list_users, UserRole};
+
+#[tokio::test]
+async fn created_users_can_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act
+    create_user(
+        &username,
+        None,
+        Secret::new(password.clone()),
+        UserRole::Owner,
+        &app.password_hashing_settings,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &username,
+            "password": &password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    let password = Secret::new(Uuid::new_v4().to_string());
+
+    // Act
+    let result = create_user(
+        &app.test_user.username,
+        None,
+        password,
+        UserRole::Editor,
+        &app.password_hashing_settings,
+        &app.db_pool,
+    )
+    .await;
+
+    // Assert
+    let e = result.unwrap_err();
+    assert!(e.to_string().contains("is already taken"), "{}", e);
+}
+
+#[tokio::test]
+async fn deleted_users_cannot_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let user_id = list_users(&app.db_pool).await.unwrap()[0].user_id;
+
+    // Act
+    let deleted = delete_user(user_id, &app.db_pool).await.unwrap();
+    let response = app.do_login().await;
+
+    // Assert
+    assert!(deleted);
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_seeded_admin_user_is_removed_by_the_migrations() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let users = list_users(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, app.test_user.username);
+}